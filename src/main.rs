@@ -3,7 +3,52 @@
 mod raytracer;
 mod raydebugger;
 mod sceneparser;
+mod renderer;
 
 fn main() {
-    raydebugger::gui::run_application();
-}
\ No newline at end of file
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        // fmt [--check] <file.scene>...
+        Some("fmt") => {
+            let check = args.iter().any(|arg| arg == "--check");
+            let paths: Vec<String> = args[1..]
+                .iter()
+                .filter(|arg| *arg != "--check")
+                .cloned()
+                .collect();
+
+            if !sceneparser::formatter::format_files(&paths, check) {
+                std::process::exit(1);
+            }
+        }
+        // check <file.scene>...
+        Some("check") => {
+            if !sceneparser::checker::check_files(&args[1..]) {
+                std::process::exit(1);
+            }
+        }
+        // render <file.scene> <directory> [<width> <height>]
+        Some("render") => {
+            let (width, height) = match &args[1..] {
+                [_, _] => (480, 360),
+                [_, _, width, height] => match (width.parse(), height.parse()) {
+                    (Ok(width), Ok(height)) => (width, height),
+                    _ => {
+                        eprintln!("Expected a width and height in pixels, got {} {}", width, height);
+                        std::process::exit(1);
+                    }
+                },
+                _ => {
+                    eprintln!("Usage: render <file.scene> <directory> [<width> <height>]");
+                    std::process::exit(1);
+                }
+            };
+
+            if !renderer::render_animation(&args[1], &args[2], width, height) {
+                std::process::exit(1);
+            }
+        }
+        _ => raydebugger::gui::run_application(),
+    }
+}
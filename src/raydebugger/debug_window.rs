@@ -2,10 +2,11 @@ use crate::sceneparser::scene_loader::load_scene;
 use crate::raytracer::raytracer::RayTracer;
 use crate::raytracer::color::{Color, ColorPixmap, RaytracerPixmap};
 use crate::raytracer::vector::{Vector, Ray};
+use crate::raytracer::math_shapes::HitRecord;
 use crate::raytracer::math::INFINITY;
 use crate::raytracer::antialiaser::AntiAliaser;
 use super::easy_pixbuf::EasyPixbuf;
-use super::gui::{DrawingArea, MAX_FRAMES};
+use super::gui::DrawingArea;
 use super::ray_debugger::OrthoAxes;
 
 use glib::Sender;
@@ -38,9 +39,9 @@ pub struct DebugWindow {
 }
 
 impl DebugWindow {
-    pub fn new(width: usize, height: usize, frame: usize) -> Self {
+    pub fn new(width: usize, height: usize, time: f64) -> Self {
         DebugWindow {
-            ray_tracer: Arc::new(Self::load_ray_tracer(width, height, frame)),
+            ray_tracer: Arc::new(Self::load_ray_tracer(width, height, time)),
             width,
             height,
             show_anti_aliasing_edges: false,
@@ -50,21 +51,19 @@ impl DebugWindow {
         }
     }
 
-    fn load_ray_tracer(width: usize, height: usize, frame: usize) -> RayTracer {
+    fn load_ray_tracer(width: usize, height: usize, time: f64) -> RayTracer {
         let mut ray_tracer = RayTracer::new_default(width, height);
         ray_tracer.add_test_objects();
-        // FIXME: Max frames
-        let time = frame as f64 / MAX_FRAMES as f64;
         if let Err(err) = load_scene(&mut ray_tracer, time) {
-            eprintln!("Error parsing scene: {}", err);
+            eprintln!("Error loading scene: {}", err);
         }
         ray_tracer
     }
 
-    pub fn reload_ray_tracer(&mut self, frame: usize, width: usize, height: usize) {
+    pub fn reload_ray_tracer(&mut self, time: f64, width: usize, height: usize) {
         self.width = width;
         self.height = height;
-        self.ray_tracer = Arc::new(Self::load_ray_tracer(self.width, self.height, frame));
+        self.ray_tracer = Arc::new(Self::load_ray_tracer(self.width, self.height, time));
     }
 
     pub fn ray_tracer(&self) -> &RayTracer {
@@ -205,10 +204,10 @@ impl DebugWindow {
 
             for object in self.ray_tracer.get_objects() {
                 // FIXME: Skip planes
-                let mut add_intersection = |d: f64| {
-                    if d < distance {
+                let mut add_intersection = |hit: HitRecord| {
+                    if hit.distance < distance {
                         foremost_object = Some(object);
-                        distance = d;
+                        distance = hit.distance;
                     }
                 };
 
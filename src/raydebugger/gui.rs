@@ -1,5 +1,7 @@
 use super::debug_window::{DebugWindow, RenderedLineSender, ANTIALIAS_THRESHOLD};
 use super::ray_debugger::RayDebugger;
+use crate::raytracer::animation::Animation;
+use crate::sceneparser::scene_loader::{read_scene, scene_animation};
 
 use cairo;
 use gtk::prelude::*;
@@ -17,8 +19,9 @@ use rand;
 const WIDTH: i32 = 480;
 const HEIGHT: i32 = 360;
 
-pub const MAX_FRAMES: usize = 300;
-pub const MAX_SECONDS: u32 = 10;
+// Each frame keeps its own scene and surfaces in memory, so longer
+// animations are previewed with frames skipped evenly
+const MAX_FRAMES: usize = 300;
 
 #[derive(Clone, Copy)]
 pub enum DrawingArea {
@@ -35,16 +38,14 @@ struct DebuggerContext {
     current_frame: usize,
     animating: bool,
     thread_pool: ThreadPool,
+    animation: Animation,
     frames: Vec<FrameContext>,
 }
 
 impl DebuggerContext {
     pub fn new() -> Self {
-        let mut frames = Vec::new();
-
-        for frame in 0..MAX_FRAMES {
-            frames.push(FrameContext::new(frame, WIDTH as usize, HEIGHT as usize));
-        }
+        let animation = load_animation();
+        let frames = create_frames(&animation, WIDTH as usize, HEIGHT as usize);
 
         let thread_pool = threadpool::Builder::new()
             .thread_name("ray-renderer".to_string())
@@ -57,20 +58,41 @@ impl DebuggerContext {
             current_frame: 0,
             animating: false,
             thread_pool,
+            animation,
             frames,
         }
     }
 
+    /// Picks up changes to the scene's animation settings. Returns whether
+    /// there are new frames.
+    pub fn reload_animation(&mut self, width: usize, height: usize) -> bool {
+        let animation = load_animation();
+        if animation == self.animation {
+            return false;
+        }
+
+        self.frames = create_frames(&animation, width, height);
+        self.current_frame = self.current_frame.min(self.frames.len() - 1);
+        self.animation = animation;
+        true
+    }
+
+    /// How long each frame is shown, longer when frames were skipped so that
+    /// the animation still takes as long as it should
+    pub fn frame_interval(&self) -> u32 {
+        let skipped = self.animation.frames as f64 / self.frames.len() as f64;
+
+        (self.animation.frame_interval() as f64 * skipped).round() as u32
+    }
+
     pub fn frame(&mut self) -> &mut FrameContext {
         &mut self.frames[self.current_frame]
     }
 
     pub fn resize_frames(&mut self, width: usize, height: usize) {
-        for frame in 0..MAX_FRAMES {
-            let frame = &mut self.frames[frame];
-
+        for frame in self.frames.iter_mut() {
             if (width, height) != (frame.width, frame.height) {
-                *frame = FrameContext::new(frame.frame_number, width, height);
+                *frame = FrameContext::new(frame.frame_number, frame.time, width, height);
             }
         }
     }
@@ -78,7 +100,7 @@ impl DebuggerContext {
     fn with_shuffled_frames<F: Fn(&mut FrameContext, &ThreadPool)>(&mut self, render: F) {
         if self.animating {
             let shuffled_frames = rand::seq::index::sample(
-                &mut rand::thread_rng(), MAX_FRAMES, MAX_FRAMES
+                &mut rand::thread_rng(), self.frames.len(), self.frames.len()
             );
             for frame in shuffled_frames.iter() {
                 render(&mut self.frames[frame], &self.thread_pool)
@@ -89,6 +111,22 @@ impl DebuggerContext {
     }
 }
 
+/// The animation set by the scene, or the default one if it can't be loaded
+fn load_animation() -> Animation {
+    scene_animation(&read_scene()).unwrap_or_default()
+}
+
+fn create_frames(animation: &Animation, width: usize, height: usize) -> Vec<FrameContext> {
+    let count = animation.frames.min(MAX_FRAMES);
+
+    (0..count)
+        .map(|frame| {
+            let animation_frame = (frame as f64 * animation.frames as f64 / count as f64) as usize;
+            FrameContext::new(frame, animation.time(animation_frame), width, height)
+        })
+        .collect()
+}
+
 fn frame(context: &Rc<RefCell<DebuggerContext>>) -> RefMut<FrameContext> {
     RefMut::map(
         context.borrow_mut(),
@@ -100,6 +138,7 @@ struct FrameContext {
     width: usize,
     height: usize,
     frame_number: usize,
+    time: f64,
     debug_window: DebugWindow,
     ray_debugger: RayDebugger,
     main_surface: cairo::ImageSurface,
@@ -110,11 +149,11 @@ struct FrameContext {
 }
 
 impl FrameContext {
-    fn new(frame: usize, width: usize, height: usize) -> Self {
+    fn new(frame: usize, time: f64, width: usize, height: usize) -> Self {
         let debug_window = DebugWindow::new(
             width,
             height,
-            frame
+            time
         );
 
         let (width_i32, height_i32) =
@@ -154,6 +193,7 @@ impl FrameContext {
             width,
             height,
             frame_number: frame,
+            time,
             debug_window,
             ray_debugger,
             main_surface,
@@ -186,7 +226,7 @@ impl FrameContext {
         &mut self, thread_pool: &ThreadPool, raytrace_ortho_views: bool, current_frame: usize,
         line_range: Vec<usize>, rendered_line_sender: RenderedLineSender,
     ) {
-        self.debug_window.reload_ray_tracer(current_frame, self.width, self.height);
+        self.debug_window.reload_ray_tracer(self.time, self.width, self.height);
         self.ray_debugger.reset_debugger();
 
         if raytrace_ortho_views {
@@ -301,8 +341,9 @@ fn build_gui(application: &gtk::Application) {
     let animate_button =
         gtk::CheckButton::new_with_label("Animate");
 
+    let frame_count = debugger_context.borrow().frames.len();
     let frame_spin_button =
-    gtk::SpinButton::new_with_range(0.0, MAX_FRAMES as f64 - 1.0, 1.0);
+    gtk::SpinButton::new_with_range(0.0, frame_count as f64 - 1.0, 1.0);
 
     let threshold_scale =
         gtk::Scale::new_with_range(gtk::Orientation::Horizontal, 0.0, 0.1, 0.001);
@@ -532,13 +573,15 @@ fn build_gui(application: &gtk::Application) {
 
         move |button| {
             if button.get_active() {
-                gtk::timeout_add(1000 / (MAX_FRAMES as u32 / MAX_SECONDS), {
+                let frame_interval = debugger_context.borrow().frame_interval();
+                gtk::timeout_add(frame_interval, {
                     let debugger_context = debugger_context.clone();
                     let frame_spin_button = frame_spin_button.clone();
 
                     move || {
                         let current_frame = debugger_context.borrow().current_frame;
-                        frame_spin_button.set_value(((current_frame + 1) % MAX_FRAMES) as f64);
+                        let frame_count = debugger_context.borrow().frames.len();
+                        frame_spin_button.set_value(((current_frame + 1) % frame_count) as f64);
 
                         Continue(debugger_context.borrow().animating)
                     }
@@ -655,19 +698,26 @@ fn build_gui(application: &gtk::Application) {
         let debugger_context = debugger_context.clone();
         let rendered_line_sender = rendered_line_sender.clone();
         let drawing_area = drawing_area.clone();
+        let frame_spin_button = frame_spin_button.clone();
         move |_button| {
-            let mut debugger_context = debugger_context.borrow_mut();
-            let debugger_context: &mut DebuggerContext = &mut *debugger_context;
-
             let width = drawing_area.get_allocated_width() as usize;
             let height = drawing_area.get_allocated_height() as usize;
 
+            // The scene may have changed its number of frames
+            if debugger_context.borrow_mut().reload_animation(width, height) {
+                let frame_count = debugger_context.borrow().frames.len();
+                frame_spin_button.set_range(0.0, frame_count as f64 - 1.0);
+            }
+
+            let mut debugger_context = debugger_context.borrow_mut();
+            let debugger_context: &mut DebuggerContext = &mut *debugger_context;
+
             let raytrace_ortho_views = debugger_context.raytrace_ortho_views;
 
             debugger_context.with_shuffled_frames(|frame, _thread_pool| {
                 // Change the frame's resolution if the window size changed
                 if (width, height) != (frame.width, frame.height) {
-                    *frame = FrameContext::new(frame.frame_number, width, height);
+                    *frame = FrameContext::new(frame.frame_number, frame.time, width, height);
                 }
             });
 
@@ -1,7 +1,7 @@
 use crate::raytracer::vector::{Vector, Ray};
 use crate::raytracer::color::Color;
 use crate::raytracer::raytracer::{RayTracer, RayType};
-use crate::raytracer::rt_object::RTObject;
+use crate::raytracer::math_shapes::HitRecord;
 use crate::raytracer::math::INFINITY;
 use super::debug_shape::DebugShape;
 use super::gui::DrawingArea;
@@ -100,7 +100,7 @@ impl RayDebugger {
         self.rays.clear();
 
         let mut ray_debugger_callback = |
-            depth: i32, ray: Ray, intersection_distance: f64, intersected_object: Option<&RTObject>,
+            depth: i32, ray: Ray, intersection_distance: f64, hit: Option<&HitRecord>,
             color: &Color, ray_type: &RayType
         | {
             let intersected = intersection_distance != INFINITY;
@@ -111,13 +111,7 @@ impl RayDebugger {
                 1000.0
             };
 
-            let normal = intersected_object.map(|obj| {
-                let normal = obj
-                    .get_shape()
-                    .get_normal(intersection_point);
-                //obj.get_shape().get_transformation().reverse_transform_vector(normal)
-                normal
-            });
+            let normal = hit.map(|hit| hit.normal.normalized());
 
             let ray_info = RayInfo {
                 depth,
@@ -0,0 +1,36 @@
+// How many frames an animated scene has, how fast they are played, and the
+// scene's `time` in each of them.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animation {
+    pub frames: usize,
+    pub fps: f64,
+    /// The time in the first frame
+    pub start: f64,
+    /// The time the frame after the last one would have, so that a looping
+    /// animation doesn't show the same moment twice
+    pub end: f64,
+}
+
+impl Default for Animation {
+    /// Ten seconds at 30 frames per second, with the time going from 0 to 1
+    fn default() -> Self {
+        Animation {
+            frames: 300,
+            fps: 30.0,
+            start: 0.0,
+            end: 1.0,
+        }
+    }
+}
+
+impl Animation {
+    pub fn time(&self, frame: usize) -> f64 {
+        self.start + (self.end - self.start) * frame as f64 / self.frames as f64
+    }
+
+    /// How long each frame is shown, in milliseconds
+    pub fn frame_interval(&self) -> u32 {
+        (1000.0 / self.fps).round() as u32
+    }
+}
@@ -0,0 +1,118 @@
+use super::color::Color;
+use super::math::{PI, abs};
+use super::texture::Texture;
+use super::vector::{Vector, UV};
+
+// What rays that don't hit anything see, looked up by their direction. The
+// camera looks towards +Z with +Y up, so that is the front of the sky.
+
+#[derive(Clone)]
+pub enum Background {
+    Color(Color),
+    /// From `bottom` looking straight down to `top` looking straight up
+    Gradient { bottom: Color, top: Color },
+    /// A latitude/longitude image; the front is at the middle, and U goes
+    /// right all around the Y axis.
+    Equirectangular { texture: Box<dyn Texture>, intensity: f64 },
+    /// One texture for each face, in the order +X, -X, +Y, -Y, +Z, -Z, as
+    /// seen from inside the cube.
+    CubeMap { faces: Vec<Box<dyn Texture>>, intensity: f64 },
+    /// A single texture with the faces in a 4x3 cross, as seen from inside:
+    ///
+    /// ```text
+    ///     +Y
+    /// -X  +Z  +X  -Z
+    ///     -Y
+    /// ```
+    CubeCross { texture: Box<dyn Texture>, intensity: f64 },
+}
+
+impl Background {
+    pub fn get_color(&self, direction: Vector) -> Color {
+        let radiance = self.get_radiance(direction);
+
+        Color::in_range(radiance.r, radiance.g, radiance.b)
+    }
+
+    /// Like get_color(), but not limited to 0..1, for lighting with high
+    /// dynamic range images.
+    pub fn get_radiance(&self, direction: Vector) -> Color {
+        let direction = direction.normalized();
+
+        match self {
+            Background::Color(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let t = (direction.y + 1.0) / 2.0;
+
+                Color::new(
+                    bottom.r + (top.r - bottom.r) * t,
+                    bottom.g + (top.g - bottom.g) * t,
+                    bottom.b + (top.b - bottom.b) * t,
+                    1.0,
+                )
+            }
+            Background::Equirectangular { texture, intensity } => {
+                let uv = UV {
+                    u: 0.5 + direction.x.atan2(direction.z) / (2.0 * PI),
+                    v: 0.5 + Color::in_limit(direction.y, -1.0, 1.0).asin() / PI,
+                };
+
+                scale(texture.get_color_at(uv), *intensity)
+            }
+            Background::CubeMap { faces, intensity } => {
+                let (face, uv) = Self::get_cube_face(direction);
+
+                scale(faces[face].get_color_at(uv), *intensity)
+            }
+            Background::CubeCross { texture, intensity } => {
+                let (face, uv) = Self::get_cube_face(direction);
+                let (column, row) = [(2.0, 1.0), (0.0, 1.0), (1.0, 0.0), (1.0, 2.0), (1.0, 1.0), (3.0, 1.0)][face];
+
+                let uv = UV {
+                    u: (column + uv.u) / 4.0,
+                    v: (2.0 - row + uv.v) / 3.0,
+                };
+
+                scale(texture.get_color_at(uv), *intensity)
+            }
+        }
+    }
+
+    /// The face the direction points at (in the CubeMap order), and where on
+    /// that face.
+    fn get_cube_face(d: Vector) -> (usize, UV) {
+        let (x, y, z) = (abs(d.x), abs(d.y), abs(d.z));
+
+        // Each face is seen with +Y up, or with the front (+Z) down for the
+        // top and up for the bottom, like in the cross.
+        let (face, u, v) = if x >= y && x >= z {
+            if d.x > 0.0 {
+                (0, -d.z / x, d.y / x)
+            } else {
+                (1, d.z / x, d.y / x)
+            }
+        } else if y >= z {
+            if d.y > 0.0 {
+                (2, d.x / y, -d.z / y)
+            } else {
+                (3, d.x / y, d.z / y)
+            }
+        } else if d.z > 0.0 {
+            (4, d.x / z, d.y / z)
+        } else {
+            (5, -d.x / z, d.y / z)
+        };
+
+        // From -1..1 to 0..1
+        let uv = UV {
+            u: Color::in_limit((u + 1.0) / 2.0, 0.0, 1.0),
+            v: Color::in_limit((v + 1.0) / 2.0, 0.0, 1.0),
+        };
+
+        (face, uv)
+    }
+}
+
+fn scale(color: Color, intensity: f64) -> Color {
+    Color::new(color.r * intensity, color.g * intensity, color.b * intensity, 1.0)
+}
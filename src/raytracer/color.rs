@@ -68,6 +68,11 @@ impl Color {
         (r, g, b)
     }
 
+    /// Perceived brightness, from 0 to 1
+    pub fn luminance(self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
     pub fn intensify(self, intensity: f64) -> Color {
         Color::in_range(self.r * intensity, self.g * intensity, self.b * intensity)
     }
@@ -2,169 +2,167 @@
 
 use super::rt_object::RTObject;
 use super::vector::{Vector, Ray, UV};
-use super::math_shapes::{MathShape, AddIntersection};
-use super::transformation::MatrixTransformation;
+use super::math::EPSILON;
+use super::math_shapes::{MathShape, AddIntersection, HitRecord, Interval};
+use super::transformation::{MatrixTransformation, Transformation};
 
 #[derive(Clone, Copy)]
 pub enum Operator {
     Union,
     Intersection,
     Difference,
+    /// Inside an odd number of the objects
+    Xor,
 }
 
 #[derive(Clone)]
 pub struct CSG {
     transformation: MatrixTransformation,
-    a_obj: RTObject,
-    b_obj: RTObject,
+    /// With a difference, everything after the first is cut out of it
+    objects: Vec<RTObject>,
     operator: Operator,
+    /// Whether its surfaces have the material of the object they come from,
+    /// instead of its own
+    operand_materials: bool,
 }
 
 impl CSG {
-    pub fn new(transformation: MatrixTransformation, a_obj: RTObject, b_obj: RTObject, operator: Operator) -> Self {
+    pub fn new(transformation: MatrixTransformation, objects: Vec<RTObject>, operator: Operator) -> Self {
         CSG {
             transformation,
-            a_obj,
-            b_obj,
+            objects,
             operator,
+            operand_materials: false,
         }
     }
 
+    pub fn set_operand_materials(&mut self, operand_materials: bool) {
+        self.operand_materials = operand_materials;
+    }
+
     pub fn get_operation(&self) -> Operator {
         self.operator
     }
 }
 
-impl MathShape for CSG {
-    fn intersects(&self, ray: Ray, add_intersection: AddIntersection) {
-        let a = self.a_obj.get_shape();
-        let b = self.b_obj.get_shape();
-
+impl CSG {
+    /// Whether a point inside or outside each operand is inside the result
+    fn contains(&self, inside: &[bool]) -> bool {
         match self.operator {
-            Operator::Union => {
-                let mut check_intersection_1a = |d: f64| {
-                    if !b.is_inside(ray.point + ray.direction * d) {
-                        add_intersection(d);
-                    }
-                };
-
-                self.a_obj.intersects(ray.clone(), &mut check_intersection_1a);
-
-                let mut check_intersection_1b = |d: f64| {
-                    if !a.is_inside(ray.point + ray.direction * d) {
-                        add_intersection(d);
-                    }
-                };
-
-                self.b_obj.intersects(ray.clone(), &mut check_intersection_1b);
-            }
-            Operator::Intersection => {
-                let mut check_intersection_2a = |d: f64| {
-                    if b.is_inside(ray.point + ray.direction * d) {
-                        add_intersection(d);
-                    }
-                };
-
-                self.a_obj.intersects(ray.clone(), &mut check_intersection_2a);
-
-                let mut check_intersection_2b = |d: f64| {
-                    if a.is_inside(ray.point + ray.direction * d) {
-                        add_intersection(d);
-                    }
-                };
-
-                self.b_obj.intersects(ray.clone(), &mut check_intersection_2b);
-            }
-            Operator::Difference => {
-                let mut check_intersection_3a = |d: f64| {
-                    if !b.is_inside(ray.point + ray.direction * d) {
-                        add_intersection(d);
-                    }
-                };
-
-                self.a_obj.intersects(ray.clone(), &mut check_intersection_3a);
-
-                let mut check_intersection_3b = |d: f64| {
-                    if a.is_inside(ray.point + ray.direction * d) {
-                        add_intersection(d);
-                    }
-                };
-
-                self.b_obj.intersects(ray.clone(), &mut check_intersection_3b);
-            }
+            Operator::Union => inside.iter().any(|&inside| inside),
+            Operator::Intersection => inside.iter().all(|&inside| inside),
+            Operator::Difference => inside[0] && !inside[1..].iter().any(|&inside| inside),
+            Operator::Xor => inside.iter().filter(|&&inside| inside).count() % 2 == 1,
         }
     }
+}
 
-    fn get_normal(&self, surface_point: Vector) -> Vector {
-        let a = self.a_obj.get_shape();
-        let b = self.b_obj.get_shape();
+/// Combines the intervals of each operand along the same ray into those of
+/// the result, given whether being inside each operand is inside the result.
+/// Each hit keeps the operand it came from, with its normal turned around
+/// where the result is entered by leaving the operand. Where operands only
+/// touch, the result has no interval, but surfaces without an inside, like
+/// planes, are kept.
+pub fn combine_intervals<'a>(
+    operands: Vec<(&'a RTObject, Vec<Interval<'a>>)>, contains: impl Fn(&[bool]) -> bool,
+) -> Vec<Interval<'a>> {
+    // Each time the ray enters or leaves an operand
+    let mut events = vec![];
+    for (operand, (object, intervals)) in operands.iter().enumerate() {
+        for interval in intervals {
+            let entry = HitRecord { object: interval.entry.object.or(Some(*object)), ..interval.entry };
+            let exit = HitRecord { object: interval.exit.object.or(Some(*object)), ..interval.exit };
+            let surface = entry.distance == exit.distance;
+            events.push((operand, true, surface, entry));
+            events.push((operand, false, surface, exit));
+        }
+    }
+    // Entering first, so that touching intervals join up, and surfaces
+    // without an inside are entered before they are left
+    events.sort_by(|(_, a_entering, _, a), (_, b_entering, _, b)| {
+        a.distance.total_cmp(&b.distance).then(b_entering.cmp(a_entering))
+    });
 
-        match self.operator {
-            Operator::Union | Operator::Intersection => {
-                if a.is_on_surface(surface_point) {
-                    a.get_normal(surface_point)
-                } else if b.is_on_surface(surface_point) {
-                    b.get_normal(surface_point)
-                } else {
-                    //panic!("Get CSG normal failed.")
-                    Vector::new(1.0, 0.0, 0.0)
-                }
-            }
-            Operator::Difference => {
-                if a.is_on_surface(surface_point) {
-                    a.get_normal(surface_point)
-                } else if b.is_on_surface(surface_point) {
-                    b.get_normal(surface_point) * -1.0
-                } else {
-                    // FIXME: Weird, why doesn't this panic like the one above?
-                    Vector::new(1.0, 0.0, 0.0)
+    let mut depths = vec![0; operands.len()];
+    let mut inside = vec![false; operands.len()];
+    let mut was_inside = contains(&inside);
+    let mut entry: Option<(HitRecord, bool)> = None;
+    let mut intervals = vec![];
+
+    for (operand, entering, surface, hit) in events {
+        depths[operand] += if entering { 1 } else { -1 };
+        inside[operand] = depths[operand] > 0;
+
+        let is_inside = contains(&inside);
+        if is_inside == was_inside {
+            continue;
+        }
+        was_inside = is_inside;
+
+        let hit = if entering != is_inside { hit.flipped() } else { hit };
+        match entry.take() {
+            None => entry = Some((hit, surface)),
+            Some((entry, entry_surface)) => {
+                if hit.distance - entry.distance > EPSILON || (entry_surface && surface) {
+                    intervals.push(Interval { entry, exit: hit });
                 }
             }
         }
     }
 
-    fn is_inside(&self, point: Vector) -> bool {
-        let a = self.a_obj.get_shape();
-        let b = self.b_obj.get_shape();
+    intervals
+}
 
-        match self.operator {
-            Operator::Union => a.is_inside(point) || b.is_inside(point),
-            Operator::Intersection => a.is_inside(point) && b.is_inside(point),
-            Operator::Difference => a.is_inside(point) && !b.is_inside(point),
+impl MathShape for CSG {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        for interval in self.intervals(ray) {
+            add_intersection(interval.entry);
+            add_intersection(interval.exit);
         }
     }
 
-    fn is_on_surface(&self, point: Vector) -> bool {
-        let a = self.a_obj.get_shape();
-        let b = self.b_obj.get_shape();
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        let operands = self.objects
+            .iter()
+            .map(|obj| (obj, obj.intervals(ray.clone())))
+            .collect();
 
-        match self.operator {
-            Operator::Union => {
-                (a.is_on_surface(point) && !b.is_inside(point)) ||
-                    (b.is_on_surface(point) && !a.is_inside(point))
-            }
-            Operator::Intersection => {
-                (a.is_on_surface(point) && b.is_inside(point)) ||
-                    (b.is_on_surface(point) && a.is_inside(point))
-            }
-            Operator::Difference => {
-                (a.is_on_surface(point) && !b.is_inside(point)) ||
-                    (b.is_on_surface(point) && a.is_inside(point))
+        let mut intervals = combine_intervals(operands, |inside| self.contains(inside));
+
+        // With its own material, solid textures are in its own space
+        if !self.operand_materials {
+            for interval in intervals.iter_mut() {
+                for hit in [&mut interval.entry, &mut interval.exit] {
+                    let point = ray.point + ray.direction * hit.distance;
+                    hit.object = None;
+                    hit.local_point = self.transformation.reverse_transform_vector(point);
+                }
             }
         }
+
+        intervals
     }
 
-    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
-        let a = self.a_obj.get_shape();
-        let b = self.b_obj.get_shape();
+    // Hits come with their normals and UV coordinates from intervals(), and
+    // points are never tested against the result
 
-        if a.is_on_surface(point) {
-            a.get_uv_coordinates(point)
-        } else if b.is_on_surface(point) {
-            b.get_uv_coordinates(point)
-        } else {
-            Err("CSG's get_uv_coordinates called outside of a surface!")
-        }
+    fn get_normal(&self, _surface_point: Vector) -> Vector {
+        // FIXME: No panic
+        unimplemented!("CSG normals come from the hits of its operands")
+    }
+
+    fn is_inside(&self, _point: Vector) -> bool {
+        // FIXME: No panic
+        unimplemented!("CSG points are not tested, their intervals are combined")
+    }
+
+    fn is_on_surface(&self, _point: Vector) -> bool {
+        // FIXME: No panic
+        unimplemented!("CSG points are not tested, their intervals are combined")
+    }
+
+    fn get_uv_coordinates(&self, _point: Vector) -> Result<UV, &'static str> {
+        Err("CSG UV coordinates come from the hits of its operands")
     }
 
     fn set_transformation(&mut self, transformation: MatrixTransformation) {
@@ -183,4 +181,90 @@ impl MathShape for CSG {
     fn clone_box(&self) -> Box<dyn MathShape> {
         Box::new(self.clone())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::math_shapes::MathSphere;
+
+    fn sphere() -> RTObject {
+        let shape = MathSphere::new(MatrixTransformation::create_identity_matrix(), Vector::new(0.0, 0.0, 0.0), 1.0);
+        RTObject::new_default(Box::new(shape))
+    }
+
+    /// Intervals along the X axis, with the normals pointing along it
+    fn intervals<'a>(object: &'a RTObject, spans: &[(f64, f64)]) -> (&'a RTObject, Vec<Interval<'a>>) {
+        let ray = Ray { point: Vector::new(0.0, 0.0, 0.0), direction: Vector::new(1.0, 0.0, 0.0) };
+        let shape = &**object.get_shape();
+        let hit = |distance: f64, normal: f64| {
+            HitRecord::on_face(shape, &ray, distance, Vector::new(normal, 0.0, 0.0), None, None)
+        };
+
+        let intervals = spans
+            .iter()
+            .map(|&(entry, exit)| Interval { entry: hit(entry, -1.0), exit: hit(exit, 1.0) })
+            .collect();
+
+        (object, intervals)
+    }
+
+    fn combine(operator: Operator, a: &[(f64, f64)], b: &[(f64, f64)]) -> Vec<(f64, f64)> {
+        let (first, second) = (sphere(), sphere());
+        let csg = CSG::new(MatrixTransformation::create_identity_matrix(), vec![], operator);
+        let operands = vec![intervals(&first, a), intervals(&second, b)];
+
+        combine_intervals(operands, |inside| csg.contains(inside))
+            .iter()
+            .map(|interval| (interval.entry.distance, interval.exit.distance))
+            .collect()
+    }
+
+    #[test]
+    fn union() {
+        assert_eq!(combine(Operator::Union, &[(0.0, 1.0)], &[(2.0, 3.0)]), [(0.0, 1.0), (2.0, 3.0)]);
+        assert_eq!(combine(Operator::Union, &[(0.0, 1.0)], &[(1.0, 2.0)]), [(0.0, 2.0)]);
+        assert_eq!(combine(Operator::Union, &[(0.0, 3.0)], &[(1.0, 2.0)]), [(0.0, 3.0)]);
+    }
+
+    #[test]
+    fn intersection() {
+        assert_eq!(combine(Operator::Intersection, &[(0.0, 1.0)], &[(2.0, 3.0)]), []);
+        // Touching isn't overlapping
+        assert_eq!(combine(Operator::Intersection, &[(0.0, 1.0)], &[(1.0, 2.0)]), []);
+        assert_eq!(combine(Operator::Intersection, &[(0.0, 3.0)], &[(1.0, 2.0)]), [(1.0, 2.0)]);
+        assert_eq!(combine(Operator::Intersection, &[(0.0, 2.0)], &[(1.0, 3.0)]), [(1.0, 2.0)]);
+    }
+
+    /// A plane's hits have no length, but it is still drawn
+    #[test]
+    fn surfaces_are_kept() {
+        assert_eq!(combine(Operator::Union, &[(1.0, 1.0)], &[(2.0, 3.0)]), [(1.0, 1.0), (2.0, 3.0)]);
+        assert_eq!(combine(Operator::Intersection, &[(1.0, 1.0)], &[(0.0, 3.0)]), [(1.0, 1.0)]);
+        assert_eq!(combine(Operator::Difference, &[(0.0, 2.0)], &[(2.0, 2.0)]), [(0.0, 2.0)]);
+    }
+
+    #[test]
+    fn difference() {
+        assert_eq!(combine(Operator::Difference, &[(0.0, 2.0)], &[(2.0, 3.0)]), [(0.0, 2.0)]);
+        assert_eq!(combine(Operator::Difference, &[(1.0, 2.0)], &[(0.0, 1.0)]), [(1.0, 2.0)]);
+        assert_eq!(combine(Operator::Difference, &[(0.0, 3.0)], &[(1.0, 2.0)]), [(0.0, 1.0), (2.0, 3.0)]);
+        assert_eq!(combine(Operator::Difference, &[(1.0, 2.0)], &[(0.0, 3.0)]), []);
+    }
+
+    /// The walls of a hole belong to what was cut out, facing into the hole
+    #[test]
+    fn difference_flips_the_cut_surfaces() {
+        let (outer, inner) = (sphere(), sphere());
+        let csg = CSG::new(MatrixTransformation::create_identity_matrix(), vec![], Operator::Difference);
+        let operands = vec![intervals(&outer, &[(0.0, 3.0)]), intervals(&inner, &[(1.0, 2.0)])];
+        let result = combine_intervals(operands, |inside| csg.contains(inside));
+
+        let wall = result[0].exit;
+        assert!(std::ptr::eq(wall.object.unwrap(), &inner));
+        assert_eq!(wall.normal.x, 1.0);
+
+        let wall = result[1].entry;
+        assert!(std::ptr::eq(wall.object.unwrap(), &inner));
+        assert_eq!(wall.normal.x, -1.0);
+    }
+}
@@ -0,0 +1,194 @@
+use super::background::Background;
+use super::color::Color;
+use super::math::{PI, sin, cos, sqrt};
+use super::vector::Vector;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+// Lights the scene with the background. The light arriving at a point is the
+// sum over a fixed set of directions, picked once with a probability that
+// follows the brightness of the background, so bright spots like the sun get
+// most of the samples. Using the same directions everywhere trades noise for
+// a little banding when there are few samples.
+
+// The grid over latitude and longitude used to find the bright spots
+const GRID_WIDTH: usize = 64;
+const GRID_HEIGHT: usize = 32;
+const GRID_SUBSAMPLES: usize = 4;
+
+// Normals in the irradiance map, over latitude and longitude
+const MAP_WIDTH: usize = 32;
+const MAP_HEIGHT: usize = 16;
+
+#[derive(Clone)]
+struct Sample {
+    direction: Vector,
+    // The radiance divided by the probability and the number of samples
+    weight: Color,
+}
+
+#[derive(Clone)]
+pub struct EnvironmentLight {
+    samples: Vec<Sample>,
+    irradiance_map: Option<Vec<Color>>,
+}
+
+impl EnvironmentLight {
+    pub fn new(background: &Background, sample_count: usize) -> Self {
+        // How likely each cell of the grid is to be picked, as a running sum
+        let mut cdf = Vec::with_capacity(GRID_WIDTH * GRID_HEIGHT);
+        let mut total = 0.0;
+
+        for row in 0..GRID_HEIGHT {
+            for column in 0..GRID_WIDTH {
+                let mut brightness = 0.0;
+                for j in 0..GRID_SUBSAMPLES {
+                    for i in 0..GRID_SUBSAMPLES {
+                        let x = column as f64 + (i as f64 + 0.5) / GRID_SUBSAMPLES as f64;
+                        let y = row as f64 + (j as f64 + 0.5) / GRID_SUBSAMPLES as f64;
+                        let direction = grid_direction(x, y);
+                        brightness += background.get_radiance(direction).luminance();
+                    }
+                }
+
+                total += brightness * cell_solid_angle(row);
+                cdf.push(total);
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut samples = Vec::with_capacity(sample_count);
+
+        // A black background gives no light at all
+        if total > 0.0 {
+            for index in 0..sample_count {
+                // Stratified, so that the samples spread evenly over the light
+                let target = (index as f64 + rng.gen::<f64>()) / sample_count as f64 * total;
+                let cell = cdf.iter().position(|&sum| sum > target).unwrap_or(cdf.len() - 1);
+                let (column, row) = (cell % GRID_WIDTH, cell / GRID_WIDTH);
+                let previous = if cell == 0 { 0.0 } else { cdf[cell - 1] };
+                let probability = (cdf[cell] - previous) / total;
+
+                // Uniform over the solid angle of the cell
+                let x = column as f64 + rng.gen::<f64>();
+                let (bottom, top) = (row_sine(row as f64), row_sine(row as f64 + 1.0));
+                let y_sine = bottom + (top - bottom) * rng.gen::<f64>();
+                let direction = direction_from(x, y_sine);
+
+                let density = probability / cell_solid_angle(row);
+                let radiance = background.get_radiance(direction);
+                let factor = 1.0 / (density * sample_count as f64);
+
+                samples.push(Sample {
+                    direction,
+                    weight: Color::new(radiance.r * factor, radiance.g * factor, radiance.b * factor, 1.0),
+                });
+            }
+        }
+
+        EnvironmentLight { samples, irradiance_map: None }
+    }
+
+    /// Precomputes the light for every normal, so that shading doesn't need a
+    /// ray per sample; the price is that nothing casts shadows on it.
+    pub fn compute_irradiance_map(&mut self) {
+        let mut map = Vec::with_capacity(MAP_WIDTH * MAP_HEIGHT);
+
+        for row in 0..MAP_HEIGHT {
+            for column in 0..MAP_WIDTH {
+                let x = (column as f64 + 0.5) * GRID_WIDTH as f64 / MAP_WIDTH as f64;
+                let y = (row as f64 + 0.5) * GRID_HEIGHT as f64 / MAP_HEIGHT as f64;
+                let normal = grid_direction(x, y);
+
+                map.push(self.get_irradiance(normal, |_| 1.0));
+            }
+        }
+
+        self.irradiance_map = Some(map);
+    }
+
+    /// The light falling on a surface facing `normal`, divided by pi so that
+    /// multiplying by the color gives what a matte surface reflects. It isn't
+    /// limited to 0..1. `visibility` tells how much light gets through in a
+    /// direction (0 when it is blocked); it isn't used with an irradiance map.
+    pub fn get_irradiance(&self, normal: Vector, visibility: impl Fn(Vector) -> f64) -> Color {
+        if let Some(map) = &self.irradiance_map {
+            return Self::lookup(map, normal);
+        }
+
+        let mut irradiance = Color::new(0.0, 0.0, 0.0, 1.0);
+
+        for sample in &self.samples {
+            let cosine = sample.direction * normal;
+            if cosine <= 0.0 {
+                continue;
+            }
+
+            let amount = cosine * visibility(sample.direction) / PI;
+            if amount == 0.0 {
+                continue;
+            }
+
+            irradiance.r += sample.weight.r * amount;
+            irradiance.g += sample.weight.g * amount;
+            irradiance.b += sample.weight.b * amount;
+        }
+
+        irradiance
+    }
+
+    /// Bilinear interpolation between the normals around `normal`
+    fn lookup(map: &[Color], normal: Vector) -> Color {
+        let normal = normal.normalized();
+        let x = (0.5 + normal.x.atan2(normal.z) / (2.0 * PI)) * MAP_WIDTH as f64 - 0.5;
+        let y = (0.5 + Color::in_limit(normal.y, -1.0, 1.0).asin() / PI) * MAP_HEIGHT as f64 - 0.5;
+
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        // Around the Y axis, but not over the poles
+        let column = |x: f64| (x as i64).rem_euclid(MAP_WIDTH as i64) as usize;
+        let row = |y: f64| (y.max(0.0) as usize).min(MAP_HEIGHT - 1);
+
+        let mut result = Color::new(0.0, 0.0, 0.0, 1.0);
+        for &(x, y, weight) in &[
+            (x0, y0, (1.0 - fx) * (1.0 - fy)),
+            (x0 + 1.0, y0, fx * (1.0 - fy)),
+            (x0, y0 + 1.0, (1.0 - fx) * fy),
+            (x0 + 1.0, y0 + 1.0, fx * fy),
+        ] {
+            let color = map[row(y) * MAP_WIDTH + column(x)];
+            result.r += color.r * weight;
+            result.g += color.g * weight;
+            result.b += color.b * weight;
+        }
+
+        result
+    }
+}
+
+/// Sine of the latitude at the bottom of a grid row; rows go from -90 to 90
+/// degrees.
+fn row_sine(row: f64) -> f64 {
+    sin((row / GRID_HEIGHT as f64 - 0.5) * PI)
+}
+
+fn cell_solid_angle(row: usize) -> f64 {
+    (row_sine(row as f64 + 1.0) - row_sine(row as f64)) * 2.0 * PI / GRID_WIDTH as f64
+}
+
+/// The direction at a position of the grid, with the front (+Z) in the middle
+/// like the equirectangular background.
+fn grid_direction(x: f64, y: f64) -> Vector {
+    let latitude = (y / GRID_HEIGHT as f64 - 0.5) * PI;
+
+    direction_from(x, sin(latitude))
+}
+
+fn direction_from(x: f64, y_sine: f64) -> Vector {
+    let longitude = (x / GRID_WIDTH as f64 - 0.5) * 2.0 * PI;
+    let radius = sqrt((1.0 - y_sine * y_sine).max(0.0));
+
+    Vector::new(radius * sin(longitude), y_sine, radius * cos(longitude))
+}
@@ -1,5 +1,5 @@
 use super::texture::Texture;
-use super::vector::UV;
+use super::vector::{Vector, UV};
 use super::color::Color;
 
 pub trait Material: Send + Sync {
@@ -12,6 +12,27 @@ pub trait Material: Send + Sync {
         return self.get_color_at(uv_coordinates.u, uv_coordinates.v);
     }
 
+    /// Like get_color_at_uv, for a pixel covering `footprint` of the UV space
+    fn get_color_at_uv_footprint(&self, uv_coordinates: UV, _footprint: f64) -> Color
+    {
+        self.get_color_at_uv(uv_coordinates)
+    }
+
+    /// The color at a point in the object's own space, for materials using
+    /// solid textures. None means the UV coordinates should be used instead.
+    fn get_color_at_point(&self, _point: Vector) -> Option<Color>
+    {
+        None
+    }
+
+    /// The normal at the UV coordinates in tangent space, where X follows U,
+    /// Y follows V and Z points out of the surface; None if it isn't changed
+    /// by a bump or normal map.
+    fn get_tangent_normal_at_uv(&self, _uv_coordinates: UV) -> Option<Vector>
+    {
+        None
+    }
+
     fn get_reflectivity_at_uv(&self, uv_coordinates: UV) -> f64
     {
         return self.get_reflectivity_at(uv_coordinates.u, uv_coordinates.v);
@@ -71,6 +92,8 @@ pub struct TexturedMaterial {
     texture: Box<dyn Texture>,
     reflectivity: f64,
     transparency: f64,
+    // Sample the texture in object space, repeating every this many units
+    solid_size: Option<f64>,
 }
 
 impl TexturedMaterial {
@@ -79,8 +102,15 @@ impl TexturedMaterial {
             texture,
             reflectivity,
             transparency,
+            solid_size: None,
         }
     }
+
+    /// Samples the texture by the point in object space, with one repetition
+    /// of the pattern every `size` units, instead of by UV coordinates.
+    pub fn set_solid(&mut self, size: f64) {
+        self.solid_size = Some(size);
+    }
 }
 
 impl Material for TexturedMaterial {
@@ -88,12 +118,126 @@ impl Material for TexturedMaterial {
         self.texture.get_color_at(UV { u, v })
     }
 
+    fn get_color_at_uv_footprint(&self, uv_coordinates: UV, footprint: f64) -> Color {
+        self.texture.get_color_at_footprint(uv_coordinates, footprint)
+    }
+
+    fn get_color_at_point(&self, point: Vector) -> Option<Color> {
+        let size = self.solid_size?;
+
+        // The object's origin goes where the middle of the UV square would be
+        let point = point * (1.0 / size) + Vector::new(0.5, 0.5, 0.0);
+        self.texture.get_color_at_point(point)
+    }
+
     fn get_reflectivity_at(&self, _u: f64, _v: f64) -> f64 {
         self.reflectivity
     }
 
-    fn get_transparency_at(&self, _u: f64, _v: f64) -> f64 {
-        self.transparency
+    fn get_transparency_at(&self, u: f64, v: f64) -> f64 {
+        if self.solid_size.is_some() {
+            return self.transparency;
+        }
+
+        // Where the texture is see-through, so is the material
+        let alpha = self.texture.get_color_at(UV { u, v }).a;
+        1.0 - (1.0 - self.transparency) * alpha
+    }
+
+    fn clone_box(&self) -> Box<dyn Material> {
+        Box::new(self.clone())
+    }
+}
+
+/// Textures that vary the other properties of a material, read as grayscale
+/// except for the normal map.
+#[derive(Clone)]
+pub struct MaterialMaps {
+    pub reflectivity: Option<Box<dyn Texture>>,
+    pub transparency: Option<Box<dyn Texture>>,
+    /// Heights, with white being the highest
+    pub bump: Option<Box<dyn Texture>>,
+    /// How high white is, in hundredths of the size of the texture
+    pub bump_strength: f64,
+    /// Tangent space normals, encoded as colors
+    pub normal: Option<Box<dyn Texture>>,
+}
+
+impl MaterialMaps {
+    pub fn new() -> Self {
+        MaterialMaps {
+            reflectivity: None,
+            transparency: None,
+            bump: None,
+            bump_strength: 1.0,
+            normal: None,
+        }
+    }
+}
+
+/// Applies MaterialMaps on top of any other material
+#[derive(Clone)]
+pub struct MappedMaterial {
+    material: Box<dyn Material>,
+    maps: MaterialMaps,
+}
+
+impl MappedMaterial {
+    pub fn new(material: Box<dyn Material>, maps: MaterialMaps) -> Self {
+        MappedMaterial { material, maps }
+    }
+}
+
+impl Material for MappedMaterial {
+    fn get_color_at(&self, u: f64, v: f64) -> Color {
+        self.material.get_color_at(u, v)
+    }
+
+    fn get_color_at_uv_footprint(&self, uv_coordinates: UV, footprint: f64) -> Color {
+        self.material.get_color_at_uv_footprint(uv_coordinates, footprint)
+    }
+
+    fn get_color_at_point(&self, point: Vector) -> Option<Color> {
+        self.material.get_color_at_point(point)
+    }
+
+    fn get_tangent_normal_at_uv(&self, uv_coordinates: UV) -> Option<Vector> {
+        if let Some(normal_map) = &self.maps.normal {
+            let color = normal_map.get_color_at(uv_coordinates);
+            let normal = Vector::new(
+                color.r * 2.0 - 1.0,
+                color.g * 2.0 - 1.0,
+                color.b * 2.0 - 1.0,
+            );
+            return Some(normal.normalized());
+        }
+
+        let bump_map = self.maps.bump.as_ref()?;
+
+        // The slope of the height map, from the heights around the point
+        let step = 1.0 / 512.0;
+        let height = |u: f64, v: f64| bump_map.get_color_at(UV { u, v }).luminance();
+        let (u, v) = (uv_coordinates.u, uv_coordinates.v);
+
+        let du = (height(u + step, v) - height(u - step, v)) / (2.0 * step);
+        let dv = (height(u, v + step) - height(u, v - step)) / (2.0 * step);
+        let strength = self.maps.bump_strength / 100.0;
+
+        Some(Vector::new(-du * strength, -dv * strength, 1.0).normalized())
+    }
+
+    fn get_reflectivity_at(&self, u: f64, v: f64) -> f64 {
+        match &self.maps.reflectivity {
+            Some(map) => map.get_color_at(UV { u, v }).luminance(),
+            None => self.material.get_reflectivity_at(u, v),
+        }
+    }
+
+    fn get_transparency_at(&self, u: f64, v: f64) -> f64 {
+        match &self.maps.transparency {
+            Some(map) => map.get_color_at(UV { u, v }).luminance(),
+            None => self.material.get_transparency_at(u, v),
+        }
     }
 
     fn clone_box(&self) -> Box<dyn Material> {
@@ -22,3 +22,71 @@ pub fn abs(x: f64) -> f64 {
 pub fn sqrt(x: f64) -> f64 {
     x.sqrt()
 }
+
+/// Value of the polynomial with the given coefficients, from the highest
+/// power down, at `x`.
+pub fn evaluate_polynomial(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().fold(0.0, |sum, coefficient| sum * x + coefficient)
+}
+
+/// Real roots between `min` and `max` of the polynomial with the given
+/// coefficients (from the highest power down), in increasing order. The roots
+/// of the derivative split the range into parts where the polynomial only
+/// goes up or down, and each part with a sign change has one root, found by
+/// bisection. Roots where the polynomial only touches 0 are missed.
+pub fn polynomial_roots(coefficients: &[f64], min: f64, max: f64) -> Vec<f64> {
+    let degree = match coefficients.len() {
+        0 | 1 => return vec![],
+        length => length - 1,
+    };
+
+    let derivative: Vec<f64> = coefficients[..degree]
+        .iter()
+        .enumerate()
+        .map(|(index, coefficient)| coefficient * (degree - index) as f64)
+        .collect();
+
+    let mut bounds = vec![min];
+    bounds.extend(polynomial_roots(&derivative, min, max));
+    bounds.push(max);
+
+    let mut roots = vec![];
+
+    for pair in bounds.windows(2) {
+        let (mut low, mut high) = (pair[0], pair[1]);
+        let low_value = evaluate_polynomial(coefficients, low);
+        let high_value = evaluate_polynomial(coefficients, high);
+
+        if low_value == 0.0 {
+            if roots.last() != Some(&low) {
+                roots.push(low);
+            }
+            continue;
+        }
+        if high_value == 0.0 {
+            roots.push(high);
+            continue;
+        }
+        if low_value.signum() == high_value.signum() {
+            continue;
+        }
+
+        // Until the middle can't be told apart from the ends
+        loop {
+            let middle = (low + high) / 2.0;
+            if middle <= low || middle >= high {
+                break;
+            }
+
+            if evaluate_polynomial(coefficients, middle).signum() == low_value.signum() {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        roots.push((low + high) / 2.0);
+    }
+
+    roots
+}
@@ -1,11 +1,87 @@
-use super::math::{PI, EPSILON, INFINITY, NEG_INFINITY, sin, sqrt, abs, acos};
+use super::math::{PI, EPSILON, INFINITY, NEG_INFINITY, sin, sqrt, abs, acos, polynomial_roots};
 use super::transformation::{MatrixTransformation, Transformation};
 use super::vector::{Vector, UV, Ray};
+use super::color::Color;
+use super::rt_object::RTObject;
+
+use std::sync::Arc;
+
+pub type AddIntersection<'a, 'b> = &'a mut dyn FnMut(HitRecord<'b>);
+
+/// Where a ray crosses the surface of a shape, with what the shape knows
+/// about the surface there
+#[derive(Clone, Copy)]
+pub struct HitRecord<'a> {
+    pub distance: f64,
+    /// The point in the shape's own space, before its transformation, where
+    /// solid textures are looked up
+    pub local_point: Vector,
+    /// Out of the shape, or out of the CSG it is a part of
+    pub normal: Vector,
+    pub uv: Option<UV>,
+    /// For shapes made of several faces, which one; see cube_face() and
+    /// MathCone::cone_face()
+    pub face: Option<usize>,
+    /// Whether the ray comes from the side the normal points to
+    pub front_face: bool,
+    /// The shape whose surface it is, for the UV coordinates around it
+    pub shape: &'a dyn MathShape,
+    /// The CSG operand whose material it has, if not the drawn object's
+    pub object: Option<&'a RTObject>,
+}
+
+impl<'a> HitRecord<'a> {
+    /// A hit `distance` along a ray in the shape's own space, with the normal
+    /// in world space and the UV coordinates the shape worked out there
+    pub fn on_face(
+        shape: &'a dyn MathShape, ray: &Ray, distance: f64, normal: Vector, uv: Option<UV>, face: Option<usize>,
+    ) -> HitRecord<'a> {
+        let direction = shape.get_transformation().transform_direction_vector(ray.direction);
+
+        HitRecord {
+            distance,
+            local_point: ray.point + ray.direction * distance,
+            normal,
+            uv,
+            face,
+            front_face: direction * normal < 0.0,
+            shape,
+            object: None,
+        }
+    }
+
+    /// The open end of a shape that goes on forever, which has no surface
+    pub fn at_infinity(shape: &'a dyn MathShape, distance: f64) -> HitRecord<'a> {
+        HitRecord {
+            distance,
+            local_point: Vector::new(distance, distance, distance),
+            normal: Vector::new(0.0, 0.0, 0.0),
+            uv: None,
+            face: None,
+            front_face: true,
+            shape,
+            object: None,
+        }
+    }
 
-pub type AddIntersection<'a> = &'a mut dyn FnMut(f64);
+    /// The same hit seen from the other side of the surface
+    pub fn flipped(&self) -> HitRecord<'a> {
+        HitRecord { normal: self.normal * -1.0, front_face: !self.front_face, ..*self }
+    }
+}
+
+/// Where a ray goes through a shape, from `entry` to `exit`. The distances
+/// can be infinite for shapes that go on forever.
+#[derive(Clone, Copy)]
+pub struct Interval<'a> {
+    pub entry: HitRecord<'a>,
+    pub exit: HitRecord<'a>,
+}
 
 pub trait MathShape: Send + Sync {
-    fn intersects(&self, ray: Ray, add_intersection: AddIntersection);
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>);
+    /// The parts of the ray inside the shape, in order
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>>;
     fn get_normal(&self, surface_point: Vector) -> Vector;
     fn is_inside(&self, point: Vector) -> bool;
     fn is_on_surface(&self, point: Vector) -> bool;
@@ -25,6 +101,122 @@ impl Clone for Box<dyn MathShape> {
     }
 }
 
+/// The intervals of a solid shape, from where the ray crosses its surface,
+/// in increasing order, and whether the ray is inside it at `start`. Each
+/// crossing goes from inside to outside or back. The ray is cut off at
+/// `start` and `end`, which are infinite for shapes that go on forever.
+/// `hit_record` makes the hits where the ray crosses the surface.
+pub fn crossing_intervals<'a>(
+    shape: &'a dyn MathShape, hit_record: impl Fn(f64) -> HitRecord<'a>,
+    start: f64, end: f64, inside: bool, crossings: &[f64],
+) -> Vec<Interval<'a>> {
+    let boundary = |distance: f64| if distance.is_finite() {
+        hit_record(distance)
+    } else {
+        HitRecord::at_infinity(shape, distance)
+    };
+
+    let mut entry = if inside { Some(start) } else { None };
+    let mut intervals = vec![];
+
+    for &crossing in crossings.iter().filter(|crossing| crossing.is_finite()) {
+        match entry.take() {
+            None => entry = Some(crossing),
+            Some(entry) => intervals.push((entry, crossing)),
+        }
+    }
+    if let Some(entry) = entry {
+        intervals.push((entry, end));
+    }
+
+    // Where the ray only touches the surface, it goes in and right back out
+    intervals
+        .into_iter()
+        .filter(|(entry, exit)| exit - entry > EPSILON)
+        .map(|(entry, exit)| Interval { entry: boundary(entry), exit: boundary(exit) })
+        .collect()
+}
+
+/// The intervals of a shape without an inside, like a plane: the ray only
+/// touches it where it crosses.
+pub fn surface_intervals(shape: &dyn MathShape, ray: Ray) -> Vec<Interval<'_>> {
+    let mut intervals = vec![];
+    shape.intersects(ray, &mut |hit| intervals.push(Interval { entry: hit, exit: hit }));
+    intervals.sort_by(|a, b| a.entry.distance.total_cmp(&b.entry.distance));
+
+    intervals
+}
+
+/// The face of a cube or box that a point relative to its center, scaled so
+/// that the faces are at -1 and 1, is nearest to: 0 to 5 for -X, +X, -Y, +Y,
+/// -Z and +Z.
+fn cube_face(p: Vector) -> usize {
+    let (x, y, z) = (abs(p.x), abs(p.y), abs(p.z));
+    let (axis, value) = if x >= y && x >= z {
+        (0, p.x)
+    } else if y >= z {
+        (1, p.y)
+    } else {
+        (2, p.z)
+    };
+
+    axis * 2 + if value > 0.0 { 1 } else { 0 }
+}
+
+/// The outward normal of a face numbered like in cube_face()
+fn cube_face_normal(face: usize) -> Vector {
+    let sign = if face % 2 == 1 { 1.0 } else { -1.0 };
+
+    match face / 2 {
+        0 => Vector::new(sign, 0.0, 0.0),
+        1 => Vector::new(0.0, sign, 0.0),
+        _ => Vector::new(0.0, 0.0, sign),
+    }
+}
+
+/// Where a ray in a box's own space, relative to its center, crosses its
+/// faces at `half` from the center along each axis: the distances and faces
+/// it enters and leaves through, if it hits the box at all.
+fn box_crossings(origin: Vector, direction: Vector, half: Vector) -> Option<((f64, usize), (f64, usize))> {
+    let origin = [origin.x, origin.y, origin.z];
+    let direction = [direction.x, direction.y, direction.z];
+    let half = [half.x, half.y, half.z];
+
+    let mut near = (NEG_INFINITY, 0);
+    let mut far = (INFINITY, 0);
+
+    for i in 0..3 {
+        if direction[i] == 0.0 {
+            if abs(origin[i]) > half[i] {
+                return None;
+            }
+            continue;
+        }
+
+        let t1 = (-half[i] - origin[i]) / direction[i];
+        let t2 = (half[i] - origin[i]) / direction[i];
+        // Going up an axis, the ray enters through the lower face
+        let (entry, exit) = if direction[i] > 0.0 {
+            ((t1, i * 2), (t2, i * 2 + 1))
+        } else {
+            ((t2, i * 2 + 1), (t1, i * 2))
+        };
+
+        if entry.0 > near.0 {
+            near = entry;
+        }
+        if exit.0 < far.0 {
+            far = exit;
+        }
+
+        if near.0 > far.0 || far.0 < 0.0 {
+            return None;
+        }
+    }
+
+    Some((near, far))
+}
+
 #[derive(Clone)]
 pub struct MathSphere {
     transformation: MatrixTransformation,
@@ -36,10 +228,9 @@ impl MathSphere {
     pub fn new(transformation: MatrixTransformation, center: Vector, radius: f64) -> Self {
         MathSphere { transformation, center, radius }
     }
-}
 
-impl MathShape for MathSphere {
-    fn intersects(&self, ray: Ray, add_intersection: AddIntersection) {
+    /// The distances where the ray goes in and out, if it hits at all
+    fn crossings(&self, ray: &Ray) -> Option<(f64, f64)> {
         let v = ray.point - self.center;
         let d = ray.direction.normalized();
 
@@ -49,38 +240,21 @@ impl MathShape for MathSphere {
         let vd = v * d;
         let sum = vd * vd - (v * v - r * r);
         if sum < 0.0 {
-            return;
+            return None;
         }
 
-        let first = (-vd + sqrt(sum)) * scale;
-        let second = (-vd - sqrt(sum)) * scale;
-
-        // Some might be behind the camera, but objects behind the camera might
-        // be of interest as well (on an orthogonal view, for example).
-        add_intersection(first);
-        add_intersection(second);
-    }
-
-    fn get_normal(&self, surface_point: Vector) -> Vector {
-        let surface_point = self.transformation.reverse_transform_vector(surface_point);
-        let normal = surface_point - self.center;
-        self.transformation.transform_direction_vector(normal).normalized()
-    }
-
-    fn is_inside(&self, point: Vector) -> bool {
-        let point = self.transformation.reverse_transform_vector(point);
-
-        (point - self.center).length() <= self.radius + EPSILON
+        Some(((-vd - sqrt(sum)) * scale, (-vd + sqrt(sum)) * scale))
     }
 
-    fn is_on_surface(&self, point: Vector) -> bool {
-        let point = self.transformation.reverse_transform_vector(point);
+    fn hit_record<'a>(&'a self, ray: &Ray, distance: f64) -> HitRecord<'a> {
+        let p = ray.point + ray.direction * distance - self.center;
+        let normal = self.transformation.transform_normal(p).normalized();
 
-        abs((point - self.center).length() - self.radius) < EPSILON
+        HitRecord::on_face(self, ray, distance, normal, Some(self.local_uv_coordinates(p)), None)
     }
 
-    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
-        let point = self.transformation.reverse_transform_vector(point - self.center);
+    /// For a point relative to the center, in the sphere's own space
+    fn local_uv_coordinates(&self, point: Vector) -> UV {
         let point = point.normalized() * (1.0 - EPSILON);
 
         let up = Vector::new(0.0, 1.0, 0.0);
@@ -110,7 +284,52 @@ impl MathShape for MathSphere {
             theta
         };
 
-        Ok(UV { u, v })
+        UV { u, v }
+    }
+}
+
+impl MathShape for MathSphere {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        // Some might be behind the camera, but objects behind the camera might
+        // be of interest as well (on an orthogonal view, for example).
+        if let Some((entry, exit)) = self.crossings(&ray) {
+            add_intersection(self.hit_record(&ray, exit));
+            add_intersection(self.hit_record(&ray, entry));
+        }
+    }
+
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        self.crossings(&ray)
+            .map(|(entry, exit)| Interval {
+                entry: self.hit_record(&ray, entry),
+                exit: self.hit_record(&ray, exit),
+            })
+            .into_iter()
+            .collect()
+    }
+
+    fn get_normal(&self, surface_point: Vector) -> Vector {
+        let surface_point = self.transformation.reverse_transform_vector(surface_point);
+        let normal = surface_point - self.center;
+        self.transformation.transform_normal(normal).normalized()
+    }
+
+    fn is_inside(&self, point: Vector) -> bool {
+        let point = self.transformation.reverse_transform_vector(point);
+
+        (point - self.center).length() <= self.radius + EPSILON
+    }
+
+    fn is_on_surface(&self, point: Vector) -> bool {
+        let point = self.transformation.reverse_transform_vector(point);
+
+        abs((point - self.center).length() - self.radius) < EPSILON
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        let point = self.transformation.reverse_transform_vector(point) - self.center;
+
+        Ok(self.local_uv_coordinates(point))
     }
 
     fn set_transformation(&mut self, transformation: MatrixTransformation) {
@@ -134,6 +353,8 @@ pub struct MathPlane {
     c: f64,
     d: f64,
     normal: Vector,
+    tile_size: f64,
+    tile_offset: UV,
 }
 
 impl MathPlane {
@@ -148,24 +369,43 @@ impl MathPlane {
             c,
             d,
             normal,
+            tile_size: 50.0,
+            tile_offset: UV { u: 0.0, v: 0.0 },
         }
     }
 
+    /// The texture repeats every `tile_size` units along the plane, shifted by
+    /// `tile_offset` (in fractions of a tile).
+    pub fn set_tiling(&mut self, tile_size: f64, tile_offset: UV) {
+        self.tile_size = tile_size;
+        self.tile_offset = tile_offset;
+    }
+
     pub fn from_normal(transformation: MatrixTransformation, normal: Vector, distance: f64) -> Self {
         MathPlane::new(transformation, normal.x, normal.y, normal.z, distance)
     }
 
     fn transformed_normal(normal: Vector, transformation: &MatrixTransformation) -> Vector {
-        transformation.transform_direction_vector(normal).normalized()
+        transformation.transform_normal(normal).normalized()
     }
 
     fn is_transformed_point_on_surface(&self, point: Vector) -> bool {
         abs(self.a * point.x + self.b * point.y + self.c * point.z + self.d) < EPSILON
     }
+
+    /// For a point in the plane's own space
+    fn local_uv_coordinates(&self, point: Vector) -> UV {
+        let (u_axis, v_axis) = plane_axes(Vector::new(self.a, self.b, self.c).normalized());
+
+        let u = (point * u_axis) / self.tile_size + self.tile_offset.u;
+        let v = (point * v_axis) / self.tile_size + self.tile_offset.v;
+
+        UV { u: u.rem_euclid(1.0), v: v.rem_euclid(1.0) }
+    }
 }
 
 impl MathShape for MathPlane {
-    fn intersects(&self, ray: Ray, add_intersection: AddIntersection) {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
         let p_n = Vector::new(self.a, self.b, self.c).normalized();
         let r_0 = ray.point;
         let r_d = ray.direction;
@@ -174,11 +414,16 @@ impl MathShape for MathPlane {
         if v_d != 0.0 {
             let t = -(p_n * r_0 + self.d) * (1.0 / v_d);
             if t >= 0.0 {
-                add_intersection(t);
+                let uv = self.local_uv_coordinates(ray.point + ray.direction * t);
+                add_intersection(HitRecord::on_face(self, &ray, t, self.normal, Some(uv), None));
             }
         }
     }
 
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        surface_intervals(self, ray)
+    }
+
     fn get_normal(&self, _surface_point: Vector) -> Vector {
         self.normal
     }
@@ -193,8 +438,8 @@ impl MathShape for MathPlane {
         )
     }
 
-    fn get_uv_coordinates(&self, _point: Vector) -> Result<UV, &'static str> {
-        Err("UV not implemented for MathPlane!")
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        Ok(self.local_uv_coordinates(self.transformation.reverse_transform_vector(point)))
     }
 
     fn set_transformation(&mut self, transformation: MatrixTransformation) {
@@ -211,109 +456,137 @@ impl MathShape for MathPlane {
     }
 }
 
+/// How the faces of a cube are laid out on its texture.
+#[derive(Debug, Clone, Copy)]
+pub enum CubeMapping {
+    /// Every face shows the whole texture.
+    Faces,
+    /// The texture is a 4x3 cross, with the front face in the middle:
+    ///
+    /// ```text
+    ///     +Y
+    /// -X  -Z  +X  +Z
+    ///     -Y
+    /// ```
+    Cross,
+}
+
+/// Two directions along a plane with the given normal, used as the U and V
+/// axes. For floors U follows X and V follows Z, for walls V points up.
+fn plane_axes(normal: Vector) -> (Vector, Vector) {
+    let hint = if abs(normal.y) > 0.9 {
+        Vector::new(0.0, 0.0, 1.0)
+    } else {
+        Vector::new(0.0, 1.0, 0.0)
+    };
+
+    let v_axis = (hint - normal * (normal * hint)).normalized();
+    let u_axis = Vector::new(
+        normal.y * v_axis.z - normal.z * v_axis.y,
+        normal.z * v_axis.x - normal.x * v_axis.z,
+        normal.x * v_axis.y - normal.y * v_axis.x,
+    );
+
+    (u_axis, v_axis)
+}
+
+/// UV coordinates on a cube, for a point `p` relative to the center, scaled so
+/// that the faces are at -1 and 1.
+fn cube_uv_coordinates(p: Vector, uv_mapping: CubeMapping) -> UV {
+    // The face is the one on the axis farthest from the center; (u, v) are
+    // as seen from outside that face, with v pointing up (or away from
+    // the front, for the top and bottom faces).
+    let (x, y, z) = (abs(p.x), abs(p.y), abs(p.z));
+    let (column, row, u, v) = if x >= y && x >= z {
+        if p.x > 0.0 {
+            (2.0, 1.0, p.z, p.y)
+        } else {
+            (0.0, 1.0, -p.z, p.y)
+        }
+    } else if y >= z {
+        if p.y > 0.0 {
+            (1.0, 0.0, p.x, p.z)
+        } else {
+            (1.0, 2.0, p.x, -p.z)
+        }
+    } else if p.z > 0.0 {
+        (3.0, 1.0, -p.x, p.y)
+    } else {
+        (1.0, 1.0, p.x, p.y)
+    };
+
+    // From -1..1 to 0..1
+    let u = Color::in_limit((u + 1.0) / 2.0, 0.0, 1.0);
+    let v = Color::in_limit((v + 1.0) / 2.0, 0.0, 1.0);
+
+    match uv_mapping {
+        CubeMapping::Faces => UV { u, v },
+        CubeMapping::Cross => UV {
+            u: (column + u) / 4.0,
+            v: (2.0 - row + v) / 3.0,
+        },
+    }
+}
+
 #[derive(Clone)]
 pub struct MathCube {
     transformation: MatrixTransformation,
-    p1: MathPlane,
-    p2: MathPlane,
-    p3: MathPlane,
-    p4: MathPlane,
-    p5: MathPlane,
-    p6: MathPlane,
+    uv_mapping: CubeMapping,
     center: Vector,
+    /// Half the length of a side
     length: f64,
 }
 
 impl MathCube {
     pub fn new(transformation: MatrixTransformation, center: Vector, length: f64) -> Self {
-        let length = length / 2.0;
-        let t = transformation;
-
         MathCube {
-            p1: MathPlane::new(t.clone(), 0.0, 0.0, 1.0, -(center.z + length / 2.0)),
-            p6: MathPlane::new(t.clone(), 0.0, 0.0, -1.0, center.z + -length / 2.0),
-            p2: MathPlane::new(t.clone(), 0.0, 1.0, 0.0, -(center.y + length / 2.0)),
-            p5: MathPlane::new(t.clone(), 0.0, -1.0, 0.0, center.y + -length / 2.0),
-            p3: MathPlane::new(t.clone(), 1.0, 0.0, 0.0, -(center.x + length / 2.0)),
-            p4: MathPlane::new(t.clone(), -1.0, 0.0, 0.0, center.x + -length / 2.0),
-
-            transformation: t,
+            transformation,
+            uv_mapping: CubeMapping::Faces,
             center,
-            length,
+            length: length / 2.0,
         }
     }
-}
 
-impl MathShape for MathCube {
-    fn intersects(&self, ray: Ray, add_intersection: AddIntersection) {
-        let mut t_near = NEG_INFINITY;
-        let mut t_far = INFINITY;
-
-        let ray_direction_v = [ray.direction.x, ray.direction.y, ray.direction.z];
-        let ray_point_v = [ray.point.x, ray.point.y, ray.point.z];
-        let center_v = [self.center.x, self.center.y, self.center.z];
-
-        // X planes
-        for i in 0..3 {
-            if ray_direction_v[i] == 0.0 {
-                if ray_point_v[i] < center_v[i] - self.length ||
-                    ray_point_v[i] > center_v[i] + self.length {
-                    return;
-                }
-                // ?
-                continue;
-            }
+    pub fn set_uv_mapping(&mut self, uv_mapping: CubeMapping) {
+        self.uv_mapping = uv_mapping;
+    }
 
-            let t1 = (center_v[i] - self.length - ray_point_v[i]) / ray_direction_v[i];
-            let t2 = (center_v[i] + self.length - ray_point_v[i]) / ray_direction_v[i];
+    /// A point in world space relative to the center, with the faces at -1 and 1
+    fn local_point(&self, point: Vector) -> Vector {
+        (self.transformation.reverse_transform_vector(point) - self.center) * (1.0 / self.length)
+    }
 
-            let (t1, t2) = if t1 > t2 {
-                (t2, t1)
-            } else {
-                (t1, t2)
-            };
+    fn hit_record<'a>(&'a self, ray: &Ray, (distance, face): (f64, usize)) -> HitRecord<'a> {
+        let p = (ray.point + ray.direction * distance - self.center) * (1.0 / self.length);
+        let normal = self.transformation.transform_normal(cube_face_normal(face)).normalized();
 
-            if t1 > t_near {
-                t_near = t1;
-            }
-            if t2 < t_far {
-                t_far = t2;
-            }
+        HitRecord::on_face(
+            self, ray, distance, normal, Some(cube_uv_coordinates(p, self.uv_mapping)), Some(face)
+        )
+    }
+}
 
-            if t_near > t_far || t_far < 0.0 {
-                return;
-            }
+impl MathShape for MathCube {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        for interval in self.intervals(ray) {
+            add_intersection(interval.entry);
+            add_intersection(interval.exit);
         }
-
-        add_intersection(t_near);
-        add_intersection(t_far);
     }
 
-    fn get_normal(&self, surface_point: Vector) -> Vector {
-        // TODO: This could be greatly improved, since we should already know
-        // which surface was intersected.
-
-        let surface_point = self.transformation.reverse_transform_vector(surface_point);
-
-        let planes = [
-            &self.p1,
-            &self.p2,
-            &self.p3,
-            &self.p4,
-            &self.p5,
-            &self.p6,
-        ];
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        let half = Vector::new(self.length, self.length, self.length);
 
-        for plane in planes.iter() {
-            if plane.is_transformed_point_on_surface(surface_point) {
-                return plane.get_normal(surface_point);
-            }
-        }
+        box_crossings(ray.point - self.center, ray.direction, half)
+            .map(|(near, far)| Interval { entry: self.hit_record(&ray, near), exit: self.hit_record(&ray, far) })
+            .into_iter()
+            .collect()
+    }
 
-        // FIXME
-        Vector::new(1.0, 1.0, 1.0)
+    fn get_normal(&self, surface_point: Vector) -> Vector {
+        let face = cube_face(self.local_point(surface_point));
 
-        //panic!("Get normal for MathCube failed!")
+        self.transformation.transform_normal(cube_face_normal(face)).normalized()
     }
 
     fn is_inside(&self, point: Vector) -> bool {
@@ -328,44 +601,229 @@ impl MathShape for MathCube {
     }
 
     fn is_on_surface(&self, point: Vector) -> bool {
-        let point = self.transformation.reverse_transform_vector(point);
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+        let (x, y, z) = (abs(p.x), abs(p.y), abs(p.z));
+        let on_face = |x: f64| abs(x - self.length) < EPSILON;
+
+        x.max(y).max(z) <= self.length + EPSILON && (on_face(x) || on_face(y) || on_face(z))
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        Ok(cube_uv_coordinates(self.local_point(point), self.uv_mapping))
+    }
+
+    fn set_transformation(&mut self, transformation: MatrixTransformation) {
+        self.transformation = transformation;
+    }
+
+    fn get_transformation(&self) -> &MatrixTransformation {
+        &self.transformation
+    }
+
+    fn clone_box(&self) -> Box<dyn MathShape> {
+        Box::new(self.clone())
+    }
+}
+
+/// Angle around the Y axis from 0 to 1, starting at -Z and going through +X
+fn angle_around_y(p: Vector) -> f64 {
+    0.5 + p.x.atan2(-p.z) / (2.0 * PI)
+}
+
+// The faces of a cone, in hit records
+const CONE_SIDE: usize = 0;
+const CONE_BOTTOM: usize = 1;
+const CONE_TOP: usize = 2;
 
-        fn is_between(x: f64, start: f64, end: f64) -> bool {
-            (start..=end).contains(&x)
-        }
-
-        let center = self.center;
-        let length = self.length;
-
-        if is_between(point.y, center.y - length - EPSILON, center.y + length + EPSILON) &&
-            is_between(point.x, center.x - length - EPSILON, center.x + length + EPSILON) &&
-            (self.p1.is_transformed_point_on_surface(point) || self.p6.is_transformed_point_on_surface(point)) {
-            return true;
-        } else if is_between(point.z, center.z - length - EPSILON, center.z + length + EPSILON) &&
-            is_between(point.x, center.x - length - EPSILON, center.x + length + EPSILON) &&
-            (self.p2.is_transformed_point_on_surface(point) || self.p5.is_transformed_point_on_surface(point)) {
-            return true;
-        } else if is_between(point.y, center.y - length - EPSILON, center.y + length + EPSILON) &&
-            is_between(point.z, center.z - length - EPSILON, center.z + length + EPSILON) &&
-            (self.p3.is_transformed_point_on_surface(point) || self.p4.is_transformed_point_on_surface(point)) {
-            return true;
+/// A capped cone around the Y axis, from `radius` at the bottom to
+/// `top_radius` at the top, with `center` halfway up. With both radii equal
+/// it is a cylinder.
+#[derive(Clone)]
+pub struct MathCone {
+    transformation: MatrixTransformation,
+    center: Vector,
+    radius: f64,
+    top_radius: f64,
+    height: f64,
+}
+
+impl MathCone {
+    pub fn new(
+        transformation: MatrixTransformation, center: Vector, radius: f64, top_radius: f64, height: f64,
+    ) -> Self {
+        MathCone { transformation, center, radius, top_radius, height }
+    }
+
+    pub fn new_cylinder(transformation: MatrixTransformation, center: Vector, radius: f64, height: f64) -> Self {
+        MathCone::new(transformation, center, radius, radius, height)
+    }
+
+    /// How much the radius grows going up one unit
+    fn slope(&self) -> f64 {
+        (self.top_radius - self.radius) / self.height
+    }
+
+    /// The radius at height `y` above the center
+    fn radius_at(&self, y: f64) -> f64 {
+        (self.radius + self.top_radius) / 2.0 + self.slope() * y
+    }
+
+    /// Where the ray crosses the side or the caps, in no particular order,
+    /// with the face it crosses
+    fn crossings(&self, ray: &Ray) -> Vec<(f64, usize)> {
+        let o = ray.point - self.center;
+        let d = ray.direction;
+        let half = self.height / 2.0;
+        let slope = self.slope();
+        let middle_radius = self.radius_at(0.0);
+
+        // x² + z² = radius_at(y)², between the caps
+        let radius = middle_radius + slope * o.y;
+        let a = d.x * d.x + d.z * d.z - slope * slope * d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.z * d.z - slope * d.y * radius);
+        let c = o.x * o.x + o.z * o.z - radius * radius;
+
+        let side_roots = if abs(a) < EPSILON {
+            if b != 0.0 { vec![-c / b] } else { vec![] }
         } else {
-            return false;
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant < 0.0 {
+                vec![]
+            } else {
+                vec![(-b - sqrt(discriminant)) / (2.0 * a), (-b + sqrt(discriminant)) / (2.0 * a)]
+            }
+        };
+
+        let mut crossings: Vec<(f64, usize)> = side_roots
+            .into_iter()
+            .filter(|t| {
+                let y = o.y + t * d.y;
+                abs(y) <= half && self.radius_at(y) >= 0.0
+            })
+            .map(|t| (t, CONE_SIDE))
+            .collect();
+
+        if d.y != 0.0 {
+            for &(y, radius, face) in &[(-half, self.radius, CONE_BOTTOM), (half, self.top_radius, CONE_TOP)] {
+                let t = (y - o.y) / d.y;
+                let (x, z) = (o.x + t * d.x, o.z + t * d.z);
+                if radius > 0.0 && x * x + z * z <= radius * radius {
+                    crossings.push((t, face));
+                }
+            }
         }
+
+        crossings
     }
 
-    fn get_uv_coordinates(&self, _point: Vector) -> Result<UV, &'static str> {
-        Err("UV not implemented for MathCube!")
+    /// The face that a point relative to the center is nearest to
+    fn cone_face(&self, p: Vector) -> usize {
+        let (side, cap) = self.surface_distances(p);
+
+        match (cap < side, p.y > 0.0) {
+            (false, _) => CONE_SIDE,
+            (true, false) => CONE_BOTTOM,
+            (true, true) => CONE_TOP,
+        }
     }
 
-    fn set_transformation(&mut self, transformation: MatrixTransformation) {
-        self.p1.set_transformation(transformation.clone());
-        self.p2.set_transformation(transformation.clone());
-        self.p3.set_transformation(transformation.clone());
-        self.p4.set_transformation(transformation.clone());
-        self.p5.set_transformation(transformation.clone());
-        self.p6.set_transformation(transformation.clone());
+    /// The normal on a face, at a point relative to the center
+    fn face_normal(&self, p: Vector, face: usize) -> Vector {
+        let normal = match face {
+            CONE_SIDE => Vector::new(p.x, -self.slope() * self.radius_at(p.y), p.z),
+            CONE_BOTTOM => Vector::new(0.0, -1.0, 0.0),
+            _ => Vector::new(0.0, 1.0, 0.0),
+        };
+
+        self.transformation.transform_normal(normal).normalized()
+    }
+
+    /// Around the side U goes once around and V goes up; the caps show the
+    /// texture as seen from above.
+    fn face_uv_coordinates(&self, p: Vector, face: usize) -> UV {
+        if face == CONE_SIDE {
+            UV {
+                u: angle_around_y(p),
+                v: Color::in_limit(p.y / self.height + 0.5, 0.0, 1.0),
+            }
+        } else {
+            let radius = self.radius.max(self.top_radius);
+            UV {
+                u: Color::in_limit((p.x / radius + 1.0) / 2.0, 0.0, 1.0),
+                v: Color::in_limit((p.z / radius + 1.0) / 2.0, 0.0, 1.0),
+            }
+        }
+    }
+
+    fn hit_record<'a>(&'a self, ray: &Ray, (distance, face): (f64, usize)) -> HitRecord<'a> {
+        let p = ray.point + ray.direction * distance - self.center;
+
+        HitRecord::on_face(
+            self, ray, distance, self.face_normal(p, face), Some(self.face_uv_coordinates(p, face)), Some(face)
+        )
+    }
+
+    /// Distances from a point relative to the center to the side and to the
+    /// nearest cap.
+    fn surface_distances(&self, p: Vector) -> (f64, f64) {
+        let slope = self.slope();
+        let side = abs(sqrt(p.x * p.x + p.z * p.z) - self.radius_at(p.y)) / sqrt(1.0 + slope * slope);
+        let cap = abs(abs(p.y) - self.height / 2.0);
+
+        (side, cap)
+    }
+}
+
+impl MathShape for MathCone {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        for crossing in self.crossings(&ray) {
+            add_intersection(self.hit_record(&ray, crossing));
+        }
+    }
+
+    /// A cone is convex, so the ray is inside from its first crossing to its
+    /// last one; a single crossing only touches an edge.
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        let crossings = self.crossings(&ray);
+        let entry = crossings.iter().copied().min_by(|a, b| a.0.total_cmp(&b.0));
+        let exit = crossings.iter().copied().max_by(|a, b| a.0.total_cmp(&b.0));
+
+        match (entry, exit) {
+            (Some(entry), Some(exit)) if exit.0 - entry.0 > EPSILON => vec![Interval {
+                entry: self.hit_record(&ray, entry),
+                exit: self.hit_record(&ray, exit),
+            }],
+            _ => vec![],
+        }
+    }
+
+    fn get_normal(&self, surface_point: Vector) -> Vector {
+        let p = self.transformation.reverse_transform_vector(surface_point) - self.center;
+
+        self.face_normal(p, self.cone_face(p))
+    }
+
+    fn is_inside(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        abs(p.y) <= self.height / 2.0 + EPSILON &&
+            sqrt(p.x * p.x + p.z * p.z) <= self.radius_at(p.y) + EPSILON
+    }
+
+    fn is_on_surface(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+        let (side, cap) = self.surface_distances(p);
+
+        self.is_inside(point) && (side < EPSILON || cap < EPSILON)
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        Ok(self.face_uv_coordinates(p, self.cone_face(p)))
+    }
 
+    fn set_transformation(&mut self, transformation: MatrixTransformation) {
         self.transformation = transformation;
     }
 
@@ -376,4 +834,736 @@ impl MathShape for MathCube {
     fn clone_box(&self) -> Box<dyn MathShape> {
         Box::new(self.clone())
     }
-}
\ No newline at end of file
+}
+
+/// A ring around the Y axis, `radius` from the center to the middle of the
+/// tube.
+#[derive(Clone)]
+pub struct MathTorus {
+    transformation: MatrixTransformation,
+    center: Vector,
+    radius: f64,
+    tube_radius: f64,
+}
+
+impl MathTorus {
+    pub fn new(transformation: MatrixTransformation, center: Vector, radius: f64, tube_radius: f64) -> Self {
+        MathTorus { transformation, center, radius, tube_radius }
+    }
+
+    /// The point relative to the center, and its distance to the middle of
+    /// the tube.
+    fn tube_distance(&self, point: Vector) -> (Vector, f64) {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+        let ring = sqrt(p.x * p.x + p.z * p.z) - self.radius;
+
+        (p, sqrt(ring * ring + p.y * p.y))
+    }
+
+    fn hit_record<'a>(&'a self, ray: &Ray, distance: f64) -> HitRecord<'a> {
+        let p = ray.point + ray.direction * distance - self.center;
+
+        HitRecord::on_face(
+            self, ray, distance, self.local_normal(p), Some(self.local_uv_coordinates(p)), None
+        )
+    }
+
+    /// The normal in world space, for a point relative to the center
+    fn local_normal(&self, p: Vector) -> Vector {
+        // Away from the nearest point in the middle of the tube
+        let ring = Vector::new(p.x, 0.0, p.z).normalized() * self.radius;
+
+        self.transformation.transform_normal(p - ring).normalized()
+    }
+
+    /// U goes around the Y axis and V around the tube, starting inside.
+    fn local_uv_coordinates(&self, p: Vector) -> UV {
+        let ring = sqrt(p.x * p.x + p.z * p.z) - self.radius;
+
+        UV {
+            u: angle_around_y(p),
+            v: 0.5 + p.y.atan2(-ring) / (2.0 * PI),
+        }
+    }
+}
+
+impl MathTorus {
+    /// Where the ray crosses the surface, in increasing order
+    fn crossings(&self, ray: &Ray) -> Vec<f64> {
+        let scale = 1.0 / ray.direction.length();
+        let d = ray.direction * scale;
+        let o = ray.point - self.center;
+
+        // Only look inside the bounding sphere, starting from where the ray
+        // enters it, which keeps the numbers small.
+        let outer = self.radius + self.tube_radius;
+        let od = o * d;
+        let discriminant = od * od - (o * o - outer * outer);
+        if discriminant < 0.0 {
+            return vec![];
+        }
+        let start = -od - sqrt(discriminant);
+        let end = -od + sqrt(discriminant);
+        let o = o + d * start;
+
+        // (|p|² + R² - r²)² = 4R²(x² + z²), with p = o + t * d and |d| = 1
+        let r2 = self.radius * self.radius;
+        let f = o * d;
+        let k = o * o + r2 - self.tube_radius * self.tube_radius;
+        let coefficients = [
+            1.0,
+            4.0 * f,
+            4.0 * f * f + 2.0 * k - 4.0 * r2 * (d.x * d.x + d.z * d.z),
+            4.0 * f * k - 8.0 * r2 * (o.x * d.x + o.z * d.z),
+            k * k - 4.0 * r2 * (o.x * o.x + o.z * o.z),
+        ];
+
+        polynomial_roots(&coefficients, 0.0, end - start)
+            .into_iter()
+            .map(|t| (t + start) * scale)
+            .collect()
+    }
+}
+
+impl MathShape for MathTorus {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        for t in self.crossings(&ray) {
+            add_intersection(self.hit_record(&ray, t));
+        }
+    }
+
+    /// The ray starts outside, where it enters the bounding sphere, so the
+    /// roots take turns going in and out. A lone last root is a touch whose
+    /// other half was missed.
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        let mut crossings = self.crossings(&ray);
+        crossings.truncate(crossings.len() / 2 * 2);
+
+        crossing_intervals(self, |t| self.hit_record(&ray, t), NEG_INFINITY, INFINITY, false, &crossings)
+    }
+
+    fn get_normal(&self, surface_point: Vector) -> Vector {
+        self.local_normal(self.tube_distance(surface_point).0)
+    }
+
+    fn is_inside(&self, point: Vector) -> bool {
+        self.tube_distance(point).1 <= self.tube_radius + EPSILON
+    }
+
+    fn is_on_surface(&self, point: Vector) -> bool {
+        abs(self.tube_distance(point).1 - self.tube_radius) < EPSILON
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        Ok(self.local_uv_coordinates(self.tube_distance(point).0))
+    }
+
+    fn set_transformation(&mut self, transformation: MatrixTransformation) {
+        self.transformation = transformation;
+    }
+
+    fn get_transformation(&self) -> &MatrixTransformation {
+        &self.transformation
+    }
+
+    fn clone_box(&self) -> Box<dyn MathShape> {
+        Box::new(self.clone())
+    }
+}
+
+/// A flat, round piece of a plane. Like planes, it has no inside.
+#[derive(Clone)]
+pub struct MathDisc {
+    transformation: MatrixTransformation,
+    center: Vector,
+    normal: Vector,
+    radius: f64,
+}
+
+impl MathDisc {
+    pub fn new(transformation: MatrixTransformation, center: Vector, normal: Vector, radius: f64) -> Self {
+        MathDisc { transformation, center, normal: normal.normalized(), radius }
+    }
+
+    /// The disc fills the UV square, oriented like on planes; for a point
+    /// relative to the center.
+    fn local_uv_coordinates(&self, p: Vector) -> UV {
+        let (u_axis, v_axis) = plane_axes(self.normal);
+
+        UV {
+            u: Color::in_limit((p * u_axis / self.radius + 1.0) / 2.0, 0.0, 1.0),
+            v: Color::in_limit((p * v_axis / self.radius + 1.0) / 2.0, 0.0, 1.0),
+        }
+    }
+}
+
+impl MathShape for MathDisc {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        let facing = self.normal * ray.direction;
+        if facing == 0.0 {
+            return;
+        }
+
+        let t = (self.normal * (self.center - ray.point)) / facing;
+        let p = ray.point + ray.direction * t - self.center;
+        if p.length() <= self.radius {
+            let normal = self.transformation.transform_normal(self.normal).normalized();
+            add_intersection(HitRecord::on_face(self, &ray, t, normal, Some(self.local_uv_coordinates(p)), None));
+        }
+    }
+
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        surface_intervals(self, ray)
+    }
+
+    fn get_normal(&self, _surface_point: Vector) -> Vector {
+        self.transformation.transform_normal(self.normal).normalized()
+    }
+
+    fn is_inside(&self, _point: Vector) -> bool {
+        false
+    }
+
+    fn is_on_surface(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        abs(p * self.normal) < EPSILON && p.length() <= self.radius + EPSILON
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        Ok(self.local_uv_coordinates(p))
+    }
+
+    fn set_transformation(&mut self, transformation: MatrixTransformation) {
+        self.transformation = transformation;
+    }
+
+    fn get_transformation(&self) -> &MatrixTransformation {
+        &self.transformation
+    }
+
+    fn clone_box(&self) -> Box<dyn MathShape> {
+        Box::new(self.clone())
+    }
+}
+
+/// A box along the axes, `size` across on each of them.
+#[derive(Clone)]
+pub struct MathBox {
+    transformation: MatrixTransformation,
+    uv_mapping: CubeMapping,
+    center: Vector,
+    size: Vector,
+}
+
+impl MathBox {
+    pub fn new(transformation: MatrixTransformation, center: Vector, size: Vector) -> Self {
+        MathBox { transformation, uv_mapping: CubeMapping::Faces, center, size }
+    }
+
+    pub fn set_uv_mapping(&mut self, uv_mapping: CubeMapping) {
+        self.uv_mapping = uv_mapping;
+    }
+
+    /// The point relative to the center, scaled so that the faces are at -1
+    /// and 1.
+    fn local_point(&self, point: Vector) -> Vector {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        Vector::new(p.x * 2.0 / self.size.x, p.y * 2.0 / self.size.y, p.z * 2.0 / self.size.z)
+    }
+
+    fn hit_record<'a>(&'a self, ray: &Ray, (distance, face): (f64, usize)) -> HitRecord<'a> {
+        let p = ray.point + ray.direction * distance - self.center;
+        let p = Vector::new(p.x * 2.0 / self.size.x, p.y * 2.0 / self.size.y, p.z * 2.0 / self.size.z);
+        let normal = self.transformation.transform_normal(cube_face_normal(face)).normalized();
+
+        HitRecord::on_face(
+            self, ray, distance, normal, Some(cube_uv_coordinates(p, self.uv_mapping)), Some(face)
+        )
+    }
+}
+
+impl MathShape for MathBox {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        for interval in self.intervals(ray) {
+            add_intersection(interval.entry);
+            add_intersection(interval.exit);
+        }
+    }
+
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        let half = self.size * 0.5;
+
+        box_crossings(ray.point - self.center, ray.direction, half)
+            .map(|(near, far)| Interval { entry: self.hit_record(&ray, near), exit: self.hit_record(&ray, far) })
+            .into_iter()
+            .collect()
+    }
+
+    fn get_normal(&self, surface_point: Vector) -> Vector {
+        let face = cube_face(self.local_point(surface_point));
+
+        self.transformation.transform_normal(cube_face_normal(face)).normalized()
+    }
+
+    fn is_inside(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        abs(p.x) <= self.size.x / 2.0 + EPSILON &&
+            abs(p.y) <= self.size.y / 2.0 + EPSILON &&
+            abs(p.z) <= self.size.z / 2.0 + EPSILON
+    }
+
+    fn is_on_surface(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+        let on_face = |x: f64, size: f64| abs(abs(x) - size / 2.0) < EPSILON;
+
+        self.is_inside(point) &&
+            (on_face(p.x, self.size.x) || on_face(p.y, self.size.y) || on_face(p.z, self.size.z))
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        Ok(cube_uv_coordinates(self.local_point(point), self.uv_mapping))
+    }
+
+    fn set_transformation(&mut self, transformation: MatrixTransformation) {
+        self.transformation = transformation;
+    }
+
+    fn get_transformation(&self) -> &MatrixTransformation {
+        &self.transformation
+    }
+
+    fn clone_box(&self) -> Box<dyn MathShape> {
+        Box::new(self.clone())
+    }
+}
+
+/// Latitude and longitude around the center, for shapes without a better
+/// way to wrap a texture around them.
+pub fn spherical_uv_coordinates(p: Vector) -> UV {
+    let length = p.length();
+    let y = if length > 0.0 { p.y / length } else { 0.0 };
+
+    UV {
+        u: angle_around_y(p),
+        v: 0.5 + Color::in_limit(y, -1.0, 1.0).asin() / PI,
+    }
+}
+
+/// The surface where
+///
+/// ```text
+/// xx·x² + yy·y² + zz·z² + xy·xy + xz·xz + yz·yz + x·x + y·y + z·z + constant = 0
+/// ```
+///
+/// with the point relative to the center, and the coefficients in that order.
+/// The inside is where the sum is negative. Paraboloids and hyperboloids go
+/// on forever; intersecting them with a box in a CSG cuts them to size.
+#[derive(Clone)]
+pub struct MathQuadric {
+    transformation: MatrixTransformation,
+    center: Vector,
+    coefficients: [f64; 10],
+}
+
+impl MathQuadric {
+    pub fn new(transformation: MatrixTransformation, center: Vector, coefficients: [f64; 10]) -> Self {
+        MathQuadric { transformation, center, coefficients }
+    }
+
+    fn evaluate(&self, p: Vector) -> f64 {
+        let [xx, yy, zz, xy, xz, yz, x, y, z, constant] = self.coefficients;
+
+        xx * p.x * p.x + yy * p.y * p.y + zz * p.z * p.z +
+            xy * p.x * p.y + xz * p.x * p.z + yz * p.y * p.z +
+            x * p.x + y * p.y + z * p.z + constant
+    }
+
+    fn gradient(&self, p: Vector) -> Vector {
+        let [xx, yy, zz, xy, xz, yz, x, y, z, _] = self.coefficients;
+
+        Vector::new(
+            2.0 * xx * p.x + xy * p.y + xz * p.z + x,
+            2.0 * yy * p.y + xy * p.x + yz * p.z + y,
+            2.0 * zz * p.z + xz * p.x + yz * p.y + z,
+        )
+    }
+
+    fn hit_record<'a>(&'a self, ray: &Ray, distance: f64) -> HitRecord<'a> {
+        let p = ray.point + ray.direction * distance - self.center;
+        let normal = self.transformation.transform_normal(self.gradient(p)).normalized();
+
+        HitRecord::on_face(self, ray, distance, normal, Some(spherical_uv_coordinates(p)), None)
+    }
+}
+
+impl MathQuadric {
+    /// Where the ray crosses the surface, in increasing order, and whether
+    /// it is inside far behind its start.
+    fn crossings(&self, ray: &Ray) -> (Vec<f64>, bool) {
+        let [xx, yy, zz, xy, xz, yz, x, y, z, _] = self.coefficients;
+        let o = ray.point - self.center;
+        let d = ray.direction;
+
+        // Putting o + t * d into the sum gives a·t² + b·t + c
+        let a = xx * d.x * d.x + yy * d.y * d.y + zz * d.z * d.z +
+            xy * d.x * d.y + xz * d.x * d.z + yz * d.y * d.z;
+        let b = 2.0 * (xx * o.x * d.x + yy * o.y * d.y + zz * o.z * d.z) +
+            xy * (o.x * d.y + o.y * d.x) + xz * (o.x * d.z + o.z * d.x) + yz * (o.y * d.z + o.z * d.y) +
+            x * d.x + y * d.y + z * d.z;
+        let c = self.evaluate(o);
+
+        // Along a line, the sum is b·t + c; otherwise the sign of a is the
+        // sign of the sum far away on both sides
+        if abs(a) < EPSILON {
+            return if b != 0.0 { (vec![-c / b], b > 0.0) } else { (vec![], c <= 0.0) };
+        }
+
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return (vec![], a < 0.0);
+        }
+
+        let first = (-b - sqrt(discriminant)) / (2.0 * a);
+        let second = (-b + sqrt(discriminant)) / (2.0 * a);
+
+        (vec![first.min(second), first.max(second)], a < 0.0)
+    }
+}
+
+impl MathShape for MathQuadric {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        for t in self.crossings(&ray).0 {
+            add_intersection(self.hit_record(&ray, t));
+        }
+    }
+
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        let (crossings, inside) = self.crossings(&ray);
+
+        crossing_intervals(self, |t| self.hit_record(&ray, t), NEG_INFINITY, INFINITY, inside, &crossings)
+    }
+
+    fn get_normal(&self, surface_point: Vector) -> Vector {
+        let p = self.transformation.reverse_transform_vector(surface_point) - self.center;
+
+        self.transformation.transform_normal(self.gradient(p)).normalized()
+    }
+
+    fn is_inside(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        self.evaluate(p) <= EPSILON
+    }
+
+    fn is_on_surface(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        // The value divided by the slope is about the distance to the surface
+        abs(self.evaluate(p)) <= EPSILON * self.gradient(p).length().max(1.0)
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        Ok(spherical_uv_coordinates(p))
+    }
+
+    fn set_transformation(&mut self, transformation: MatrixTransformation) {
+        self.transformation = transformation;
+    }
+
+    fn get_transformation(&self) -> &MatrixTransformation {
+        &self.transformation
+    }
+
+    fn clone_box(&self) -> Box<dyn MathShape> {
+        Box::new(self.clone())
+    }
+}
+
+/// A function of a point that is negative inside a shape, zero on its surface
+/// and positive outside.
+pub type ImplicitFunction = Arc<dyn Fn(Vector) -> f64 + Send + Sync>;
+
+// How far apart the points are when looking for the surface of implicit
+// shapes, as a fraction of the bounding radius
+const IMPLICIT_MIN_STEP: f64 = 1.0 / 512.0;
+const IMPLICIT_MAX_STEP: f64 = 1.0 / 16.0;
+
+/// The surface where a function of the point relative to the center is
+/// zero. It is found by stepping along the ray as far as the value and its
+/// slope tell is safe, so sharp features can be missed. The surface must fit
+/// in the sphere of the given radius around the center; the shape is cut off
+/// there.
+#[derive(Clone)]
+pub struct MathImplicit {
+    transformation: MatrixTransformation,
+    center: Vector,
+    radius: f64,
+    function: ImplicitFunction,
+}
+
+impl MathImplicit {
+    pub fn new(
+        transformation: MatrixTransformation, center: Vector, radius: f64, function: ImplicitFunction,
+    ) -> Self {
+        MathImplicit { transformation, center, radius, function }
+    }
+
+    fn gradient(&self, p: Vector) -> Vector {
+        let h = self.radius * 1e-5;
+        let f = &self.function;
+
+        Vector::new(
+            f(p + Vector::new(h, 0.0, 0.0)) - f(p - Vector::new(h, 0.0, 0.0)),
+            f(p + Vector::new(0.0, h, 0.0)) - f(p - Vector::new(0.0, h, 0.0)),
+            f(p + Vector::new(0.0, 0.0, h)) - f(p - Vector::new(0.0, 0.0, h)),
+        ) * (1.0 / (2.0 * h))
+    }
+
+    fn hit_record<'a>(&'a self, ray: &Ray, distance: f64) -> HitRecord<'a> {
+        let p = ray.point + ray.direction * distance - self.center;
+        let normal = self.transformation.transform_normal(self.gradient(p)).normalized();
+
+        HitRecord::on_face(self, ray, distance, normal, Some(spherical_uv_coordinates(p)), None)
+    }
+}
+
+impl MathImplicit {
+    /// Where the ray enters and leaves the bounding sphere, whether it is
+    /// inside the shape where it enters, and where it crosses the surface in
+    /// between, in increasing order.
+    fn march(&self, ray: &Ray) -> Option<(f64, f64, bool, Vec<f64>)> {
+        let f = &self.function;
+        let o = ray.point - self.center;
+        let d = ray.direction;
+        let speed = d.length();
+
+        // Only look inside the bounding sphere
+        let od = o * d;
+        let discriminant = od * od - (d * d) * (o * o - self.radius * self.radius);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let start = (-od - sqrt(discriminant)) / (d * d);
+        let end = (-od + sqrt(discriminant)) / (d * d);
+
+        let min_step = self.radius * IMPLICIT_MIN_STEP / speed;
+        let max_step = self.radius * IMPLICIT_MAX_STEP / speed;
+
+        let mut t = start;
+        let mut value = f(o + d * t);
+        let inside = value <= 0.0;
+        let mut crossings = vec![];
+
+        while t < end {
+            // How far the surface is at least, if the slope doesn't change much
+            let slope = self.gradient(o + d * t).length();
+            let step = if slope > 0.0 { 0.8 * abs(value) / slope / speed } else { max_step };
+            let next_t = (t + step.max(min_step).min(max_step)).min(end);
+            let next_value = f(o + d * next_t);
+
+            if (value <= 0.0) != (next_value <= 0.0) {
+                // Narrow it down between the two points
+                let (mut low, mut high) = (t, next_t);
+                for _ in 0..40 {
+                    let middle = (low + high) / 2.0;
+                    if (f(o + d * middle) <= 0.0) == (value <= 0.0) {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                crossings.push((low + high) / 2.0);
+            }
+
+            t = next_t;
+            value = next_value;
+        }
+
+        Some((start, end, inside, crossings))
+    }
+}
+
+impl MathShape for MathImplicit {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        if let Some((_, _, _, crossings)) = self.march(&ray) {
+            for t in crossings {
+                add_intersection(self.hit_record(&ray, t));
+            }
+        }
+    }
+
+    /// Cut off by the bounding sphere
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        match self.march(&ray) {
+            Some((start, end, inside, crossings)) => {
+                crossing_intervals(self, |t| self.hit_record(&ray, t), start, end, inside, &crossings)
+            }
+            None => vec![],
+        }
+    }
+
+    fn get_normal(&self, surface_point: Vector) -> Vector {
+        let p = self.transformation.reverse_transform_vector(surface_point) - self.center;
+
+        self.transformation.transform_normal(self.gradient(p)).normalized()
+    }
+
+    fn is_inside(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        p.length() <= self.radius && (self.function)(p) <= EPSILON
+    }
+
+    fn is_on_surface(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        // The value divided by the slope is about the distance to the surface
+        p.length() <= self.radius &&
+            abs((self.function)(p)) <= EPSILON * self.gradient(p).length().max(1.0)
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        let p = self.transformation.reverse_transform_vector(point) - self.center;
+
+        Ok(spherical_uv_coordinates(p))
+    }
+
+    fn set_transformation(&mut self, transformation: MatrixTransformation) {
+        self.transformation = transformation;
+    }
+
+    fn get_transformation(&self) -> &MatrixTransformation {
+        &self.transformation
+    }
+
+    fn clone_box(&self) -> Box<dyn MathShape> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sphere squashed like the stand in globes.scene. On the ellipsoid
+    /// x² + (y / 0.1)² + z² = 1 the normal points along (x, y / 0.01, z).
+    #[test]
+    fn squashed_sphere_normals() {
+        let transformation = MatrixTransformation::create_scaling_matrix(1.0, 0.1, 1.0);
+        let sphere = MathSphere::new(transformation, Vector::new(0.0, 0.0, 0.0), 1.0);
+        let object = RTObject::new_default(Box::new(sphere));
+
+        for &(x, z) in [(0.0, 0.0), (0.5, 0.0), (0.3, -0.6), (-0.7, 0.2), (0.0, 0.95)].iter() {
+            // Straight down onto the top, like a camera looking at it
+            let ray = Ray { point: Vector::new(x, 10.0, z), direction: Vector::new(0.0, -1.0, 0.0) };
+            let hit = object.intervals(ray.clone())[0].entry;
+            let point = ray.point + ray.direction * hit.distance;
+
+            assert!(abs(point.y - 0.1 * sqrt(1.0 - x * x - z * z)) < 1e-9, "missed the top at {:?}", point);
+
+            let expected = Vector::new(point.x, point.y / 0.01, point.z).normalized();
+            let normal = hit.normal.normalized();
+            assert!((normal - expected).length() < 1e-9, "normal at {:?} was {:?}, expected {:?}", point, normal, expected);
+            assert!((object.get_shape().get_normal(point) - expected).length() < 1e-9);
+            assert!(hit.front_face);
+        }
+    }
+
+    fn spans(shape: &dyn MathShape, ray: Ray) -> Vec<(f64, f64)> {
+        shape.intervals(ray)
+            .iter()
+            .map(|interval| ((interval.entry.distance * 1e6).round() / 1e6, (interval.exit.distance * 1e6).round() / 1e6))
+            .collect()
+    }
+
+    #[test]
+    fn torus_intervals() {
+        let identity = MatrixTransformation::create_identity_matrix();
+        let torus = MathTorus::new(identity, Vector::new(0.0, 0.0, 0.0), 2.0, 0.5);
+
+        let across = Ray { point: Vector::new(-10.0, 0.0, 0.0), direction: Vector::new(1.0, 0.0, 0.0) };
+        assert_eq!(spans(&torus, across), [(7.5, 8.5), (11.5, 12.5)]);
+
+        let down = Ray { point: Vector::new(2.0, 10.0, 0.0), direction: Vector::new(0.0, -2.0, 0.0) };
+        assert_eq!(spans(&torus, down), [(4.75, 5.25)]);
+    }
+
+    /// Which side is inside depends on the sign of the square term along the
+    /// ray
+    #[test]
+    fn quadric_intervals() {
+        let identity = MatrixTransformation::create_identity_matrix();
+        let origin = Vector::new(0.0, 0.0, 0.0);
+        let ray = Ray { point: Vector::new(0.0, -10.0, 0.0), direction: Vector::new(0.0, 1.0, 0.0) };
+
+        // x² + y² + z² - 4
+        let sphere = MathQuadric::new(identity.clone(), origin, [1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -4.0]);
+        assert_eq!(spans(&sphere, ray.clone()), [(8.0, 12.0)]);
+
+        // The two sheets of x² - y² + z² + 1, opening up and down
+        let sheets = MathQuadric::new(identity.clone(), origin, [1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(spans(&sheets, ray.clone()), [(NEG_INFINITY, 9.0), (11.0, INFINITY)]);
+
+        // A paraboloid x² + z² - y, crossed once along its axis
+        let paraboloid = MathQuadric::new(identity.clone(), origin, [1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0]);
+        assert_eq!(spans(&paraboloid, ray.clone()), [(10.0, INFINITY)]);
+
+        // Missing a cylinder x² + z² - 1 that the ray runs alongside
+        let cylinder = MathQuadric::new(identity, origin, [1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0]);
+        let outside = Ray { point: Vector::new(2.0, 0.0, 0.0), ..ray };
+        assert_eq!(spans(&cylinder, outside), []);
+        assert_eq!(spans(&cylinder, ray), [(NEG_INFINITY, INFINITY)]);
+    }
+
+    /// Right at the rim, the side and the cap are both about as near, so
+    /// the hits keep the face the ray actually crossed.
+    #[test]
+    fn cone_hits_know_their_face() {
+        let cylinder = MathCone::new_cylinder(MatrixTransformation::create_identity_matrix(), Vector::new(0.0, 0.0, 0.0), 1.0, 2.0);
+        let rim = 1.0 - 1e-12;
+
+        let down = Ray { point: Vector::new(rim, 5.0, 0.0), direction: Vector::new(0.0, -1.0, 0.0) };
+        let interval = cylinder.intervals(down)[0];
+        assert_eq!((interval.entry.face, interval.exit.face), (Some(CONE_TOP), Some(CONE_BOTTOM)));
+        assert_eq!(interval.entry.normal.y, 1.0);
+
+        let across = Ray { point: Vector::new(-5.0, rim, 0.0), direction: Vector::new(1.0, 0.0, 0.0) };
+        let interval = cylinder.intervals(across)[0];
+        assert_eq!((interval.entry.face, interval.exit.face), (Some(CONE_SIDE), Some(CONE_SIDE)));
+        assert_eq!((interval.entry.normal.x, interval.exit.normal.x), (-1.0, 1.0));
+
+        // In through the side and out of the top
+        let corner = Ray { point: Vector::new(-2.0, 0.0, 0.0), direction: Vector::new(1.0, 0.5, 0.0) };
+        let interval = cylinder.intervals(corner)[0];
+        assert_eq!(interval.entry.face, Some(CONE_SIDE));
+        assert_eq!(interval.entry.normal.x, -1.0);
+        assert_eq!(interval.exit.face, Some(CONE_TOP));
+        assert_eq!(interval.exit.normal.y, 1.0);
+    }
+
+    /// Hits are worked out in the shape's own space, and agree with what the
+    /// shape says about the same point in the world
+    #[test]
+    fn hits_carry_their_local_point() {
+        let transformation = MatrixTransformation::create_translation_matrix(5.0, 0.0, 0.0);
+        let torus = MathTorus::new(transformation, Vector::new(0.0, 1.0, 0.0), 2.0, 0.5);
+        let object = RTObject::new_default(Box::new(torus));
+
+        let ray = Ray { point: Vector::new(7.0, 10.0, 0.3), direction: Vector::new(0.0, -1.0, 0.0) };
+        let hit = object.intervals(ray.clone())[0].entry;
+        let point = ray.point + ray.direction * hit.distance;
+        let shape = object.get_shape();
+
+        assert!((hit.local_point - Vector::new(2.0, point.y, 0.3)).length() < 1e-9);
+        assert!((hit.normal - shape.get_normal(point)).length() < 1e-9);
+        let uv = shape.get_uv_coordinates(point).unwrap();
+        assert!(abs(hit.uv.unwrap().u - uv.u) < 1e-9 && abs(hit.uv.unwrap().v - uv.v) < 1e-9);
+    }
+}
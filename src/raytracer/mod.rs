@@ -2,6 +2,12 @@ pub mod math;
 pub mod vector;
 pub mod color;
 pub mod texture;
+pub mod noise;
+pub mod procedural_texture;
+pub mod background;
+pub mod animation;
+pub mod environment_light;
+pub mod quaternion;
 pub mod transformation;
 pub mod point_light;
 pub mod material;
@@ -11,3 +17,4 @@ pub mod camera;
 pub mod raytracer;
 pub mod antialiaser;
 pub mod csg;
+pub mod sdf;
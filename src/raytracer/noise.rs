@@ -0,0 +1,95 @@
+use super::vector::Vector;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+// Ken Perlin's "improved noise", with the permutation table shuffled from a
+// seed so that different textures don't have to look alike.
+
+#[derive(Clone)]
+pub struct Perlin {
+    permutation: Vec<u8>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut permutation: Vec<u8> = (0..=255).collect();
+        permutation.shuffle(&mut StdRng::seed_from_u64(seed));
+
+        // Repeated, so that lookups don't need to wrap
+        let repeated = permutation.clone();
+        permutation.extend(repeated);
+
+        Perlin { permutation }
+    }
+
+    /// Smooth noise in about -1..1, which is 0 at every integer point.
+    pub fn noise(&self, point: Vector) -> f64 {
+        let (x, y, z) = (point.x.floor(), point.y.floor(), point.z.floor());
+
+        // Position inside the unit cube
+        let (fx, fy, fz) = (point.x - x, point.y - y, point.z - z);
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+
+        let p = &self.permutation;
+        let (x, y, z) = ((x as i64 & 255) as usize, (y as i64 & 255) as usize, (z as i64 & 255) as usize);
+
+        let a = p[x] as usize + y;
+        let aa = p[a] as usize + z;
+        let ab = p[a + 1] as usize + z;
+        let b = p[x + 1] as usize + y;
+        let ba = p[b] as usize + z;
+        let bb = p[b + 1] as usize + z;
+
+        lerp(w,
+            lerp(v,
+                lerp(u, grad(p[aa], fx, fy, fz), grad(p[ba], fx - 1.0, fy, fz)),
+                lerp(u, grad(p[ab], fx, fy - 1.0, fz), grad(p[bb], fx - 1.0, fy - 1.0, fz)),
+            ),
+            lerp(v,
+                lerp(u, grad(p[aa + 1], fx, fy, fz - 1.0), grad(p[ba + 1], fx - 1.0, fy, fz - 1.0)),
+                lerp(u, grad(p[ab + 1], fx, fy - 1.0, fz - 1.0), grad(p[bb + 1], fx - 1.0, fy - 1.0, fz - 1.0)),
+            ),
+        )
+    }
+
+    /// Sum of `octaves` layers of noise, each twice as detailed and half as
+    /// strong as the previous one. Always positive.
+    pub fn turbulence(&self, point: Vector, octaves: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut weight = 1.0;
+        let mut point = point;
+
+        for _ in 0..octaves {
+            sum += weight * self.noise(point).abs();
+            weight *= 0.5;
+            point = point * 2.0;
+        }
+
+        sum
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Dot product with one of 12 gradient directions, picked by the hash
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
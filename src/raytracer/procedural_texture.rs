@@ -0,0 +1,105 @@
+use super::color::Color;
+use super::math::{PI, sin, sqrt};
+use super::noise::Perlin;
+use super::texture::Texture;
+use super::vector::{Vector, UV};
+
+// Textures computed from a formula instead of read from an image. Each one is
+// defined over all of space; on a surface with UV coordinates it is sampled
+// on the (u, v, 0) plane, where 0..1 is one repetition of the pattern.
+
+#[derive(Clone)]
+pub enum Pattern {
+    /// `count` squares along every axis, alternating colors
+    Checker { count: f64 },
+    /// `count` stripes along X, alternating colors
+    Stripes { count: f64 },
+    /// From the first color at X = 0 to the second at X = 1
+    LinearGradient,
+    /// From the first color at the center of the UV square to the second at
+    /// its edges
+    RadialGradient,
+    /// Perlin noise; higher scales have smaller features
+    Noise { scale: f64, perlin: Perlin },
+    /// Stripes along X, bent by turbulence
+    Marble { scale: f64, perlin: Perlin },
+    /// Rings around the Z axis through the center of the UV square, bent by
+    /// turbulence
+    Wood { rings: f64, perlin: Perlin },
+}
+
+#[derive(Clone)]
+pub struct ProceduralTexture {
+    pattern: Pattern,
+    a: Color,
+    b: Color,
+}
+
+impl ProceduralTexture {
+    pub fn new(pattern: Pattern, a: Color, b: Color) -> Self {
+        ProceduralTexture { pattern, a, b }
+    }
+
+    /// How much of the second color there is at `point`, from 0 to 1
+    fn amount_at(&self, point: Vector) -> f64 {
+        match &self.pattern {
+            Pattern::Checker { count } => {
+                let cell = (point.x * count).floor()
+                    + (point.y * count).floor()
+                    + (point.z * count).floor();
+
+                (cell as i64).rem_euclid(2) as f64
+            }
+            Pattern::Stripes { count } => {
+                ((point.x * count).floor() as i64).rem_euclid(2) as f64
+            }
+            Pattern::LinearGradient => point.x,
+            Pattern::RadialGradient => {
+                let x = point.x - 0.5;
+                let y = point.y - 0.5;
+
+                sqrt(x * x + y * y + point.z * point.z) * 2.0
+            }
+            Pattern::Noise { scale, perlin } => {
+                (perlin.noise(point * *scale) + 1.0) / 2.0
+            }
+            Pattern::Marble { scale, perlin } => {
+                let turbulence = perlin.turbulence(point * 2.0, 6);
+
+                (1.0 + sin((point.x * scale + turbulence * 2.0) * PI)) / 2.0
+            }
+            Pattern::Wood { rings, perlin } => {
+                let x = point.x - 0.5;
+                let y = point.y - 0.5;
+                let turbulence = perlin.turbulence(point * 4.0, 4);
+
+                (sqrt(x * x + y * y) * rings + turbulence * 0.5).fract()
+            }
+        }
+    }
+
+    fn color_at(&self, point: Vector) -> Color {
+        let t = Color::in_limit(self.amount_at(point), 0.0, 1.0);
+
+        Color::new(
+            self.a.r + (self.b.r - self.a.r) * t,
+            self.a.g + (self.b.g - self.a.g) * t,
+            self.a.b + (self.b.b - self.a.b) * t,
+            self.a.a + (self.b.a - self.a.a) * t,
+        )
+    }
+}
+
+impl Texture for ProceduralTexture {
+    fn get_color_at(&self, uv_coordinates: UV) -> Color {
+        self.color_at(Vector::new(uv_coordinates.u, uv_coordinates.v, 0.0))
+    }
+
+    fn get_color_at_point(&self, point: Vector) -> Option<Color> {
+        Some(self.color_at(point))
+    }
+
+    fn clone_box(&self) -> Box<dyn Texture> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,179 @@
+// Rotations as unit quaternions, which compose and interpolate without the
+// gimbal lock of Euler angles.
+
+use super::math::{acos, cos, sin, sqrt};
+use super::vector::Vector;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Quaternion { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Turning by `angle` around `axis`, counterclockwise when looking
+    /// against the direction it points in.
+    pub fn from_axis_angle(axis: Vector, angle: f64) -> Self {
+        let axis = axis.normalized() * sin(angle / 2.0);
+
+        Quaternion::new(cos(angle / 2.0), axis.x, axis.y, axis.z)
+    }
+
+    /// The rotation of a matrix without scaling or shear, with the largest
+    /// of w, x, y and z found first so that nothing is divided by almost 0.
+    pub fn from_rotation_matrix(m: [[f64; 3]; 3]) -> Self {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        let quaternion = if trace > 0.0 {
+            let s = sqrt(trace + 1.0) * 2.0;
+            Quaternion::new(0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = sqrt(1.0 + m[0][0] - m[1][1] - m[2][2]) * 2.0;
+            Quaternion::new((m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = sqrt(1.0 + m[1][1] - m[0][0] - m[2][2]) * 2.0;
+            Quaternion::new((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = sqrt(1.0 + m[2][2] - m[0][0] - m[1][1]) * 2.0;
+            Quaternion::new((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s)
+        };
+
+        quaternion.normalized()
+    }
+
+    pub fn dot(a: Quaternion, b: Quaternion) -> f64 {
+        a.w * b.w + a.x * b.x + a.y * b.y + a.z * b.z
+    }
+
+    pub fn length(self) -> f64 {
+        sqrt(Quaternion::dot(self, self))
+    }
+
+    pub fn normalized(self) -> Quaternion {
+        self * (1.0 / self.length())
+    }
+
+    /// The opposite rotation, for unit quaternions
+    pub fn conjugate(self) -> Quaternion {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn rotate_vector(self, vector: Vector) -> Vector {
+        let rotated = self * Quaternion::new(0.0, vector.x, vector.y, vector.z) * self.conjugate();
+
+        Vector::new(rotated.x, rotated.y, rotated.z)
+    }
+
+    pub fn to_rotation_matrix(self) -> [[f64; 3]; 3] {
+        let Quaternion { w, x, y, z } = self.normalized();
+
+        [
+            [ 1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y) ],
+            [ 2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x) ],
+            [ 2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y) ],
+        ]
+    }
+
+    /// Turns from `a` at t = 0 to `b` at t = 1 at a constant speed, the
+    /// shortest way around.
+    pub fn slerp(a: Quaternion, b: Quaternion, t: f64) -> Quaternion {
+        let mut b = b;
+        let mut dot = Quaternion::dot(a, b);
+        // q and -q are the same rotation, but only one of them is close by
+        if dot < 0.0 {
+            b = b * -1.0;
+            dot = -dot;
+        }
+
+        // Almost the same rotation, where sin(angle) is too small to divide by
+        if dot > 0.9995 {
+            return (a * (1.0 - t) + b * t).normalized();
+        }
+
+        let angle = acos(dot);
+
+        (a * sin((1.0 - t) * angle) + b * sin(t * angle)) * (1.0 / sin(angle))
+    }
+}
+
+/// Rotating by `rhs` first, then by `self`
+impl std::ops::Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        )
+    }
+}
+
+impl std::ops::Mul<f64> for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: f64) -> Quaternion {
+        Quaternion::new(self.w * rhs, self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl std::ops::Add for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, rhs: Quaternion) -> Quaternion {
+        Quaternion::new(self.w + rhs.w, self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same_rotation(a: Quaternion, b: Quaternion) {
+        // q and -q are the same rotation
+        assert!(1.0 - Quaternion::dot(a, b).abs() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn slerp_turns_at_a_constant_speed() {
+        let axis = Vector::new(1.0, -1.0, 2.0);
+        let a = Quaternion::from_axis_angle(axis, 0.2);
+        let b = Quaternion::from_axis_angle(axis, 1.8);
+
+        assert_same_rotation(Quaternion::slerp(a, b, 0.0), a);
+        assert_same_rotation(Quaternion::slerp(a, b, 1.0), b);
+        assert_same_rotation(Quaternion::slerp(a, b, 0.25), Quaternion::from_axis_angle(axis, 0.6));
+        assert_same_rotation(Quaternion::slerp(a, b, 0.5), Quaternion::from_axis_angle(axis, 1.0));
+    }
+
+    /// From 350° to 10° goes through 0°, not back through 180°
+    #[test]
+    fn slerp_takes_the_short_way() {
+        let axis = Vector::new(0.0, 1.0, 0.0);
+        let degree = std::f64::consts::PI / 180.0;
+        let a = Quaternion::from_axis_angle(axis, 350.0 * degree);
+        let b = Quaternion::from_axis_angle(axis, 10.0 * degree);
+
+        assert_same_rotation(Quaternion::slerp(a, b, 0.5), Quaternion::identity());
+    }
+
+    #[test]
+    fn rotation_matrix_round_trip() {
+        for &(x, y, z, angle) in [(1.0, 0.0, 0.0, 0.5), (0.0, 1.0, 1.0, 3.0), (-1.0, 2.0, 0.5, -2.5)].iter() {
+            let rotation = Quaternion::from_axis_angle(Vector::new(x, y, z), angle);
+
+            assert_same_rotation(Quaternion::from_rotation_matrix(rotation.to_rotation_matrix()), rotation);
+        }
+    }
+}
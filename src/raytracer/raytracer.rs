@@ -1,9 +1,13 @@
 use super::color::Color;
 use super::vector::{Vector, UV, Ray};
 use super::rt_object::RTObject;
+use super::math_shapes::{MathShape, HitRecord};
 use super::camera::{Camera, PerspectiveCamera};
-use super::transformation::{TransformationStack, MatrixTransformation};
+use super::transformation::{TransformationStack, MatrixTransformation, Transformation};
 use super::point_light::PointLight;
+use super::background::Background;
+use super::animation::Animation;
+use super::environment_light::EnvironmentLight;
 use super::math::{PI, INFINITY, EPSILON, sqrt};
 
 #[derive(Clone, Copy)]
@@ -13,9 +17,9 @@ pub enum RayType {
     TransmissionRay,
 }
 
-// Used by debuggers to show info about each ray
+// Used by debuggers to show info about each ray, with what it hit
 pub type RayDebuggerCallback<'a, 'b> = &'a mut Option<&'b mut dyn FnMut(
-    i32, Ray, f64, Option<&RTObject>, &Color, &RayType
+    i32, Ray, f64, Option<&HitRecord>, &Color, &RayType
 )>;
 
 #[derive(Clone)]
@@ -32,6 +36,9 @@ pub struct RayTracer {
 
     objects: Vec<RTObject>,
     point_lights: Vec<PointLight>,
+    background: Background,
+    environment_light: Option<EnvironmentLight>,
+    animation: Animation,
 }
 
 impl RayTracer {
@@ -66,6 +73,9 @@ impl RayTracer {
 
             objects: vec![],
             point_lights: vec![],
+            background: Background::Color(Color::BLACK),
+            environment_light: None,
+            animation: Animation::default(),
         }
     }
 
@@ -135,73 +145,76 @@ impl RayTracer {
     ) -> Color {
         let ray_type = ray_type.unwrap_or(RayType::NormalRay);
 
-        let mut nearest_distance = INFINITY;
-        let mut nearest_object = None;
+        let mut nearest: Option<(&RTObject, HitRecord)> = None;
 
         for obj in self.objects.iter() {
-            let mut add_intersection = |d: f64| {
-                if d > EPSILON && d < nearest_distance {
-                    nearest_distance = d;
-                    nearest_object = Some(obj);
+            for interval in obj.intervals(ray.clone()) {
+                // The ends of shapes that go on forever can't be seen
+                for hit in [interval.entry, interval.exit].iter().filter(|hit| hit.distance.is_finite()) {
+                    if hit.distance > EPSILON && nearest.is_none_or(|(_, nearest)| hit.distance < nearest.distance) {
+                        nearest = Some((obj, *hit));
+                    }
                 }
-            };
-
-            obj.intersects(ray.clone(), &mut add_intersection);
+            }
         }
 
-        let rt_object = match nearest_object {
-            Some(rt_object) => rt_object,
+        let (rt_object, hit) = match nearest {
+            Some(nearest) => nearest,
             None => {
+                let color = self.background.get_color(ray.direction);
                 if let Some(debugger) = ray_debugger_callback {
-                    debugger(depth, ray, INFINITY, None, &Color::BLACK, &ray_type);
+                    debugger(depth, ray, INFINITY, None, &color, &ray_type);
                 }
-                return Color::BLACK;
+                return color;
             }
         };
 
+        let nearest_distance = hit.distance;
         let point = ray.point + ray.direction * nearest_distance;
-        let normal = rt_object.get_shape().get_normal(point).normalized();
+        let normal = hit.normal.normalized();
+        let uv_coord = hit.uv.unwrap_or(UV { u: 0.0, v: 0.0 });
+
+        // A CSG can keep the materials of its parts
+        let rt_object = hit.object.unwrap_or(rt_object);
+
+        let normal = Self::get_mapped_normal(rt_object, hit.shape, point, normal, uv_coord);
+
+        let c = match rt_object.get_material().get_color_at_point(hit.local_point) {
+            Some(color) => color,
+            None => {
+                let footprint = self.get_uv_footprint(
+                    hit.shape, point, normal, nearest_distance * ray.direction.length(), uv_coord
+                );
+                rt_object.get_material().get_color_at_uv_footprint(uv_coord, footprint)
+            }
+        };
 
-        let uv_coord = rt_object
-            .get_shape()
-            .get_uv_coordinates(point)
-            .unwrap_or(UV { u: 0.0, v: 0.0 });
+        let ambient = match &self.environment_light {
+            Some(environment_light) => {
+                // The side facing the viewer, like the light below
+                let facing_normal = if hit.front_face { normal } else { normal * -1.0 };
 
-        let c = rt_object.get_material().get_color_at_uv(uv_coord);
+                let irradiance = environment_light.get_irradiance(facing_normal, |direction| {
+                    self.get_shadow_transparency(point, direction, INFINITY)
+                });
 
-        let ambient = c * Color::in_range(1.0, 1.0, 1.0).intensify(0.6);
+                c * Color::in_range(irradiance.r, irradiance.g, irradiance.b)
+            }
+            None => c * Color::in_range(1.0, 1.0, 1.0).intensify(0.6),
+        };
         let mut final_light = ambient;
 
         for light in self.point_lights.iter() {
-            let shadow_ray = Ray {
-                point,
-                direction: (*light.get_point() - point).normalized()
-            };
+            let direction = (*light.get_point() - point).normalized();
             let distance_to_light = (*light.get_point() - point).length();
-            let mut transparency = 1.0;
-            use std::cell::RefCell;
-            let mut cached_obj: Option<&RTObject> = None;
-            // Share it betwen both the for loop and the closure.
-            let cached_obj = RefCell::new(&mut cached_obj);
-
-            let mut add_shadow_intersection = |d: f64| {
-                if d > EPSILON && d < distance_to_light {
-                    let cached_obj = cached_obj.borrow_mut().unwrap();
-                    transparency *= cached_obj.get_material().get_transparency_at_uv(uv_coord);
-                }
-            };
-
-            for obj in self.objects.iter() {
-                cached_obj.borrow_mut().replace(obj);
-                obj.intersects(shadow_ray.clone(), &mut add_shadow_intersection);
-            }
+            let transparency = self.get_shadow_transparency(point, direction, distance_to_light);
 
             // Ignore this light, because there is an opaque object in the way.
             if transparency == 0.0 {
                 continue;
             }
 
-            let angle = Vector::angle(shadow_ray.direction, normal);
+            let angle = Vector::angle(direction, normal);
 
             if angle < 0.0 {
                 panic!("Holy crap, negative angle!");
@@ -227,8 +240,7 @@ impl RayTracer {
             final_light = final_light + c * light_color;
         }
 
-        let angle = Vector::angle( ray.direction *-1.0, normal);
-        let (r1, r2, normal, inside_out) = if angle >= PI / 2.0 {
+        let (r1, r2, normal, inside_out) = if !hit.front_face {
             (1.45, 1.0, normal * -1.0, true)
         } else {
             (1.0, 1.45, normal, false)
@@ -280,21 +292,50 @@ impl RayTracer {
         }
 
         if let Some(debugger) = ray_debugger_callback {
-            debugger(depth, ray, nearest_distance, Some(rt_object), &final_light, &ray_type);
+            debugger(depth, ray, nearest_distance, Some(&hit), &final_light, &ray_type);
         }
 
         final_light
     }
 
-    pub fn set_camera_from_vector(&mut self, center: Vector) {
-        use super::transformation::Transformation;
-        let center = self.transformation_stack
+    /// How much light gets from `point` to `distance` away in `direction`,
+    /// going through whatever is in between.
+    fn get_shadow_transparency(&self, point: Vector, direction: Vector, distance: f64) -> f64 {
+        let shadow_ray = Ray { point, direction };
+        let mut transparency = 1.0;
+
+        for obj in self.objects.iter() {
+            for interval in obj.intervals(shadow_ray.clone()) {
+                // Surfaces without an inside are only crossed once
+                let hits = if interval.entry.distance == interval.exit.distance {
+                    vec![interval.entry]
+                } else {
+                    vec![interval.entry, interval.exit]
+                };
+
+                for hit in hits {
+                    if hit.distance > EPSILON && hit.distance < distance {
+                        let shadow_uv_coord = hit.uv.unwrap_or(UV { u: 0.0, v: 0.0 });
+                        transparency *= hit.object.unwrap_or(obj)
+                            .get_material()
+                            .get_transparency_at_uv(shadow_uv_coord);
+                    }
+                }
+            }
+        }
+
+        transparency
+    }
+
+    pub fn set_camera_from_vector(&mut self, center: Vector, look_at: Option<Vector>) {
+        let transformation = self.transformation_stack
             .get_transformation()
-            .expect("Expected a transformation in the stack!")
-            .transform_vector(center);
+            .expect("Expected a transformation in the stack!");
+        let center = transformation.transform_vector(center);
+        let look_at = look_at.map(|look_at| transformation.transform_vector(look_at));
         self.camera = Box::new(PerspectiveCamera::new(
             self.width, self.height, center,
-            None, None, None
+            look_at, None, None
         ));
     }
 
@@ -302,6 +343,27 @@ impl RayTracer {
         self.camera = camera;
     }
 
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn set_animation(&mut self, animation: Animation) {
+        self.animation = animation;
+    }
+
+    pub fn animation(&self) -> &Animation {
+        &self.animation
+    }
+
+    /// Lights the scene with the background, besides the point lights
+    pub fn set_environment_light(&mut self, environment_light: Option<EnvironmentLight>) {
+        self.environment_light = environment_light;
+    }
+
     pub fn add_light(&mut self, light: PointLight) {
         self.point_lights.push(light);
     }
@@ -333,6 +395,97 @@ impl RayTracer {
         incident - (normal * 2.0 * (normal * incident))
     }
 
+    /// Roughly how much of the UV space one pixel covers around `point`,
+    /// measured by looking up the UV coordinates one pixel away on the surface.
+    fn get_uv_footprint(
+        &self, shape: &dyn MathShape, point: Vector, normal: Vector, distance: f64, uv: UV
+    ) -> f64 {
+        // Camera rays are one unit long and the screen is one unit high
+        let pixel_size = distance / self.height as f64;
+
+        let (tangent, bitangent) = Self::get_surface_directions(normal);
+        let mut footprint: f64 = 0.0;
+
+        for direction in [tangent, bitangent].iter() {
+            let change = Self::get_uv_change(shape, point, *direction * pixel_size, uv);
+
+            if let Some((du, dv)) = change {
+                footprint = footprint.max(sqrt(du * du + dv * dv));
+            }
+        }
+
+        footprint
+    }
+
+    /// The normal bent by the material's bump or normal map, if it has one.
+    fn get_mapped_normal(
+        rt_object: &RTObject, shape: &dyn MathShape, point: Vector, normal: Vector, uv: UV
+    ) -> Vector {
+        let tangent_normal = match rt_object.get_material().get_tangent_normal_at_uv(uv) {
+            Some(tangent_normal) => tangent_normal,
+            None => return normal,
+        };
+
+        // Find which directions on the surface U and V grow in, from how
+        // they change along two known directions.
+        let step = 0.01;
+        let (a, b) = Self::get_surface_directions(normal);
+        let change_a = Self::get_uv_change(shape, point, a * step, uv);
+        let change_b = Self::get_uv_change(shape, point, b * step, uv);
+
+        let ((du_a, dv_a), (du_b, dv_b)) = match (change_a, change_b) {
+            (Some(change_a), Some(change_b)) => (change_a, change_b),
+            _ => return normal,
+        };
+
+        let determinant = du_a * dv_b - du_b * dv_a;
+        if determinant.abs() < EPSILON * EPSILON {
+            return normal;
+        }
+
+        let tangent = (a * dv_b - b * dv_a).normalized();
+        let bitangent = (b * du_a - a * du_b).normalized();
+
+        // With a negative determinant both come out reversed
+        let (tangent, bitangent) = if determinant < 0.0 {
+            (tangent * -1.0, bitangent * -1.0)
+        } else {
+            (tangent, bitangent)
+        };
+
+        (tangent * tangent_normal.x + bitangent * tangent_normal.y + normal * tangent_normal.z)
+            .normalized()
+    }
+
+    /// Two perpendicular directions along a surface, made from the axes least
+    /// aligned with its normal.
+    fn get_surface_directions(normal: Vector) -> (Vector, Vector) {
+        let mut axes = [
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+            Vector::new(0.0, 0.0, 1.0),
+        ];
+        axes.sort_by(|a, b| (*a * normal).abs().partial_cmp(&(*b * normal).abs()).unwrap());
+
+        let tangent = (axes[0] - normal * (axes[0] * normal)).normalized();
+        let bitangent = axes[1] - normal * (axes[1] * normal);
+        let bitangent = (bitangent - tangent * (bitangent * tangent)).normalized();
+
+        (tangent, bitangent)
+    }
+
+    /// How much U and V change between `point` and `point + offset`, or None
+    /// if the shape has no UV coordinates there.
+    fn get_uv_change(shape: &dyn MathShape, point: Vector, offset: Vector, uv: UV) -> Option<(f64, f64)> {
+        let other = shape.get_uv_coordinates(point + offset).ok()?;
+
+        // UV coordinates wrap around, so 0.95 and 0.05 are close
+        let du = other.u - uv.u;
+        let dv = other.v - uv.v;
+
+        Some((du - du.round(), dv - dv.round()))
+    }
+
     fn get_refracted_ray_direction(
         incident: Vector, normal: Vector, r: f64, total_internal_reflection: &mut bool
     ) -> Vector {
@@ -1,7 +1,7 @@
 use super::vector::Ray;
 use super::color::Color;
 use super::material::{Material, SolidColorMaterial};
-use super::math_shapes::{MathShape, AddIntersection};
+use super::math_shapes::{MathShape, AddIntersection, Interval};
 
 #[derive(Clone)]
 pub struct RTObject {
@@ -25,11 +25,15 @@ impl RTObject {
         ))))
     }
 
-    pub fn intersects(&self, ray: Ray, add_intersection: AddIntersection) {
+    pub fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
         let transformed_ray = self.shape.reverse_transform_ray(ray);
         self.shape.intersects(transformed_ray, add_intersection);
     }
 
+    pub fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        self.shape.intervals(self.shape.reverse_transform_ray(ray))
+    }
+
     pub fn get_material(&self) -> &Box<dyn Material> {
         &self.material
     }
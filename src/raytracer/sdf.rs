@@ -0,0 +1,380 @@
+use super::csg::Operator;
+use super::math::{EPSILON, sin, cos, sqrt, abs};
+use super::math_shapes::{MathShape, AddIntersection, HitRecord, Interval, crossing_intervals, spherical_uv_coordinates};
+use super::transformation::{MatrixTransformation, Transformation};
+use super::vector::{Vector, UV, Ray};
+use super::color::Color;
+
+use std::sync::Arc;
+
+// Signed distance fields: shapes given by the distance to their surface,
+// negative inside, which can be blended smoothly and bent.
+
+// Fractions of the bounding radius: the smallest step taken while looking
+// for the surface, and the offset used to find the gradient
+const MIN_STEP: f64 = 1e-4;
+const GRADIENT_OFFSET: f64 = 1e-5;
+const MAX_STEPS: usize = 2000;
+
+#[derive(Clone)]
+pub enum Sdf {
+    Sphere { center: Vector, radius: f64 },
+    /// `size` across, with the edges rounded off by `rounding`
+    Box { center: Vector, size: Vector, rounding: f64 },
+    /// From `start` to `end`, with round ends
+    Capsule { start: Vector, end: Vector, radius: f64 },
+    /// Around the Y axis
+    Torus { center: Vector, radius: f64, tube_radius: f64 },
+    /// Around the Y axis
+    Cylinder { center: Vector, radius: f64, height: f64 },
+    /// A shape placed in the scene; the distance is only right for
+    /// transformations that scale evenly. Made with transformed().
+    Transformed { transformation: MatrixTransformation, scale: f64, sdf: Box<Sdf> },
+    /// Two shapes combined like in a CSG, rounding the seams off by `blend`
+    Smooth { operator: Operator, a: Box<Sdf>, b: Box<Sdf>, blend: f64 },
+    /// `count` copies along each axis of the frame, `spacing` apart
+    Repeat { frame: MatrixTransformation, spacing: Vector, count: Vector, sdf: Box<Sdf> },
+    /// Turned around the Y axis of the frame by `amount` radians per unit
+    /// up. Made with twist().
+    Twist { frame: MatrixTransformation, amount: f64, stretch: f64, sdf: Box<Sdf> },
+}
+
+impl Sdf {
+    pub fn transformed(transformation: MatrixTransformation, sdf: Sdf) -> Sdf {
+        let [x, y, z] = axis_scales(&transformation);
+
+        Sdf::Transformed { transformation, scale: x.min(y).min(z), sdf: Box::new(sdf) }
+    }
+
+    pub fn twist(frame: MatrixTransformation, amount: f64, sdf: Sdf) -> Sdf {
+        // Twisting stretches the distances the most at the far edge
+        let (center, radius) = sdf.bounds();
+        let center = frame.reverse_transform_vector(center);
+        let reach = radius + sqrt(center.x * center.x + center.z * center.z);
+
+        Sdf::Twist { frame, amount, stretch: sqrt(1.0 + amount * amount * reach * reach), sdf: Box::new(sdf) }
+    }
+
+    pub fn distance(&self, p: Vector) -> f64 {
+        match self {
+            Sdf::Sphere { center, radius } => (p - *center).length() - radius,
+            Sdf::Box { center, size, rounding } => {
+                let p = p - *center;
+                let q = Vector::new(
+                    abs(p.x) - size.x / 2.0 + rounding,
+                    abs(p.y) - size.y / 2.0 + rounding,
+                    abs(p.z) - size.z / 2.0 + rounding,
+                );
+                let outside = Vector::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+                let inside = q.x.max(q.y).max(q.z).min(0.0);
+
+                outside + inside - rounding
+            }
+            Sdf::Capsule { start, end, radius } => {
+                let along = *end - *start;
+                let p = p - *start;
+                let h = if along * along > 0.0 {
+                    Color::in_limit((p * along) / (along * along), 0.0, 1.0)
+                } else {
+                    0.0
+                };
+
+                (p - along * h).length() - radius
+            }
+            Sdf::Torus { center, radius, tube_radius } => {
+                let p = p - *center;
+                let ring = sqrt(p.x * p.x + p.z * p.z) - radius;
+
+                sqrt(ring * ring + p.y * p.y) - tube_radius
+            }
+            Sdf::Cylinder { center, radius, height } => {
+                let p = p - *center;
+                let side = sqrt(p.x * p.x + p.z * p.z) - radius;
+                let cap = abs(p.y) - height / 2.0;
+
+                let outside = sqrt(side.max(0.0).powi(2) + cap.max(0.0).powi(2));
+                outside + side.max(cap).min(0.0)
+            }
+            Sdf::Transformed { transformation, scale, sdf } => {
+                sdf.distance(transformation.reverse_transform_vector(p)) * scale
+            }
+            Sdf::Smooth { operator, a, b, blend } => {
+                let (a, b) = (a.distance(p), b.distance(p));
+
+                match operator {
+                    Operator::Union => smooth_min(a, b, *blend),
+                    Operator::Intersection => -smooth_min(-a, -b, *blend),
+                    Operator::Difference => -smooth_min(-a, b, *blend),
+                    // The union with the intersection cut out
+                    Operator::Xor => {
+                        let union = smooth_min(a, b, *blend);
+                        let intersection = -smooth_min(-a, -b, *blend);
+                        -smooth_min(-union, intersection, *blend)
+                    }
+                }
+            }
+            Sdf::Repeat { frame, spacing, count, sdf } => {
+                let local = frame.reverse_transform_vector(p);
+
+                // Into the cell of the nearest copy
+                let fold = |x: f64, spacing: f64, count: f64| {
+                    if spacing == 0.0 || count <= 1.0 {
+                        return x;
+                    }
+                    let middle = (count - 1.0) / 2.0;
+                    let cell = Color::in_limit((x / spacing + middle).round(), 0.0, count - 1.0);
+                    x - spacing * (cell - middle)
+                };
+                let local = Vector::new(
+                    fold(local.x, spacing.x, count.x),
+                    fold(local.y, spacing.y, count.y),
+                    fold(local.z, spacing.z, count.z),
+                );
+
+                sdf.distance(frame.transform_vector(local))
+            }
+            Sdf::Twist { frame, amount, stretch, sdf } => {
+                let local = frame.reverse_transform_vector(p);
+                let angle = -amount * local.y;
+                let local = Vector::new(
+                    local.x * cos(angle) - local.z * sin(angle),
+                    local.y,
+                    local.x * sin(angle) + local.z * cos(angle),
+                );
+
+                sdf.distance(frame.transform_vector(local)) / stretch
+            }
+        }
+    }
+
+    /// A sphere around the whole shape, as its center and radius
+    pub fn bounds(&self) -> (Vector, f64) {
+        match self {
+            Sdf::Sphere { center, radius } => (*center, *radius),
+            Sdf::Box { center, size, .. } => (*center, size.length() / 2.0),
+            Sdf::Capsule { start, end, radius } => {
+                ((*start + *end) * 0.5, (*end - *start).length() / 2.0 + radius)
+            }
+            Sdf::Torus { center, radius, tube_radius } => (*center, radius + tube_radius),
+            Sdf::Cylinder { center, radius, height } => {
+                (*center, sqrt(radius * radius + height * height / 4.0))
+            }
+            Sdf::Transformed { transformation, sdf, .. } => {
+                let (center, radius) = sdf.bounds();
+                let [x, y, z] = axis_scales(transformation);
+
+                (transformation.transform_vector(center), radius * x.max(y).max(z))
+            }
+            Sdf::Smooth { operator, a, b, blend } => {
+                let ((a_center, a_radius), (b_center, b_radius)) = (a.bounds(), b.bounds());
+
+                match operator {
+                    // Blending can bulge out by a quarter of the blend
+                    Operator::Union | Operator::Xor => {
+                        let (center, radius) = enclosing_sphere(a_center, a_radius, b_center, b_radius);
+                        (center, radius + blend / 4.0)
+                    }
+                    Operator::Intersection if b_radius < a_radius => (b_center, b_radius),
+                    Operator::Intersection | Operator::Difference => (a_center, a_radius),
+                }
+            }
+            Sdf::Repeat { frame, spacing, count, sdf } => {
+                let (center, radius) = sdf.bounds();
+                let spread = |spacing: f64, count: f64| abs(spacing) * (count.max(1.0) - 1.0) / 2.0;
+                let spread = Vector::new(
+                    spread(spacing.x, count.x), spread(spacing.y, count.y), spread(spacing.z, count.z),
+                );
+
+                (center, radius + frame.transform_direction_vector(spread).length())
+            }
+            Sdf::Twist { frame, sdf, .. } => {
+                let (center, radius) = sdf.bounds();
+                let local = frame.reverse_transform_vector(center);
+
+                (
+                    frame.transform_vector(Vector::new(0.0, local.y, 0.0)),
+                    radius + sqrt(local.x * local.x + local.z * local.z),
+                )
+            }
+        }
+    }
+}
+
+/// Like min(), with the corner rounded off over `blend`
+fn smooth_min(a: f64, b: f64, blend: f64) -> f64 {
+    if blend <= 0.0 {
+        return a.min(b);
+    }
+
+    let h = Color::in_limit(0.5 + 0.5 * (b - a) / blend, 0.0, 1.0);
+    b + (a - b) * h - blend * h * (1.0 - h)
+}
+
+fn enclosing_sphere(a_center: Vector, a_radius: f64, b_center: Vector, b_radius: f64) -> (Vector, f64) {
+    let distance = (b_center - a_center).length();
+
+    if distance + b_radius <= a_radius {
+        (a_center, a_radius)
+    } else if distance + a_radius <= b_radius {
+        (b_center, b_radius)
+    } else {
+        let radius = (distance + a_radius + b_radius) / 2.0;
+        let center = a_center + (b_center - a_center) * ((radius - a_radius) / distance);
+        (center, radius)
+    }
+}
+
+fn axis_scales(transformation: &MatrixTransformation) -> [f64; 3] {
+    [
+        transformation.transform_direction_vector(Vector::new(1.0, 0.0, 0.0)).length(),
+        transformation.transform_direction_vector(Vector::new(0.0, 1.0, 0.0)).length(),
+        transformation.transform_direction_vector(Vector::new(0.0, 0.0, 1.0)).length(),
+    ]
+}
+
+/// Draws a signed distance field by ray marching: stepping along the ray by
+/// the distance to the nearest surface, which can't skip over it.
+#[derive(Clone)]
+pub struct MathSdf {
+    transformation: MatrixTransformation,
+    sdf: Arc<Sdf>,
+    center: Vector,
+    radius: f64,
+}
+
+impl MathSdf {
+    pub fn new(transformation: MatrixTransformation, sdf: Sdf) -> Self {
+        let (center, radius) = sdf.bounds();
+
+        MathSdf { transformation, sdf: Arc::new(sdf), center, radius }
+    }
+
+    fn gradient(&self, p: Vector) -> Vector {
+        let h = self.radius * GRADIENT_OFFSET;
+        let sdf = &self.sdf;
+
+        Vector::new(
+            sdf.distance(p + Vector::new(h, 0.0, 0.0)) - sdf.distance(p - Vector::new(h, 0.0, 0.0)),
+            sdf.distance(p + Vector::new(0.0, h, 0.0)) - sdf.distance(p - Vector::new(0.0, h, 0.0)),
+            sdf.distance(p + Vector::new(0.0, 0.0, h)) - sdf.distance(p - Vector::new(0.0, 0.0, h)),
+        )
+    }
+
+    fn hit_record<'a>(&'a self, ray: &Ray, distance: f64) -> HitRecord<'a> {
+        let p = ray.point + ray.direction * distance;
+        let normal = self.transformation.transform_normal(self.gradient(p)).normalized();
+
+        HitRecord::on_face(self, ray, distance, normal, Some(spherical_uv_coordinates(p - self.center)), None)
+    }
+}
+
+impl MathSdf {
+    /// Where the ray enters and leaves the bounding sphere, whether it is
+    /// inside the shape where it enters, and where it crosses the surface in
+    /// between, in increasing order.
+    fn march(&self, ray: &Ray) -> Option<(f64, f64, bool, Vec<f64>)> {
+        let o = ray.point - self.center;
+        let d = ray.direction;
+        let speed = d.length();
+
+        // Only march inside the bounding sphere
+        let od = o * d;
+        let discriminant = od * od - (d * d) * (o * o - self.radius * self.radius);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let start = (-od - sqrt(discriminant)) / (d * d);
+        let end = (-od + sqrt(discriminant)) / (d * d);
+
+        let distance = |t: f64| self.sdf.distance(ray.point + d * t);
+        let min_step = self.radius * MIN_STEP / speed;
+
+        let mut t = start;
+        let mut value = distance(t);
+        let inside = value <= 0.0;
+        let mut crossings = vec![];
+
+        for _ in 0..MAX_STEPS {
+            if t >= end {
+                break;
+            }
+
+            let next_t = (t + (abs(value) / speed).max(min_step)).min(end);
+            let next_value = distance(next_t);
+
+            if (value <= 0.0) != (next_value <= 0.0) {
+                // Narrow it down between the two points
+                let (mut low, mut high) = (t, next_t);
+                for _ in 0..40 {
+                    let middle = (low + high) / 2.0;
+                    if (distance(middle) <= 0.0) == (value <= 0.0) {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                crossings.push((low + high) / 2.0);
+            }
+
+            t = next_t;
+            value = next_value;
+        }
+
+        Some((start, end, inside, crossings))
+    }
+}
+
+impl MathShape for MathSdf {
+    fn intersects<'a>(&'a self, ray: Ray, add_intersection: AddIntersection<'_, 'a>) {
+        if let Some((_, _, _, crossings)) = self.march(&ray) {
+            for t in crossings {
+                add_intersection(self.hit_record(&ray, t));
+            }
+        }
+    }
+
+    fn intervals(&self, ray: Ray) -> Vec<Interval<'_>> {
+        match self.march(&ray) {
+            Some((start, end, inside, crossings)) => {
+                crossing_intervals(self, |t| self.hit_record(&ray, t), start, end, inside, &crossings)
+            }
+            None => vec![],
+        }
+    }
+
+    fn get_normal(&self, surface_point: Vector) -> Vector {
+        let p = self.transformation.reverse_transform_vector(surface_point);
+
+        self.transformation.transform_normal(self.gradient(p)).normalized()
+    }
+
+    fn is_inside(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point);
+
+        self.sdf.distance(p) <= EPSILON
+    }
+
+    fn is_on_surface(&self, point: Vector) -> bool {
+        let p = self.transformation.reverse_transform_vector(point);
+
+        abs(self.sdf.distance(p)) <= EPSILON * self.radius.max(1.0)
+    }
+
+    fn get_uv_coordinates(&self, point: Vector) -> Result<UV, &'static str> {
+        let p = self.transformation.reverse_transform_vector(point);
+
+        Ok(spherical_uv_coordinates(p - self.center))
+    }
+
+    fn set_transformation(&mut self, transformation: MatrixTransformation) {
+        self.transformation = transformation;
+    }
+
+    fn get_transformation(&self) -> &MatrixTransformation {
+        &self.transformation
+    }
+
+    fn clone_box(&self) -> Box<dyn MathShape> {
+        Box::new(self.clone())
+    }
+}
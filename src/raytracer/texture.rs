@@ -1,9 +1,23 @@
-use super::vector::UV;
+use super::vector::{Vector, UV};
 use super::color::{Color, RaytracerPixmap, ColorPixmap};
 
+use std::sync::{Arc, OnceLock};
+
 pub trait Texture: Send + Sync {
     fn get_color_at(&self, uv_coordinates: UV) -> Color;
     fn clone_box(&self) -> Box<dyn Texture>;
+
+    /// `footprint` is roughly how much of the UV space one pixel covers, so
+    /// that textures can be blurred where they would otherwise alias.
+    fn get_color_at_footprint(&self, uv_coordinates: UV, _footprint: f64) -> Color {
+        self.get_color_at(uv_coordinates)
+    }
+
+    /// Looks up a point in space instead of on the UV square, for textures that
+    /// are defined everywhere (None for the ones that aren't).
+    fn get_color_at_point(&self, _point: Vector) -> Option<Color> {
+        None
+    }
 }
 
 impl Clone for Box<dyn Texture> {
@@ -12,28 +26,360 @@ impl Clone for Box<dyn Texture> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+/// What happens to UV coordinates outside of 0..1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
 #[derive(Clone)]
 pub struct PixmapTexture {
-    pixmap: RaytracerPixmap,
+    pixmap: Arc<RaytracerPixmap>,
+    // The mipmaps of the pixmap, each half the size of the previous one,
+    // down to 1x1. Only built the first time they're needed, and shared
+    // between the clones of the texture.
+    mip_levels: Arc<OnceLock<Vec<RaytracerPixmap>>>,
+    filter: Filter,
+    wrap: Wrap,
+    mipmaps: bool,
+    // Filtered colors of other textures are kept within 0..1
+    high_dynamic_range: bool,
 }
 
 impl PixmapTexture {
     pub fn from_pixmap(pixmap: RaytracerPixmap) -> Self {
-        PixmapTexture { pixmap }
+        // Sharp texels like before filtering existed, unless a scene asks
+        // for smoother ones
+        PixmapTexture {
+            pixmap: Arc::new(pixmap),
+            mip_levels: Arc::new(OnceLock::new()),
+            filter: Filter::Nearest,
+            wrap: Wrap::Repeat,
+            mipmaps: false,
+            high_dynamic_range: false,
+        }
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+    }
+
+    pub fn set_mipmaps(&mut self, mipmaps: bool) {
+        self.mipmaps = mipmaps;
+    }
+
+    pub fn set_high_dynamic_range(&mut self, high_dynamic_range: bool) {
+        self.high_dynamic_range = high_dynamic_range;
+    }
+
+    pub fn pixmap(&self) -> &RaytracerPixmap {
+        &self.pixmap
+    }
+
+    fn mip_levels(&self) -> &[RaytracerPixmap] {
+        self.mip_levels.get_or_init(|| {
+            let mut levels: Vec<RaytracerPixmap> = Vec::new();
+
+            loop {
+                let last = levels.last().unwrap_or(&self.pixmap);
+                if last.get_width() == 1 && last.get_height() == 1 {
+                    break;
+                }
+                let next = Self::downsample(last);
+                levels.push(next);
+            }
+
+            levels
+        })
+    }
+
+    /// The original pixmap is level 0
+    fn level(&self, level: usize) -> &RaytracerPixmap {
+        if level == 0 {
+            &self.pixmap
+        } else {
+            &self.mip_levels()[level - 1]
+        }
+    }
+
+    /// Averages each 2x2 block of pixels
+    fn downsample(pixmap: &RaytracerPixmap) -> RaytracerPixmap {
+        let width = (pixmap.get_width() / 2).max(1);
+        let height = (pixmap.get_height() / 2).max(1);
+        let mut result = RaytracerPixmap::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let x0 = (x * 2).min(pixmap.get_width() - 1);
+                let y0 = (y * 2).min(pixmap.get_height() - 1);
+                let x1 = (x * 2 + 1).min(pixmap.get_width() - 1);
+                let y1 = (y * 2 + 1).min(pixmap.get_height() - 1);
+
+                let color = mix(&[
+                    (pixmap.get_pixel_color(x0, y0), 0.25),
+                    (pixmap.get_pixel_color(x1, y0), 0.25),
+                    (pixmap.get_pixel_color(x0, y1), 0.25),
+                    (pixmap.get_pixel_color(x1, y1), 0.25),
+                ]);
+                result.set_pixel_color(x, y, color);
+            }
+        }
+
+        result
+    }
+
+    fn wrap_index(&self, index: i64, size: usize) -> usize {
+        let size = size as i64;
+
+        let index = match self.wrap {
+            Wrap::Repeat => index.rem_euclid(size),
+            Wrap::Clamp => index.max(0).min(size - 1),
+            Wrap::Mirror => {
+                let index = index.rem_euclid(2 * size);
+                if index < size {
+                    index
+                } else {
+                    2 * size - 1 - index
+                }
+            }
+        };
+
+        index as usize
+    }
+
+    fn texel(&self, pixmap: &RaytracerPixmap, x: i64, y: i64) -> Color {
+        pixmap.get_pixel_color(
+            self.wrap_index(x, pixmap.get_width()),
+            self.wrap_index(y, pixmap.get_height()),
+        )
+    }
+
+    fn sample(&self, level: usize, uv_coordinates: UV) -> Color {
+        let pixmap = self.level(level);
+
+        // Texel centers are at half coordinates, and V goes up while the
+        // pixmap rows go down.
+        let x = uv_coordinates.u * pixmap.get_width() as f64 - 0.5;
+        let y = (1.0 - uv_coordinates.v) * pixmap.get_height() as f64 - 0.5;
+
+        match self.filter {
+            Filter::Nearest => {
+                let x = nearest_index(uv_coordinates.u, pixmap.get_width());
+                let y = nearest_index(1.0 - uv_coordinates.v, pixmap.get_height());
+
+                self.texel(pixmap, x, y)
+            }
+            Filter::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                mix(&[
+                    (self.texel(pixmap, x0, y0), (1.0 - fx) * (1.0 - fy)),
+                    (self.texel(pixmap, x0 + 1, y0), fx * (1.0 - fy)),
+                    (self.texel(pixmap, x0, y0 + 1), (1.0 - fx) * fy),
+                    (self.texel(pixmap, x0 + 1, y0 + 1), fx * fy),
+                ])
+            }
+            Filter::Bicubic => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let wx = catmull_rom_weights(x - x0);
+                let wy = catmull_rom_weights(y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let mut samples = Vec::with_capacity(16);
+                for j in 0..4 {
+                    for i in 0..4 {
+                        let color = self.texel(pixmap, x0 + i - 1, y0 + j - 1);
+                        samples.push((color, wx[i as usize] * wy[j as usize]));
+                    }
+                }
+
+                let color = mix(&samples);
+
+                // Negative weights can also overshoot past white
+                if self.high_dynamic_range {
+                    color
+                } else {
+                    Color::new(
+                        Color::in_limit(color.r, 0.0, 1.0),
+                        Color::in_limit(color.g, 0.0, 1.0),
+                        Color::in_limit(color.b, 0.0, 1.0),
+                        color.a,
+                    )
+                }
+            }
+        }
     }
 }
 
 impl Texture for PixmapTexture {
     fn get_color_at(&self, uv_coordinates: UV) -> Color {
-        let width = self.pixmap.get_width();
-        let height = self.pixmap.get_height();
-        let x = uv_coordinates.u * (width - 1) as f64;
-        let y = height as f64 - (uv_coordinates.v * (height - 1) as f64) - 1.0;
+        self.sample(0, uv_coordinates)
+    }
+
+    fn get_color_at_footprint(&self, uv_coordinates: UV, footprint: f64) -> Color {
+        if !self.mipmaps {
+            return self.sample(0, uv_coordinates);
+        }
 
-        self.pixmap.get_pixel_color(x as usize, y as usize)
+        // How many texels of the full size pixmap fall in one pixel
+        let size = self.pixmap().get_width().max(self.pixmap().get_height());
+        let texels = footprint * size as f64;
+
+        let max_level = self.mip_levels().len() as f64;
+        let level = if texels > 1.0 { texels.log2().min(max_level) } else { 0.0 };
+
+        // Blend between the two nearest levels
+        let lower = level.floor();
+        let t = level - lower;
+
+        if t == 0.0 {
+            self.sample(lower as usize, uv_coordinates)
+        } else {
+            mix(&[
+                (self.sample(lower as usize, uv_coordinates), 1.0 - t),
+                (self.sample(lower as usize + 1, uv_coordinates), t),
+            ])
+        }
     }
 
     fn clone_box(&self) -> Box<dyn Texture> {
         Box::new(self.clone())
     }
 }
+
+/// The texel that covers a coordinate. The far edge of the UV square belongs
+/// to the last texel, instead of wrapping around to the first one.
+fn nearest_index(coordinate: f64, size: usize) -> i64 {
+    let index = (coordinate * size as f64).floor() as i64;
+
+    if (0.0..=1.0).contains(&coordinate) {
+        index.min(size as i64 - 1)
+    } else {
+        index
+    }
+}
+
+/// Weighted sum of colors, including alpha
+fn mix(samples: &[(Color, f64)]) -> Color {
+    let mut result = Color::EMPTY;
+
+    for (color, weight) in samples {
+        result.r += color.r * weight;
+        result.g += color.g * weight;
+        result.b += color.b * weight;
+        result.a += color.a * weight;
+    }
+
+    // Bicubic weights can be negative, so the sum can overshoot. Colors of
+    // high dynamic range images can be brighter than 1.
+    Color::new(
+        result.r.max(0.0),
+        result.g.max(0.0),
+        result.b.max(0.0),
+        Color::in_limit(result.a, 0.0, 1.0),
+    )
+}
+
+fn catmull_rom_weights(t: f64) -> [f64; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    [
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pixmap whose texels have a different red value each
+    fn numbered_pixmap(width: usize, height: usize) -> RaytracerPixmap {
+        let mut pixmap = RaytracerPixmap::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                pixmap.set_pixel_color(x, y, Color::new((y * width + x) as f64 / 10.0, 0.0, 0.0, 1.0));
+            }
+        }
+        pixmap
+    }
+
+    fn red_at(texture: &PixmapTexture, u: f64, v: f64) -> f64 {
+        (texture.get_color_at(UV { u, v }).r * 10.0).round()
+    }
+
+    #[test]
+    fn nearest_keeps_the_edges() {
+        let texture = PixmapTexture::from_pixmap(numbered_pixmap(2, 2));
+
+        // The top row of the pixmap is at v = 1
+        assert_eq!(red_at(&texture, 0.0, 1.0), 0.0);
+        assert_eq!(red_at(&texture, 1.0, 1.0), 1.0);
+        assert_eq!(red_at(&texture, 0.0, 0.0), 2.0);
+        assert_eq!(red_at(&texture, 1.0, 0.0), 3.0);
+        assert_eq!(red_at(&texture, 0.49, 0.51), 0.0);
+        assert_eq!(red_at(&texture, 0.5, 0.5), 3.0);
+
+        // Outside of the square, it still repeats
+        assert_eq!(red_at(&texture, 1.25, 0.25), 2.0);
+        assert_eq!(red_at(&texture, -0.25, 0.25), 3.0);
+    }
+
+    #[test]
+    fn mipmaps_are_built_on_first_use() {
+        let mut texture = PixmapTexture::from_pixmap(numbered_pixmap(4, 2));
+        let uv = UV { u: 0.5, v: 0.5 };
+
+        texture.get_color_at_footprint(uv, 1.0);
+        assert!(texture.mip_levels.get().is_none());
+
+        texture.set_mipmaps(true);
+        let color = texture.get_color_at_footprint(uv, 1.0);
+        let sizes: Vec<_> = texture.mip_levels.get().unwrap()
+            .iter()
+            .map(|level| (level.get_width(), level.get_height()))
+            .collect();
+
+        assert_eq!(sizes, [(2, 1), (1, 1)]);
+        assert!((color.r - 0.35).abs() < 1e-9);
+    }
+
+    /// Bicubic filtering rings past the brightest texel, which only HDR
+    /// textures are allowed to keep
+    #[test]
+    fn bicubic_clamps_low_dynamic_range() {
+        let mut pixmap = RaytracerPixmap::new(4, 1);
+        for x in 0..4 {
+            let value = if x < 2 { 0.0 } else { 1.0 };
+            pixmap.set_pixel_color(x, 0, Color::new(value, value, value, 1.0));
+        }
+
+        let mut texture = PixmapTexture::from_pixmap(pixmap);
+        texture.set_filter(Filter::Bicubic);
+        texture.set_wrap(Wrap::Clamp);
+        let uv = UV { u: 0.7, v: 0.5 };
+
+        assert_eq!(texture.get_color_at(uv).r, 1.0);
+
+        texture.set_high_dynamic_range(true);
+        assert!(texture.get_color_at(uv).r > 1.0);
+    }
+}
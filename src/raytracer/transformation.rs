@@ -1,4 +1,5 @@
 use super::math::{cos, sin};
+use super::quaternion::Quaternion;
 use super::vector::{Vector, Ray};
 
 // Just a normal stack, except that it knows how to multiply transformation
@@ -41,6 +42,9 @@ pub trait Transformation {
     fn reverse_transform_vector(&self, vector: Vector) -> Vector;
     fn transform_direction_vector(&self, vector: Vector) -> Vector;
     fn reverse_transform_direction_vector(&self, vector: Vector) -> Vector;
+    /// Normals stay perpendicular to the surface, even when it is scaled
+    /// more along some axes than others, by using the inverse transpose.
+    fn transform_normal(&self, normal: Vector) -> Vector;
     fn reverse_transform_ray(&self, ray: Ray) -> Ray;
 }
 
@@ -50,6 +54,37 @@ pub struct MatrixTransformation {
     inverse_matrix: [[f64; 4]; 4],
 }
 
+/// A transformation taken apart into scaling, then rotation, then
+/// translation, which can be blended with another one.
+#[derive(Debug, Clone, Copy)]
+pub struct Decomposition {
+    pub translation: Vector,
+    pub rotation: Quaternion,
+    pub scale: Vector,
+}
+
+impl Decomposition {
+    pub fn to_transformation(self) -> MatrixTransformation {
+        let Vector { x, y, z } = self.scale;
+        let scaling = MatrixTransformation::create_scaling_matrix(x, y, z);
+        let rotation = MatrixTransformation::create_quaternion_rotation_matrix(self.rotation);
+        let Vector { x, y, z } = self.translation;
+        let translation = MatrixTransformation::create_translation_matrix(x, y, z);
+
+        scaling.compose_with(&rotation).compose_with(&translation)
+    }
+
+    /// `a` at t = 0 and `b` at t = 1, turning in between instead of passing
+    /// through the squashed shapes blending the matrices would give.
+    pub fn interpolate(a: &Decomposition, b: &Decomposition, t: f64) -> Decomposition {
+        Decomposition {
+            translation: a.translation * (1.0 - t) + b.translation * t,
+            rotation: Quaternion::slerp(a.rotation, b.rotation, t),
+            scale: a.scale * (1.0 - t) + b.scale * t,
+        }
+    }
+}
+
 fn transform_vector(vector: Vector, matrix: [[f64; 4]; 4]) -> Vector {
     let a = matrix[0][0] * vector.x + matrix[0][1] * vector.y + matrix[0][2] * vector.z + matrix[0][3];
     let b = matrix[1][0] * vector.x + matrix[1][1] * vector.y + matrix[1][2] * vector.z + matrix[1][3];
@@ -85,6 +120,16 @@ impl Transformation for MatrixTransformation {
         transform_vector(vector, self.inverse_matrix) - transformed_origin
     }
 
+    fn transform_normal(&self, normal: Vector) -> Vector {
+        let m = self.inverse_matrix;
+
+        Vector::new(
+            m[0][0] * normal.x + m[1][0] * normal.y + m[2][0] * normal.z,
+            m[0][1] * normal.x + m[1][1] * normal.y + m[2][1] * normal.z,
+            m[0][2] * normal.x + m[1][2] * normal.y + m[2][2] * normal.z,
+        )
+    }
+
     fn reverse_transform_ray(&self, ray: Ray) -> Ray {
         Ray {
             point: self.reverse_transform_vector(ray.point),
@@ -112,6 +157,9 @@ impl MatrixTransformation {
         MatrixTransformation::new(matrix, matrix)
     }
 
+    /// Turning around X, then Y, then Z. The X and Z angles turn the same way
+    /// as create_axis_angle_rotation_matrix(), but the Y angle has always
+    /// turned the other way: `y` here is an axis-angle turn of `-y` around Y.
     pub fn create_rotation_matrix(x: f64, y: f64, z: f64) -> Self {
         fn x_rotation_matrix(angle: f64) -> [[f64; 4]; 4] {
             let csa = cos(angle);
@@ -153,8 +201,9 @@ impl MatrixTransformation {
         let matrix_3 = z_rotation_matrix(z);
         let inverse_matrix_3 = z_rotation_matrix(-z);
 
-        let temp = multiply_matrices(matrix_1, matrix_2);
-        let final_matrix = multiply_matrices(temp, matrix_3);
+        // Turned around X first, then Y and Z, like the inverse undoes it
+        let temp = multiply_matrices(matrix_3, matrix_2);
+        let final_matrix = multiply_matrices(temp, matrix_1);
         let temp = multiply_matrices(inverse_matrix_1, inverse_matrix_2);
         let final_inverse_matrix = multiply_matrices(temp, inverse_matrix_3);
 
@@ -197,6 +246,89 @@ impl MatrixTransformation {
         MatrixTransformation::new(matrix, inverse_matrix)
     }
 
+    /// Turning by `angle` around `axis`, counterclockwise when looking
+    /// against the direction it points in. Around Y, that is the opposite way
+    /// from create_rotation_matrix().
+    pub fn create_axis_angle_rotation_matrix(axis: Vector, angle: f64) -> Self {
+        MatrixTransformation::create_quaternion_rotation_matrix(Quaternion::from_axis_angle(axis, angle))
+    }
+
+    pub fn create_quaternion_rotation_matrix(rotation: Quaternion) -> Self {
+        let matrix = rotation.to_rotation_matrix();
+        let inverse_matrix = rotation.conjugate().to_rotation_matrix();
+
+        MatrixTransformation::new(affine_matrix(matrix, ZERO), affine_matrix(inverse_matrix, ZERO))
+    }
+
+    /// Each coordinate is moved by the others times their factor, e.g. x by
+    /// `xy * y + xz * z`.
+    pub fn create_shear_matrix(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Self {
+        let matrix = [
+            [ 1.0, xy, xz ],
+            [ yx, 1.0, yz ],
+            [ zx, zy, 1.0 ],
+        ];
+        // FIXME: No panic
+        let inverse_matrix = invert_matrix(matrix).expect("Shear flattens everything onto a plane!");
+
+        MatrixTransformation::new(affine_matrix(matrix, ZERO), affine_matrix(inverse_matrix, ZERO))
+    }
+
+    /// Moved to `position` and turned so that its Z axis points at `target`,
+    /// with its Y axis as close to `up` as possible.
+    pub fn create_look_at_matrix(position: Vector, target: Vector, up: Vector) -> Self {
+        let forward = (target - position).normalized();
+        let mut right = Vector::cross_product(up, forward);
+        // Looking straight along `up` leaves any way to turn around it
+        if right.length() < 1e-9 {
+            right = Vector::cross_product(Vector::new(0.0, 0.0, 1.0), forward);
+            if right.length() < 1e-9 {
+                right = Vector::new(1.0, 0.0, 0.0);
+            }
+        }
+        let right = right.normalized();
+        let up = Vector::cross_product(forward, right);
+
+        let matrix = [
+            [ right.x, up.x, forward.x ],
+            [ right.y, up.y, forward.y ],
+            [ right.z, up.z, forward.z ],
+        ];
+        // Turning back is the transpose, and moving back is done after it
+        let inverse_matrix = transpose(matrix);
+        let inverse_translation = -multiply_vector(inverse_matrix, position);
+
+        MatrixTransformation::new(
+            affine_matrix(matrix, position),
+            affine_matrix(inverse_matrix, inverse_translation),
+        )
+    }
+
+    /// Takes apart into scaling, rotation and translation. Mirroring ends up
+    /// as a negative X scale, and shear is lost.
+    pub fn decompose(&self) -> Decomposition {
+        let m = self.matrix;
+        let translation = Vector::new(m[0][3], m[1][3], m[2][3]);
+        let mut columns = [0, 1, 2].map(|j| Vector::new(m[0][j], m[1][j], m[2][j]));
+        let mut scale = Vector::new(columns[0].length(), columns[1].length(), columns[2].length());
+
+        let determinant = Vector::cross_product(columns[0], columns[1]) * columns[2];
+        if determinant < 0.0 {
+            scale.x = -scale.x;
+        }
+        for (axis, column) in columns.iter_mut().enumerate() {
+            *column = *column * (1.0 / scale.axis(axis));
+        }
+
+        let rotation = Quaternion::from_rotation_matrix([
+            [ columns[0].x, columns[1].x, columns[2].x ],
+            [ columns[0].y, columns[1].y, columns[2].y ],
+            [ columns[0].z, columns[1].z, columns[2].z ],
+        ]);
+
+        Decomposition { translation, rotation, scale }
+    }
+
     pub fn compose_with(&self, other: &MatrixTransformation) -> MatrixTransformation {
         let new_matrix = multiply_matrices(other.matrix, self.matrix);
         let new_inverse_matrix = multiply_matrices(self.inverse_matrix, other.inverse_matrix);
@@ -205,6 +337,43 @@ impl MatrixTransformation {
     }
 }
 
+const ZERO: Vector = Vector { x: 0.0, y: 0.0, z: 0.0 };
+
+fn affine_matrix(linear: [[f64; 3]; 3], translation: Vector) -> [[f64; 4]; 4] {
+    [
+        [ linear[0][0], linear[0][1], linear[0][2], translation.x ],
+        [ linear[1][0], linear[1][1], linear[1][2], translation.y ],
+        [ linear[2][0], linear[2][1], linear[2][2], translation.z ],
+        [ 0.0, 0.0, 0.0, 1.0 ],
+    ]
+}
+
+fn transpose(matrix: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [0, 1, 2].map(|i| [0, 1, 2].map(|j| matrix[j][i]))
+}
+
+fn multiply_vector(matrix: [[f64; 3]; 3], vector: Vector) -> Vector {
+    let [a, b, c] = matrix.map(|row| row[0] * vector.x + row[1] * vector.y + row[2] * vector.z);
+
+    Vector::new(a, b, c)
+}
+
+/// Inverse of a 3x3 matrix from its cofactors, if it has one
+fn invert_matrix(m: [[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let cofactor = |i: usize, j: usize| {
+        let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+        let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+        m[i1][j1] * m[i2][j2] - m[i1][j2] * m[i2][j1]
+    };
+
+    let determinant = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+
+    Some([0, 1, 2].map(|i| [0, 1, 2].map(|j| cofactor(j, i) / determinant)))
+}
+
 fn multiply_matrices(matrix1: [[f64; 4]; 4], matrix2: [[f64; 4]; 4]) -> [[f64; 4]; 4] {
     let mut result: [[f64; 4]; 4] = Default::default();
 
@@ -218,3 +387,81 @@ fn multiply_matrices(matrix1: [[f64; 4]; 4], matrix2: [[f64; 4]; 4]) -> [[f64; 4
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Turning around all three axes and back ends up where it started
+    #[test]
+    fn rotation_matrix_times_inverse_is_identity() {
+        for &(x, y, z) in [(0.3, 0.0, 0.0), (0.0, -1.2, 0.0), (0.4, 1.1, -2.5), (3.0, -0.7, 0.9)].iter() {
+            let rotation = MatrixTransformation::create_rotation_matrix(x, y, z);
+            let product = multiply_matrices(rotation.matrix, rotation.inverse_matrix);
+
+            for i in 0..4 {
+                for j in 0..4 {
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    assert!((product[i][j] - expected).abs() < 1e-12, "{:?} at ({}, {}, {})", product, x, y, z);
+                }
+            }
+        }
+    }
+
+    fn assert_same_matrix(a: &MatrixTransformation, b: &MatrixTransformation) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.matrix[i][j] - b.matrix[i][j]).abs() < 1e-9, "{:?} != {:?}", a.matrix, b.matrix);
+                assert!((a.inverse_matrix[i][j] - b.inverse_matrix[i][j]).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn euler_angles_against_axis_angle() {
+        let angle = 0.7;
+        let axis_angle = |x, y, z, angle| {
+            MatrixTransformation::create_axis_angle_rotation_matrix(Vector::new(x, y, z), angle)
+        };
+
+        assert_same_matrix(&MatrixTransformation::create_rotation_matrix(angle, 0.0, 0.0), &axis_angle(1.0, 0.0, 0.0, angle));
+        assert_same_matrix(&MatrixTransformation::create_rotation_matrix(0.0, angle, 0.0), &axis_angle(0.0, 1.0, 0.0, -angle));
+        assert_same_matrix(&MatrixTransformation::create_rotation_matrix(0.0, 0.0, angle), &axis_angle(0.0, 0.0, 1.0, angle));
+    }
+
+    #[test]
+    fn decompose_round_trip() {
+        let rotation = MatrixTransformation::create_axis_angle_rotation_matrix(Vector::new(1.0, 2.0, -0.5), 2.1);
+
+        for &scale in [(1.0, 1.0, 1.0), (2.0, 3.0, 0.5), (-1.5, 1.0, 2.0)].iter() {
+            let transformation = MatrixTransformation::create_scaling_matrix(scale.0, scale.1, scale.2)
+                .compose_with(&rotation)
+                .compose_with(&MatrixTransformation::create_translation_matrix(4.0, -5.0, 6.0));
+
+            let decomposition = transformation.decompose();
+            assert!((decomposition.translation - Vector::new(4.0, -5.0, 6.0)).length() < 1e-9);
+            assert!((decomposition.scale.x.abs() - scale.0.abs()).abs() < 1e-9);
+
+            assert_same_matrix(&decomposition.to_transformation(), &transformation);
+        }
+    }
+
+    #[test]
+    fn interpolate_decompositions() {
+        let start = MatrixTransformation::create_scaling_matrix(1.0, 1.0, 1.0).decompose();
+        let end = MatrixTransformation::create_scaling_matrix(3.0, 3.0, 3.0)
+            .compose_with(&MatrixTransformation::create_axis_angle_rotation_matrix(Vector::new(0.0, 0.0, 1.0), 2.0))
+            .compose_with(&MatrixTransformation::create_translation_matrix(10.0, 0.0, 0.0))
+            .decompose();
+
+        assert_same_matrix(&Decomposition::interpolate(&start, &end, 0.0).to_transformation(), &start.to_transformation());
+        assert_same_matrix(&Decomposition::interpolate(&start, &end, 1.0).to_transformation(), &end.to_transformation());
+
+        // Halfway turned halfway, grown halfway and moved halfway, without
+        // shrinking through the middle like a blend of the matrices would
+        let halfway = MatrixTransformation::create_scaling_matrix(2.0, 2.0, 2.0)
+            .compose_with(&MatrixTransformation::create_axis_angle_rotation_matrix(Vector::new(0.0, 0.0, 1.0), 1.0))
+            .compose_with(&MatrixTransformation::create_translation_matrix(5.0, 0.0, 0.0));
+        assert_same_matrix(&Decomposition::interpolate(&start, &end, 0.5).to_transformation(), &halfway);
+    }
+}
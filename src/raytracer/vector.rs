@@ -6,7 +6,7 @@ pub struct Ray {
     pub direction: Vector,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct UV {
     pub u: f64,
     pub v: f64,
@@ -61,7 +61,7 @@ impl Vector {
     pub fn cross_product(a: Vector, b: Vector) -> Vector {
         Vector::new(
             a.y * b.z - a.z * b.y,
-            a.x * b.z - a.z * b.x,
+            a.z * b.x - a.x * b.z,
             a.x * b.y - a.y * b.x,
         )
     }
@@ -118,3 +118,19 @@ impl std::ops::Neg for Vector {
         Vector::new(-self.x, -self.y, -self.z)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Right-handed, like the rotations
+    #[test]
+    fn cross_product_of_the_axes() {
+        let (x, y, z) = (Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), Vector::new(0.0, 0.0, 1.0));
+
+        for &(a, b, expected) in [(x, y, z), (y, z, x), (z, x, y)].iter() {
+            assert!((Vector::cross_product(a, b) - expected).length() < 1e-12);
+            assert!((Vector::cross_product(b, a) + expected).length() < 1e-12);
+        }
+    }
+}
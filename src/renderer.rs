@@ -0,0 +1,149 @@
+use crate::raytracer::antialiaser::AntiAliaser;
+use crate::raytracer::color::{ColorPixmap, RaytracerPixmap};
+use crate::raytracer::raytracer::RayTracer;
+use crate::sceneparser::scene_loader::{load_scene_source, scene_animation, SceneError};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+// Renders every frame of a scene's animation to numbered binary PPM files,
+// which video encoders like ffmpeg read directly, without the debugger's
+// window.
+
+/// Returns false if the scene could not be loaded or a frame not written.
+pub fn render_animation(path: &str, directory: &str, width: usize, height: usize) -> bool {
+    let scene = match fs::read_to_string(path) {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            return false;
+        }
+    };
+
+    let animation = match scene_animation(&scene) {
+        Ok(animation) => animation,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            return false;
+        }
+    };
+
+    if let Err(err) = fs::create_dir_all(directory) {
+        eprintln!("{}: {}", directory, err);
+        return false;
+    }
+
+    let thread_pool = threadpool::Builder::new()
+        .thread_name("frame-renderer".to_string())
+        .build();
+    let (sender, receiver) = mpsc::channel();
+
+    for frame in 0..animation.frames {
+        let scene = scene.clone();
+        let sender = sender.clone();
+        let file = Path::new(directory).join(format!("frame_{:04}.ppm", frame));
+
+        thread_pool.execute(move || {
+            let result = render_frame(&scene, animation.time(frame), width, height, &file);
+            sender.send(result).unwrap();
+        });
+    }
+    drop(sender);
+
+    let mut written = 0;
+    for result in receiver {
+        match result {
+            Ok(file) => {
+                println!("{}", file.display());
+                written += 1;
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    // Frames that panicked never sent anything
+    let success = written == animation.frames;
+
+    if success {
+        println!("{} frames at {} fps", animation.frames, animation.fps);
+    }
+
+    success
+}
+
+fn render_frame(scene: &str, time: f64, width: usize, height: usize, file: &Path) -> Result<PathBuf, String> {
+    let mut ray_tracer = RayTracer::new_default(width, height);
+
+    match load_scene_source(&mut ray_tracer, scene, time) {
+        // Missing textures and such are reported, but the frame is still drawn
+        Ok(()) => (),
+        Err(SceneError::Execution(errors)) => eprintln!("{}", errors.join("\n")),
+        Err(err) => return Err(err.to_string()),
+    }
+
+    let mut pixmap = RaytracerPixmap::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            pixmap.set_pixel_color(x, y, ray_tracer.get_pixel(x as f64, y as f64, &mut None));
+        }
+    }
+
+    let anti_aliaser = AntiAliaser::new(&ray_tracer, None, None);
+    let mut sub_pixels = anti_aliaser.create_sub_pixel_buffer();
+    let mut ray_counter = 0;
+    let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+
+    for y in 0..height {
+        // Each pixel is blended with the ones below it, so the last line is
+        // kept as it is, like in the debugger
+        let line = if y + 1 < height {
+            anti_aliaser.anti_alias_line_vec(y, &mut sub_pixels, &mut ray_counter, &pixmap)
+        } else {
+            (0..width).map(|x| pixmap.get_pixel_color(x, y)).collect()
+        };
+
+        for color in line {
+            let (r, g, b) = color.to_u8();
+            ppm.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    fs::write(file, ppm)
+        .map(|()| file.to_path_buf())
+        .map_err(|err| format!("{}: {}", file.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_come_from_the_duration_unless_given() {
+        let animation = scene_animation("set animation(duration: 2, fps: 24, start: 1, end: 3)").unwrap();
+        assert_eq!(animation.frames, 48);
+        assert_eq!(animation.time(0), 1.0);
+        assert_eq!(animation.time(24), 2.0);
+        // The end time belongs to the frame after the last one
+        assert!(animation.time(47) < 3.0);
+
+        let animation = scene_animation("set animation(frames: 10, duration: 2, fps: 24)").unwrap();
+        assert_eq!(animation.frames, 10);
+        assert_eq!(animation.time(5), 0.5);
+
+        let animation = scene_animation("draw(sphere(1))").unwrap();
+        assert_eq!(animation.frames, 300);
+        assert_eq!(animation.frame_interval(), 33);
+    }
+
+    #[test]
+    fn frames_are_written_as_binary_ppm() {
+        let file = std::env::temp_dir().join(format!("render_test_{}.ppm", std::process::id()));
+        let written = render_frame("draw(sphere(1))", 0.0, 4, 3, &file).unwrap();
+        let ppm = fs::read(&written).unwrap();
+        fs::remove_file(&written).unwrap();
+
+        let header = b"P6\n4 3\n255\n";
+        assert!(ppm.starts_with(header));
+        assert_eq!(ppm.len(), header.len() + 4 * 3 * 3);
+    }
+}
@@ -0,0 +1,224 @@
+use super::value::Value;
+
+// Keyframe tracks: numbers, vectors or colors given at a few points in time,
+// with the values in between filled in along a curve.
+
+pub const ANIMATION_CURVES: &[&str] = &[
+    "linear", "step", "ease_in", "ease_out", "ease_in_out", "bezier", "catmull_rom",
+];
+
+/// animate(time, [time: value, ...], curve)
+///
+/// Before the first key and after the last one the value stays the same.
+pub fn animate(args: &[Value]) -> Value {
+    let time = args[0].to_number();
+    let curve = args.get(2).map_or_else(|| "linear".to_string(), Value::to_string);
+
+    let mut keys: Vec<(f64, &Value)> = args[1]
+        .to_list()
+        .iter()
+        .map(|key| match key.to_list() {
+            [time, value] => (time.to_number(), value),
+            // FIXME: No panic
+            key => panic!("Expected a keyframe like [time, value], got {:?}", key),
+        })
+        // A key without a real time has no place on the track
+        .filter(|(time, _)| !time.is_nan())
+        .collect();
+    keys.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let (first, last) = match (keys.first(), keys.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        // FIXME: No panic
+        _ => panic!("animate() needs at least one keyframe"),
+    };
+    // Nothing compares to a NaN time, so it would find no key to be after
+    if time <= first.0 || time.is_nan() {
+        return first.1.clone();
+    }
+    if time >= last.0 {
+        return last.1.clone();
+    }
+
+    if curve == "bezier" {
+        let t = (time - first.0) / (last.0 - first.0);
+        let values: Vec<_> = keys.iter().map(|(_, value)| *value).collect();
+        return blend(&bezier_weights(values.len(), t).iter().copied().zip(values).collect::<Vec<_>>());
+    }
+
+    // The key the time is after, and how far it is towards the next one
+    let i = keys.iter().rposition(|(key_time, _)| *key_time <= time).unwrap();
+    let s = (time - keys[i].0) / (keys[i + 1].0 - keys[i].0);
+    let (a, b) = (keys[i].1, keys[i + 1].1);
+
+    match curve.as_str() {
+        "linear" => blend(&[(1.0 - s, a), (s, b)]),
+        "step" => a.clone(),
+        "ease_in" => ease(a, b, s, (0.42, 0.0), (1.0, 1.0)),
+        "ease_out" => ease(a, b, s, (0.0, 0.0), (0.58, 1.0)),
+        "ease_in_out" => ease(a, b, s, (0.42, 0.0), (0.58, 1.0)),
+        "catmull_rom" => {
+            // The ends are repeated, so that the curve starts and stops there
+            let before = keys[i.saturating_sub(1)].1;
+            let after = keys[(i + 2).min(keys.len() - 1)].1;
+            let (s2, s3) = (s * s, s * s * s);
+
+            blend(&[
+                ((-s + 2.0 * s2 - s3) / 2.0, before),
+                ((2.0 - 5.0 * s2 + 3.0 * s3) / 2.0, a),
+                ((s + 4.0 * s2 - 3.0 * s3) / 2.0, b),
+                ((s3 - s2) / 2.0, after),
+            ])
+        }
+        // FIXME: No panic
+        curve => panic!(
+            "Unknown animation curve '{}', expected one of: {}", curve, ANIMATION_CURVES.join(", ")
+        ),
+    }
+}
+
+/// From `a` to `b` with the speed following a cubic Bézier timing curve from
+/// (0, 0) to (1, 1) with the two given control points, like CSS easing.
+fn ease(a: &Value, b: &Value, s: f64, p1: (f64, f64), p2: (f64, f64)) -> Value {
+    let cubic = |u: f64, p1: f64, p2: f64| {
+        3.0 * (1.0 - u) * (1.0 - u) * u * p1 + 3.0 * (1.0 - u) * u * u * p2 + u * u * u
+    };
+
+    // The curve only moves forward in x, so the point at x = s can be bisected
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..40 {
+        let middle = (low + high) / 2.0;
+        if cubic(middle, p1.0, p2.0) < s {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    let s = cubic((low + high) / 2.0, p1.1, p2.1);
+
+    blend(&[(1.0 - s, a), (s, b)])
+}
+
+/// How much each of `count` control points pulls on a Bézier curve at `t`
+fn bezier_weights(count: usize, t: f64) -> Vec<f64> {
+    let n = count - 1;
+    let mut binomial = 1.0;
+
+    (0..=n)
+        .map(|k| {
+            let weight = binomial * t.powi(k as i32) * (1.0 - t).powi((n - k) as i32);
+            binomial = binomial * (n - k) as f64 / (k + 1) as f64;
+            weight
+        })
+        .collect()
+}
+
+/// The sum of the values times their weights
+fn blend(weighted: &[(f64, &Value)]) -> Value {
+    let sum = |component: fn(&Value) -> f64| {
+        weighted.iter().map(|(weight, value)| weight * component(value)).sum()
+    };
+
+    match weighted[0].1 {
+        Value::Number(_) => Value::Number(sum(Value::to_number)),
+        Value::Vector { .. } => Value::Vector {
+            x: sum(|value| value.to_vector().x),
+            y: sum(|value| value.to_vector().y),
+            z: sum(|value| value.to_vector().z),
+        },
+        Value::Color { .. } => Value::Color {
+            r: sum(|value| value.to_color().r),
+            g: sum(|value| value.to_color().g),
+            b: sum(|value| value.to_color().b),
+            a: sum(|value| value.to_color().a),
+        },
+        // FIXME: No panic
+        value => panic!("Cannot animate {:?}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number_at(time: f64, keys: &[(f64, f64)], curve: &str) -> f64 {
+        let keys = keys
+            .iter()
+            .map(|&(time, value)| Value::List(vec![Value::Number(time), Value::Number(value)]))
+            .collect();
+
+        animate(&[Value::Number(time), Value::List(keys), Value::String(curve.to_string())]).to_number()
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    const KEYS: &[(f64, f64)] = &[(0.0, 0.0), (1.0, 10.0), (2.0, 30.0), (3.0, 30.0)];
+
+    /// Every curve but the Bézier one goes through the keys, and all of them
+    /// hold still outside of them
+    #[test]
+    fn keys_and_ends() {
+        for &curve in ANIMATION_CURVES {
+            assert_near(number_at(-1.0, KEYS, curve), 0.0);
+            assert_near(number_at(0.0, KEYS, curve), 0.0);
+            assert_near(number_at(3.0, KEYS, curve), 30.0);
+            assert_near(number_at(4.0, KEYS, curve), 30.0);
+
+            if curve != "bezier" {
+                assert_near(number_at(1.0, KEYS, curve), 10.0);
+                assert_near(number_at(2.0, KEYS, curve), 30.0);
+            }
+        }
+    }
+
+    #[test]
+    fn between_keys() {
+        assert_near(number_at(1.25, KEYS, "linear"), 15.0);
+        assert_near(number_at(1.75, KEYS, "step"), 10.0);
+
+        // Slow at the start, fast at the end, and the other way around
+        assert!(number_at(0.25, KEYS, "ease_in") < 2.5);
+        assert!(number_at(0.25, KEYS, "ease_out") > 2.5);
+        assert_near(number_at(0.5, KEYS, "ease_in_out"), 5.0);
+
+        // Smooth through the keys, so it overshoots the flat end a little
+        assert_near(number_at(1.5, KEYS, "catmull_rom"), 20.625);
+        assert_near(number_at(2.5, KEYS, "catmull_rom"), 31.25);
+
+        // Pulled towards the middle keys without reaching them
+        assert_near(number_at(1.5, KEYS, "bezier"), 18.75);
+    }
+
+    #[test]
+    fn vectors_and_colors() {
+        let keys = Value::List(vec![
+            Value::List(vec![Value::Number(0.0), Value::Vector { x: 0.0, y: 2.0, z: -4.0 }]),
+            Value::List(vec![Value::Number(1.0), Value::Vector { x: 10.0, y: 2.0, z: 4.0 }]),
+        ]);
+        let vector = animate(&[Value::Number(0.25), keys]).to_vector();
+        assert_eq!((vector.x, vector.y, vector.z), (2.5, 2.0, -2.0));
+
+        let keys = Value::List(vec![
+            Value::List(vec![Value::Number(0.0), Value::Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 }]),
+            Value::List(vec![Value::Number(2.0), Value::Color { r: 0.0, g: 0.0, b: 1.0, a: 1.0 }]),
+        ]);
+        let color = animate(&[Value::Number(1.0), keys]).to_color();
+        assert_eq!((color.r, color.g, color.b, color.a), (0.5, 0.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn keys_without_a_time_are_skipped() {
+        let keys = [(0.0, 0.0), (f64::NAN, 100.0), (2.0, 20.0)];
+
+        assert_near(number_at(1.0, &keys, "linear"), 10.0);
+    }
+
+    #[test]
+    fn times_that_are_not_a_number_stay_at_the_first_key() {
+        for &curve in ANIMATION_CURVES {
+            assert_near(number_at(f64::NAN, KEYS, curve), 0.0);
+        }
+    }
+}
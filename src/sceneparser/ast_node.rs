@@ -1,26 +1,66 @@
 use crate::raytracer::color::Color;
-use crate::raytracer::vector::Vector;
-use crate::raytracer::transformation::MatrixTransformation;
+use crate::raytracer::vector::{Vector, UV};
+use crate::raytracer::math_shapes::CubeMapping;
+use crate::raytracer::texture::{Filter, Wrap};
+use crate::raytracer::transformation::{MatrixTransformation, TransformationStack};
+use crate::raytracer::quaternion::Quaternion;
 use crate::raytracer::point_light::PointLight;
+use crate::raytracer::background::Background;
+use crate::raytracer::animation::Animation;
+use crate::raytracer::environment_light::EnvironmentLight;
 use super::context::{SceneContext, Identifier};
 use super::scene_loader::Rule;
 use super::value::Value;
-use super::shape::{Shape, ShapeKind, CSGOperator};
+use super::formula::Formula;
+use super::shape::{Shape, ShapeKind, CSGOperator, TextureSpace, TextureMaps};
 use super::texture::Texture;
+use super::function::find_builtin;
 
-use pest::iterators::Pair;
+use pest::iterators::{Pair, Pairs};
+use pest::Span;
 use std::rc::Rc;
-use std::collections::VecDeque;
+use std::collections::{VecDeque, HashMap};
 use crate::sceneparser::shape::Material;
 
+/// Where a node starts in the scene source, for diagnostics.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn from_span(span: &Span) -> Self {
+        let (line, column) = span.start_pos().line_col();
+        Position { line, column }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Function {
     id: String,
     param_list: Vec<String>,
     body: Rc<AstStatement>,
+    position: Position,
 }
 
 impl Function {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn param_list(&self) -> &[String] {
+        &self.param_list
+    }
+
+    pub fn body(&self) -> &AstStatement {
+        &self.body
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
     pub fn call(&self, context: &mut SceneContext, value_list: Vec<Value>) {
         assert_eq!(self.param_list.len(), value_list.len());
 
@@ -35,29 +75,63 @@ impl Function {
 #[derive(Debug)]
 pub enum AstStatement {
     StatementList(Vec<AstStatement>),
-    Assignment { local: bool, id: Identifier, expression: AstExpression },
+    Assignment { local: bool, id: Identifier, expression: AstExpression, position: Position },
     Function(Function),
-    CallFunction { id: Identifier, param_list: Vec<AstExpression> },
+    CallFunction { id: Identifier, param_list: Vec<AstExpression>, position: Position },
     Draw { param_list: Vec<AstExpression> },
     Transformation {
-        x: AstExpression, y: AstExpression, z: AstExpression,
+        arguments: Vec<AstExpression>,
         transformation: Transformation,
         statement: Box<AstStatement>,
+        position: Position,
+    },
+    If { condition: AstExpression, body: Box<AstStatement>, position: Position },
+    While { condition: AstExpression, body: Box<AstStatement>, position: Position },
+    For {
+        id: Identifier,
+        iterable: AstExpression,
+        body: Box<AstStatement>,
+        position: Position,
     },
-    If { condition: AstExpression, body: Box<AstStatement> },
-    While { condition: AstExpression, body: Box<AstStatement> },
     AppendLight { param_list: Vec<AstExpression> },
-    SetCamera { position: AstExpression },
+    SetCamera { position: AstExpression, look_at: Option<AstExpression> },
+    SetBackground {
+        param_list: Vec<AstExpression>,
+        keyword_params: Vec<(Identifier, AstExpression)>,
+        position: Position,
+    },
+    SetAnimation {
+        param_list: Vec<AstExpression>,
+        keyword_params: Vec<(Identifier, AstExpression)>,
+        position: Position,
+    },
+    // Kept only so that the formatter can re-emit them
+    Comment { text: String, trailing: bool },
+    BlankLine,
 }
 
 #[derive(Debug)]
 pub enum AstExpression {
     Value(Value),
-    Reference(Identifier),
+    Reference { id: Identifier, position: Position },
     Vector { x: Box<AstExpression>, y: Box<AstExpression>, z: Box<AstExpression> },
     Rgb { r: Box<AstExpression>, g: Box<AstExpression>, b: Box<AstExpression> },
-    Object { name: String, param_list: Vec<AstExpression> },
-    Texture { texture_file: Box<AstExpression> },
+    Object {
+        name: String,
+        param_list: Vec<AstExpression>,
+        keyword_params: Vec<(Identifier, AstExpression)>,
+        position: Position,
+    },
+    Texture {
+        texture_file: Box<AstExpression>,
+        keyword_params: Vec<(Identifier, AstExpression)>,
+        position: Position,
+    },
+    FunctionCall { id: Identifier, param_list: Vec<AstExpression>, position: Position },
+    List(Vec<AstExpression>),
+    Keyframes(Vec<(AstExpression, AstExpression)>),
+    Range { start: Box<AstExpression>, end: Box<AstExpression> },
+    Index { list: Box<AstExpression>, index: Box<AstExpression> },
     Minus(Box<AstExpression>),
     BinaryOperation { a: Box<AstExpression>, operator: BinaryOperator, b: Box<AstExpression> },
 }
@@ -78,6 +152,34 @@ pub enum Transformation {
     Translate,
     Rotate,
     Scale,
+    RotateAxis,
+    RotateQuaternion,
+    Shear,
+    Orient,
+}
+
+impl Transformation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transformation::Translate => "translate",
+            Transformation::Rotate => "rotate",
+            Transformation::Scale => "scale",
+            Transformation::RotateAxis => "rotate_axis",
+            Transformation::RotateQuaternion => "rotate_quaternion",
+            Transformation::Shear => "shear",
+            Transformation::Orient => "orient",
+        }
+    }
+
+    pub fn argument_count(&self) -> usize {
+        match self {
+            Transformation::Translate | Transformation::Rotate | Transformation::Scale => 3,
+            // An axis and an angle, or a position and what to point at
+            Transformation::RotateAxis | Transformation::Orient => 2,
+            Transformation::RotateQuaternion => 4,
+            Transformation::Shear => 6,
+        }
+    }
 }
 
 pub fn expect_id(pair: Pair<Rule>) -> String {
@@ -91,11 +193,167 @@ pub fn expect_param_list(pair: Pair<Rule>) -> Vec<AstExpression> {
 
     let mut param_list = Vec::new();
     for pair in pair.into_inner() {
+        // FIXME: No panic
+        if pair.as_rule() == Rule::keyword_param {
+            panic!("Keyword arguments are only supported by objects: {}", pair.as_str());
+        }
         param_list.push(expect_expression(pair));
     }
     param_list
 }
 
+pub fn expect_object_param_list(
+    pair: Pair<Rule>
+) -> (Vec<AstExpression>, Vec<(Identifier, AstExpression)>) {
+    assert_eq!(pair.as_rule(), Rule::param_list);
+
+    let mut param_list = Vec::new();
+    let mut keyword_params = Vec::new();
+    for pair in pair.into_inner() {
+        if pair.as_rule() == Rule::keyword_param {
+            // <id> : <expression>
+            let mut inner = pair.into_inner();
+            let id = expect_id(inner.next().unwrap());
+            let expression = expect_expression(inner.next().unwrap());
+            keyword_params.push((id, expression));
+        } else {
+            param_list.push(expect_expression(pair));
+        }
+    }
+    (param_list, keyword_params)
+}
+
+/// Strips the whitespace and comments that pest sometimes includes at the end
+/// of a statement's span (after a trailing repetition, like in expressions).
+fn trim_trivia_end(text: &str) -> &str {
+    let mut end = 0;
+    let mut quote = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), _) => (),
+            (None, '/') if chars.peek().map(|&(_, c)| c) == Some('/') => {
+                while chars.peek().is_some_and(|&(_, c)| c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            (None, c) if c.is_whitespace() => continue,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, _) => (),
+        }
+        end = index + c.len_utf8();
+    }
+
+    &text[..end]
+}
+
+/// Reads `<statement_list> end`, keeping the comments and blank lines found
+/// between `start` (the end of the block's opening keyword) and the `end`.
+fn expect_block(span: &Span, start: usize, inner: &mut Pairs<Rule>) -> AstStatement {
+    let statement_list = inner.next().unwrap();
+    assert_eq!(statement_list.as_rule(), Rule::statement_list);
+
+    let end_ = inner.next().unwrap();
+    assert_eq!(end_.as_rule(), Rule::end_);
+
+    let text = &span.as_str()[start - span.start()..end_.as_span().start() - span.start()];
+    AstStatement::from_statement_list(statement_list, text, start)
+}
+
+/// Keyword arguments accepted by every object, describing its material.
+pub const MATERIAL_PARAMETERS: &[&str] = &[
+    "color", "texture", "reflectivity", "transparency", "texture_space", "texture_size",
+    "reflectivity_map", "transparency_map", "bump_map", "bump_strength", "normal_map",
+];
+
+/// Keyword arguments accepted by texture(), with the values each one can take.
+pub const TEXTURE_PARAMETERS: &[(&str, &[&str])] = &[
+    ("filter", &["nearest", "bilinear", "bicubic"]),
+    ("wrap", &["repeat", "clamp", "mirror"]),
+    ("mipmaps", &["on", "off"]),
+];
+
+/// How set background() maps a single texture onto the sky.
+pub const BACKGROUND_MAPPINGS: &[&str] = &["equirectangular", "cube"];
+
+/// Keyword arguments accepted by set background(), with the values each one
+/// can take (none for numbers).
+pub const BACKGROUND_PARAMETERS: &[(&str, &[&str])] = &[
+    ("light_samples", &[]),
+    ("irradiance_map", &["on", "off"]),
+];
+
+/// Keyword arguments of set animation(), all numbers. The duration is in
+/// seconds, and gives the number of frames unless that is set itself.
+pub const ANIMATION_PARAMETERS: &[&str] = &["frames", "fps", "duration", "start", "end"];
+
+/// Keyword arguments accepted by each object, on top of MATERIAL_PARAMETERS.
+pub fn object_parameters(name: &str) -> &'static [&'static str] {
+    match name {
+        "sphere" => &["center", "radius"],
+        "cube" => &["center", "length", "uv_mapping"],
+        "plane" => &["normal", "distance", "tile", "tile_offset"],
+        "cylinder" => &["center", "radius", "height"],
+        "cone" => &["center", "radius", "height", "top_radius"],
+        "torus" => &["center", "radius", "tube_radius"],
+        "disc" => &["center", "normal", "radius"],
+        "box" => &["center", "size", "uv_mapping", "rounding"],
+        "quadric" => &["xx", "yy", "zz", "xy", "xz", "yz", "x", "y", "z", "constant", "center"],
+        "ellipsoid" | "paraboloid" => &["center", "radii"],
+        "hyperboloid" => &["center", "radii", "sheets"],
+        "implicit" => &["formula", "center", "radius"],
+        "capsule" => &["start", "end", "radius"],
+        "smooth" => &["a", "b", "operator", "blend"],
+        "twist" => &["shape", "amount"],
+        "repeat" => &["shape", "spacing", "count"],
+        "csg" => &["a", "b", "operator", "operand_materials"],
+        // Any number of objects, by position only
+        "csg_union" | "csg_intersection" | "csg_difference" | "csg_xor" | "group" => &["operand_materials"],
+        kind => panic!("Unknown shape type in grammar: {}", kind),
+    }
+}
+
+/// The `uv_mapping` of cubes and boxes
+fn cube_mapping(value: Option<Value>) -> CubeMapping {
+    match value {
+        Some(value) => match value.to_string().as_str() {
+            "faces" => CubeMapping::Faces,
+            "cross" => CubeMapping::Cross,
+            // FIXME: No panic
+            mapping => panic!("Unknown cube UV mapping: {}", mapping),
+        },
+        None => CubeMapping::Faces,
+    }
+}
+
+/// The `operand_materials` of CSGs and groups
+fn operand_materials(value: Option<Value>, default: bool) -> bool {
+    match value {
+        Some(value) => match value.to_string().as_str() {
+            "on" => true,
+            "off" => false,
+            // FIXME: No panic
+            option => panic!("operand_materials is 'on' or 'off', not '{}'", option),
+        },
+        None => default,
+    }
+}
+
+/// The `operator` of CSGs and smooth blends
+fn csg_operator(operator: Option<String>) -> CSGOperator {
+    match operator.as_deref().unwrap_or("union") {
+        "union" => CSGOperator::Union,
+        "intersection" => CSGOperator::Intersection,
+        "difference" => CSGOperator::Difference,
+        "xor" => CSGOperator::Xor,
+        // FIXME: No panic
+        operator => panic!("Unknown CSG operator: {}", operator),
+    }
+}
+
 pub fn expect_expression(pair: Pair<Rule>) -> AstExpression {
     assert_eq!(pair.as_rule(), Rule::expression);
 
@@ -110,6 +368,7 @@ struct ValuesByType {
     objects: VecDeque<Shape>,
     colors: VecDeque<Color>,
     textures: VecDeque<Texture>,
+    keywords: HashMap<Identifier, Value>,
 }
 
 impl ValuesByType {
@@ -130,20 +389,102 @@ impl ValuesByType {
                 // FIXME: No panic
                 Value::Texture(texture) => values.textures.push_back(texture),
                 Value::Boolean(_) => panic!("Unexpected argument type: boolean"),
+                Value::List(_) => panic!("Unexpected argument type: list"),
             };
         }
 
         values
     }
 
-    fn assert_empty(&self) {
-        // FIXME: No assert
-        assert_eq!(self.numbers.len(), 0);
-        assert_eq!(self.strings.len(), 0);
-        assert_eq!(self.vectors.len(), 0);
-        assert_eq!(self.objects.len(), 0);
-        assert_eq!(self.colors.len(), 0);
-        assert_eq!(self.textures.len(), 0);
+    fn set_keywords(&mut self, name: &str, keyword_list: impl Iterator<Item=(Identifier, Value)>) {
+        let parameters = object_parameters(name);
+
+        for (id, value) in keyword_list {
+            // FIXME: No panic
+            if !parameters.contains(&id.as_str()) && !MATERIAL_PARAMETERS.contains(&id.as_str()) {
+                panic!(
+                    "Unknown argument '{}' for {}(), expected one of: {}",
+                    id, name, [parameters, MATERIAL_PARAMETERS].concat().join(", ")
+                );
+            }
+            if self.keywords.insert(id.clone(), value).is_some() {
+                panic!("Argument '{}' given more than once to {}()", id, name);
+            }
+        }
+    }
+
+    // Each of these prefers the keyword argument, and falls back to the next
+    // positional argument of the right type.
+
+    fn number(&mut self, key: &str) -> Option<f64> {
+        match self.keywords.remove(key) {
+            Some(value) => Some(value.to_number()),
+            None => self.numbers.pop_front(),
+        }
+    }
+
+    fn string(&mut self, key: &str) -> Option<String> {
+        match self.keywords.remove(key) {
+            Some(value) => Some(value.to_string()),
+            None => self.strings.pop_front(),
+        }
+    }
+
+    fn vector(&mut self, key: &str) -> Option<Vector> {
+        match self.keywords.remove(key) {
+            Some(value) => Some(value.to_vector()),
+            None => self.vectors.pop_front(),
+        }
+    }
+
+    fn object(&mut self, key: &str) -> Option<Shape> {
+        match self.keywords.remove(key) {
+            Some(Value::Object(shape)) => Some(shape),
+            // FIXME: No panic
+            Some(value) => panic!("Expected an object for '{}', got {:?}", key, value),
+            None => self.objects.pop_front(),
+        }
+    }
+
+    fn color(&mut self, key: &str) -> Option<Color> {
+        match self.keywords.remove(key) {
+            Some(Value::Color { r, g, b, a }) => Some(Color::new(r, g, b, a)),
+            // FIXME: No panic
+            Some(value) => panic!("Expected a color for '{}', got {:?}", key, value),
+            None => self.colors.pop_front(),
+        }
+    }
+
+    fn texture(&mut self, key: &str) -> Option<Texture> {
+        match self.keywords.remove(key) {
+            Some(Value::Texture(texture)) => Some(texture),
+            // FIXME: No panic
+            Some(value) => panic!("Expected a texture for '{}', got {:?}", key, value),
+            None => self.textures.pop_front(),
+        }
+    }
+
+    /// For arguments that can only be given by name.
+    fn keyword(&mut self, key: &str) -> Option<Value> {
+        self.keywords.remove(key)
+    }
+
+    /// Arguments left over once everything was taken, like a `color:` next
+    /// to a `texture:`, which would otherwise be silently ignored.
+    fn report_unused(&self, name: &str, context: &mut SceneContext) {
+        let mut keywords: Vec<_> = self.keywords.keys().collect();
+        keywords.sort();
+        for id in keywords {
+            context.report_error(format!(
+                "Argument '{}' for {}() conflicts with another one and is unused", id, name
+            ));
+        }
+
+        let positional = self.numbers.len() + self.strings.len() + self.vectors.len() +
+            self.objects.len() + self.colors.len() + self.textures.len();
+        if positional > 0 {
+            context.report_error(format!("{}() got {} unused positional arguments", name, positional));
+        }
     }
 }
 
@@ -155,7 +496,7 @@ impl AstStatement {
                     statement.execute(context);
                 }
             }
-            AstStatement::Assignment { local, id, expression } => {
+            AstStatement::Assignment { local, id, expression, .. } => {
                 let value = expression.evaluate(context);
                 if *local {
                     context.locals().insert(id.to_string(), value);
@@ -166,7 +507,7 @@ impl AstStatement {
             AstStatement::Function(function) => {
                 context.add_function(function.id.clone(), function.clone());
             }
-            AstStatement::CallFunction { id, param_list } => {
+            AstStatement::CallFunction { id, param_list, .. } => {
                 let value_list: Vec<_> = param_list
                     .into_iter()
                     .map(|param| param.evaluate(context))
@@ -190,18 +531,37 @@ impl AstStatement {
                 }
             }
             AstStatement::Transformation {
-                x, y, z,
+                arguments,
                 transformation,
                 statement,
+                ..
             } => {
-                let x = x.evaluate(context).to_number();
-                let y = y.evaluate(context).to_number();
-                let z = z.evaluate(context).to_number();
+                if arguments.len() != transformation.argument_count() {
+                    // FIXME: No panic
+                    panic!(
+                        "{}() takes {} arguments, got {}",
+                        transformation.name(), transformation.argument_count(), arguments.len()
+                    );
+                }
+                let a: Vec<_> = arguments.iter().map(|argument| argument.evaluate(context)).collect();
+                let n = |i: usize| a[i].to_number();
 
                 let matrix_transformation = match transformation {
-                    Transformation::Translate => MatrixTransformation::create_translation_matrix(x, y, z),
-                    Transformation::Rotate => MatrixTransformation::create_rotation_matrix(x, y, z),
-                    Transformation::Scale => MatrixTransformation::create_scaling_matrix(x, y, z),
+                    Transformation::Translate => MatrixTransformation::create_translation_matrix(n(0), n(1), n(2)),
+                    Transformation::Rotate => MatrixTransformation::create_rotation_matrix(n(0), n(1), n(2)),
+                    Transformation::Scale => MatrixTransformation::create_scaling_matrix(n(0), n(1), n(2)),
+                    Transformation::RotateAxis => {
+                        MatrixTransformation::create_axis_angle_rotation_matrix(a[0].to_vector(), n(1))
+                    }
+                    Transformation::RotateQuaternion => MatrixTransformation::create_quaternion_rotation_matrix(
+                        Quaternion::new(n(0), n(1), n(2), n(3)).normalized()
+                    ),
+                    Transformation::Shear => {
+                        MatrixTransformation::create_shear_matrix(n(0), n(1), n(2), n(3), n(4), n(5))
+                    }
+                    Transformation::Orient => MatrixTransformation::create_look_at_matrix(
+                        a[0].to_vector(), a[1].to_vector(), Vector::new(0.0, 1.0, 0.0)
+                    ),
                 };
 
                 // FIXME: RAII
@@ -217,16 +577,28 @@ impl AstStatement {
                     .transformation_stack_mut()
                     .pop_transformation();
             }
-            AstStatement::If { condition, body } => {
+            AstStatement::If { condition, body, .. } => {
                 if condition.evaluate(context).to_boolean() {
                     body.execute(context);
                 }
             }
-            AstStatement::While { condition, body } => {
+            AstStatement::While { condition, body, .. } => {
                 while condition.evaluate(context).to_boolean() {
                     body.execute(context);
                 }
             }
+            AstStatement::For { id, iterable, body, .. } => {
+                let list = match iterable.evaluate(context) {
+                    Value::List(list) => list,
+                    // FIXME: No panic
+                    value => panic!("Cannot iterate over {:?}", value),
+                };
+
+                for value in list {
+                    context.locals().insert(id.to_string(), value);
+                    body.execute(context);
+                }
+            }
             AstStatement::AppendLight { param_list } => {
                 use crate::raytracer::transformation::Transformation;
 
@@ -249,35 +621,175 @@ impl AstStatement {
 
                 context.ray_tracer().add_light(PointLight::new(point, color, fade_distance));
             }
-            AstStatement::SetCamera { position } => {
-                use crate::raytracer::transformation::Transformation;
-
+            AstStatement::Comment { .. } | AstStatement::BlankLine => {}
+            AstStatement::SetCamera { position, look_at } => {
+                // Both are transformed by the ray tracer, once. The position
+                // used to be transformed here as well, so scenes that turned
+                // the camera by half the angle they meant now need the full one.
                 let position = position.evaluate(context).to_vector();
+                let look_at = look_at.as_ref().map(|look_at| look_at.evaluate(context).to_vector());
 
-                let position = context
-                    .ray_tracer()
-                    .get_current_transformation()
-                    .transform_vector(position);
+                context.ray_tracer().set_camera_from_vector(position, look_at);
+            }
+            AstStatement::SetBackground { param_list, keyword_params, .. } => {
+                let value_list = param_list
+                    .iter().map(|param| param.evaluate(context));
+
+                let mut values = ValuesByType::from_value_list(value_list);
+
+                let intensity = values.numbers.pop_front().unwrap_or(1.0);
+                let mapping = values.strings.pop_front()
+                    .unwrap_or_else(|| "equirectangular".to_string());
+                let mut textures: Vec<_> = values.textures
+                    .drain(..)
+                    .map(|texture| texture.texture().clone_box())
+                    .collect();
+                let colors: Vec<_> = values.colors.drain(..).collect();
+
+                // FIXME: No panic
+                let background = match (colors.as_slice(), textures.len(), mapping.as_str()) {
+                    ([color], 0, _) => Background::Color(*color),
+                    ([bottom, top], 0, _) => Background::Gradient { bottom: *bottom, top: *top },
+                    ([], 1, "equirectangular") => Background::Equirectangular {
+                        texture: textures.remove(0),
+                        intensity,
+                    },
+                    ([], 1, "cube") => Background::CubeCross {
+                        texture: textures.remove(0),
+                        intensity,
+                    },
+                    ([], 6, _) => Background::CubeMap { faces: textures, intensity },
+                    _ => panic!(
+                        "set background() expects a color, two colors for a gradient, \
+                        a texture or six cube map textures"
+                    ),
+                };
+
+                values.report_unused("set background", context);
+
+                // Without samples the background doesn't light anything
+                let mut light_samples = 0;
+                let mut irradiance_map = false;
+
+                for (id, param) in keyword_params {
+                    // FIXME: No panic
+                    match (id.as_str(), param.evaluate(context)) {
+                        ("light_samples", Value::Number(samples)) => light_samples = samples as usize,
+                        ("irradiance_map", Value::String(option)) if option == "on" => irradiance_map = true,
+                        ("irradiance_map", Value::String(option)) if option == "off" => irradiance_map = false,
+                        (id, value) => panic!("Unknown background option {}: {:?}", id, value),
+                    }
+                }
+
+                let environment_light = if light_samples > 0 {
+                    let mut environment_light = EnvironmentLight::new(&background, light_samples);
+                    if irradiance_map {
+                        environment_light.compute_irradiance_map();
+                    }
+                    Some(environment_light)
+                } else {
+                    None
+                };
+
+                context.ray_tracer().set_background(background);
+                context.ray_tracer().set_environment_light(environment_light);
+            }
+            AstStatement::SetAnimation { param_list, keyword_params, .. } => {
+                if !param_list.is_empty() {
+                    // FIXME: No panic
+                    panic!("set animation() only takes keyword arguments");
+                }
+
+                let default = Animation::default();
+                let mut animation = default;
+                let mut frames = None;
+                let mut duration = default.frames as f64 / default.fps;
+
+                for (id, param) in keyword_params {
+                    // FIXME: No panic
+                    match (id.as_str(), param.evaluate(context)) {
+                        ("frames", Value::Number(number)) => frames = Some(number as usize),
+                        ("fps", Value::Number(fps)) => animation.fps = fps,
+                        ("duration", Value::Number(seconds)) => duration = seconds,
+                        ("start", Value::Number(start)) => animation.start = start,
+                        ("end", Value::Number(end)) => animation.end = end,
+                        (id, value) => panic!("Unknown animation option {}: {:?}", id, value),
+                    }
+                }
 
-                context.ray_tracer().set_camera_from_vector(position);
+                animation.frames = frames.unwrap_or_else(|| (duration * animation.fps).round() as usize);
+                // Also catches a NaN fps, which no comparison is true for
+                let positive_fps = animation.fps > 0.0 && animation.fps.is_finite();
+                if animation.frames == 0 || !positive_fps {
+                    // FIXME: No panic
+                    panic!("An animation needs at least one frame and a positive fps");
+                }
+
+                context.ray_tracer().set_animation(animation);
+            }
+        }
+    }
+
+    /// Converts a statement_list, where `text` is the source surrounding it
+    /// (starting at `offset`); whatever lies between the statements in there
+    /// can only be whitespace and comments.
+    pub fn from_statement_list(pair: Pair<Rule>, text: &str, offset: usize) -> Self {
+        assert_eq!(pair.as_rule(), Rule::statement_list);
+
+        let mut statement_list = Vec::new();
+        let mut position = offset;
+
+        for pair in pair.into_inner() {
+            let span = pair.as_span();
+            Self::push_trivia(&text[position - offset..span.start() - offset], &mut statement_list);
+            statement_list.push(AstStatement::from_pest(pair));
+            position = span.start() + trim_trivia_end(span.as_str()).len();
+        }
+
+        Self::push_trivia(&text[position - offset..], &mut statement_list);
+        if let Some(AstStatement::BlankLine) = statement_list.last() {
+            statement_list.pop();
+        }
+
+        AstStatement::StatementList(statement_list)
+    }
+
+    fn push_trivia(text: &str, statement_list: &mut Vec<AstStatement>) {
+        // The first line is the rest of the previous statement's line, and the
+        // last one is the indentation of the next statement.
+        let lines: Vec<&str> = text.split('\n').collect();
+
+        for (line_number, line) in lines.iter().enumerate() {
+            let after_statement = match statement_list.last() {
+                None | Some(AstStatement::Comment { .. }) | Some(AstStatement::BlankLine) => false,
+                Some(_) => true,
+            };
+
+            if let Some(start) = line.find("//") {
+                statement_list.push(AstStatement::Comment {
+                    text: line[start..].trim_end().to_string(),
+                    trailing: line_number == 0 && after_statement,
+                });
+            } else if line.trim().is_empty() && line_number != 0 && line_number != lines.len() - 1 {
+                match statement_list.last() {
+                    None | Some(AstStatement::BlankLine) => (),
+                    Some(_) => statement_list.push(AstStatement::BlankLine),
+                }
             }
         }
     }
 
     pub fn from_pest(pair: Pair<Rule>) -> Self {
         let rule = pair.as_rule();
-        let mut inner = pair.into_inner();
+        let span = pair.as_span();
 
-        match rule {
-            Rule::statement_list => {
-                let mut statement_list = Vec::new();
+        if rule == Rule::statement_list {
+            return AstStatement::from_statement_list(pair, span.as_str(), span.start());
+        }
 
-                for pair in inner {
-                    statement_list.push(AstStatement::from_pest(pair));
-                }
+        let mut inner = pair.into_inner();
 
-                AstStatement::StatementList(statement_list)
-            }
+        match rule {
             Rule::assignment_statement => {
                 let local = if let Some(Rule::local_) = inner.peek().map(|pair| pair.as_rule()) {
                     inner.next().unwrap();
@@ -295,7 +807,8 @@ impl AstStatement {
                 AstStatement::Assignment {
                     local,
                     id: id.as_str().to_string(),
-                    expression: AstExpression::from_pest(expr)
+                    expression: AstExpression::from_pest(expr),
+                    position: Position::from_span(&span),
                 }
             }
             Rule::function_statement => {
@@ -303,28 +816,24 @@ impl AstStatement {
 
                 assert_eq!(inner.next().unwrap().as_rule(), Rule::function_);
 
-                let function_id = expect_id(inner.next().unwrap());
+                let function_id = inner.next().unwrap();
+                let mut block_start = function_id.as_span().end();
+                let function_id = expect_id(function_id);
                 let mut param_list = Vec::new();
-                let statement_list;
 
-                loop {
+                while let Some(Rule::id) = inner.peek().map(|pair| pair.as_rule()) {
                     let pair = inner.next().unwrap();
-
-                    if pair.as_rule() == Rule::id {
-                        param_list.push(expect_id(pair));
-                    } else if pair.as_rule() == Rule::statement_list {
-                        statement_list = AstStatement::from_pest(pair);
-                        break;
-                    } else {
-                        unreachable!()
-                    }
+                    block_start = pair.as_span().end();
+                    param_list.push(expect_id(pair));
                 }
 
-                assert_eq!(inner.next().unwrap().as_rule(), Rule::end_);
+                let statement_list = expect_block(&span, block_start, &mut inner);
+
                 AstStatement::Function(Function {
                     id: function_id,
                     param_list,
                     body: Rc::new(statement_list),
+                    position: Position::from_span(&span),
                 })
             }
             Rule::call_statement => {
@@ -338,6 +847,7 @@ impl AstStatement {
                 AstStatement::CallFunction {
                     id,
                     param_list,
+                    position: Position::from_span(&span),
                 }
             }
             Rule::command_statement => {
@@ -359,60 +869,82 @@ impl AstStatement {
                 let transformation = inner.next().unwrap();
                 assert_eq!(transformation.as_rule(), Rule::transformation_);
 
-                let x = expect_expression(inner.next().unwrap());
-                let y = expect_expression(inner.next().unwrap());
-                let z = expect_expression(inner.next().unwrap());
-
-                let statement = inner.next().unwrap();
+                // The arguments, then the transformed statement
+                let mut pairs: Vec<_> = inner.collect();
+                let statement = pairs.pop().unwrap();
+                let arguments = pairs.into_iter().map(expect_expression).collect();
 
                 let transformation = match transformation.as_str() {
                     "translate" => Transformation::Translate,
                     "scale" => Transformation::Scale,
                     "rotate" => Transformation::Rotate,
+                    "rotate_axis" => Transformation::RotateAxis,
+                    "rotate_quaternion" => Transformation::RotateQuaternion,
+                    "shear" => Transformation::Shear,
+                    "orient" => Transformation::Orient,
                     transformation => panic!("Unknown transformation '{}'", transformation),
                 };
 
                 AstStatement::Transformation {
-                    x,
-                    y,
-                    z,
+                    arguments,
                     transformation,
                     statement: Box::new(AstStatement::from_pest(statement)),
+                    position: Position::from_span(&span),
                 }
             }
             Rule::do_statement => {
                 let do_ = inner.next().unwrap();
                 assert_eq!(do_.as_rule(), Rule::do_);
 
-                let statement_list = inner.next().unwrap();
-                assert_eq!(statement_list.as_rule(), Rule::statement_list);
-
-                let end_ = inner.next().unwrap();
-                assert_eq!(end_.as_rule(), Rule::end_);
-
-                AstStatement::from_pest(statement_list)
+                expect_block(&span, do_.as_span().end(), &mut inner)
             }
             Rule::if_statement => {
                 // if <bool_expression> then <statement_list> end
 
                 assert_eq!(inner.next().unwrap().as_rule(), Rule::if_);
                 let condition = AstExpression::from_pest(inner.next().unwrap());
-                assert_eq!(inner.next().unwrap().as_rule(), Rule::then_);
-                let statement_list = AstStatement::from_pest(inner.next().unwrap());
-                assert_eq!(inner.next().unwrap().as_rule(), Rule::end_);
-
-                AstStatement::If { condition, body: Box::new(statement_list) }
+                let then_ = inner.next().unwrap();
+                assert_eq!(then_.as_rule(), Rule::then_);
+                let statement_list = expect_block(&span, then_.as_span().end(), &mut inner);
+
+                AstStatement::If {
+                    condition,
+                    body: Box::new(statement_list),
+                    position: Position::from_span(&span),
+                }
             }
             Rule::while_statement => {
                 // while <bool_expression> do <statement_list> end
 
                 assert_eq!(inner.next().unwrap().as_rule(), Rule::while_);
                 let condition = AstExpression::from_pest(inner.next().unwrap());
-                assert_eq!(inner.next().unwrap().as_rule(), Rule::do_);
-                let statement_list = AstStatement::from_pest(inner.next().unwrap());
-                assert_eq!(inner.next().unwrap().as_rule(), Rule::end_);
+                let do_ = inner.next().unwrap();
+                assert_eq!(do_.as_rule(), Rule::do_);
+                let statement_list = expect_block(&span, do_.as_span().end(), &mut inner);
 
-                AstStatement::While { condition, body: Box::new(statement_list) }
+                AstStatement::While {
+                    condition,
+                    body: Box::new(statement_list),
+                    position: Position::from_span(&span),
+                }
+            }
+            Rule::for_statement => {
+                // for <id> in <range or expression> do <statement_list> end
+
+                assert_eq!(inner.next().unwrap().as_rule(), Rule::for_);
+                let id = expect_id(inner.next().unwrap());
+                assert_eq!(inner.next().unwrap().as_rule(), Rule::in_);
+                let iterable = AstExpression::from_pest(inner.next().unwrap());
+                let do_ = inner.next().unwrap();
+                assert_eq!(do_.as_rule(), Rule::do_);
+                let statement_list = expect_block(&span, do_.as_span().end(), &mut inner);
+
+                AstStatement::For {
+                    id,
+                    iterable,
+                    body: Box::new(statement_list),
+                    position: Position::from_span(&span),
+                }
             }
             Rule::append_light_statement => {
                 // append_light ( <param_list> )
@@ -423,12 +955,37 @@ impl AstStatement {
                 AstStatement::AppendLight { param_list }
             }
             Rule::set_camera_statement => {
-                // set_camera ( <expr> )
+                // set_camera ( <expr> [, look_at: <expr>] )
 
                 assert_eq!(inner.next().unwrap().as_rule(), Rule::set_camera_);
                 let position = expect_expression(inner.next().unwrap());
+                let look_at = inner.next().map(expect_expression);
 
-                AstStatement::SetCamera { position }
+                AstStatement::SetCamera { position, look_at }
+            }
+            Rule::set_animation_statement => {
+                // set_animation ( <param_list> )
+
+                assert_eq!(inner.next().unwrap().as_rule(), Rule::set_animation_);
+                let (param_list, keyword_params) = expect_object_param_list(inner.next().unwrap());
+
+                AstStatement::SetAnimation {
+                    param_list,
+                    keyword_params,
+                    position: Position::from_span(&span),
+                }
+            }
+            Rule::set_background_statement => {
+                // set_background ( <param_list> )
+
+                assert_eq!(inner.next().unwrap().as_rule(), Rule::set_background_);
+                let (param_list, keyword_params) = expect_object_param_list(inner.next().unwrap());
+
+                AstStatement::SetBackground {
+                    param_list,
+                    keyword_params,
+                    position: Position::from_span(&span),
+                }
             }
             rule => unimplemented!("Unknown statement rule {:?}", rule),
         }
@@ -439,7 +996,7 @@ impl AstExpression {
     pub fn evaluate(&self, context: &mut SceneContext) -> Value {
         match self {
             AstExpression::Value(value) => value.clone(),
-            AstExpression::Reference(id) => {
+            AstExpression::Reference { id, .. } => {
                 if let Some(local) = context.locals().get(id) {
                     local.clone()
                 } else if let Some(global) = context.globals().get(id) {
@@ -463,72 +1020,314 @@ impl AstExpression {
 
                 Value::Color { r, g, b, a: 1.0 }
             }
-            AstExpression::Object { name, param_list } => {
-                let value_list = param_list
-                    .iter().map(|param| param.evaluate(context));
+            AstExpression::Object { name, param_list, keyword_params, .. } => {
+                // The shapes of a group are made in its own space, which its
+                // transformation then places
+                let transformation_stack = if name == "group" {
+                    Some(std::mem::replace(
+                        context.ray_tracer().transformation_stack_mut(),
+                        TransformationStack::new_with_identity(),
+                    ))
+                } else {
+                    None
+                };
 
-                let mut values = ValuesByType::from_value_list(value_list);
+                let value_list: Vec<_> = param_list
+                    .iter().map(|param| param.evaluate(context)).collect();
+
+                if let Some(transformation_stack) = transformation_stack {
+                    *context.ray_tracer().transformation_stack_mut() = transformation_stack;
+                }
+
+                let mut values = ValuesByType::from_value_list(value_list.into_iter());
+
+                let keyword_list: Vec<_> = keyword_params
+                    .iter()
+                    .map(|(id, param)| (id.clone(), param.evaluate(context)))
+                    .collect();
+
+                values.set_keywords(name, keyword_list.into_iter());
 
                 let shape_kind = match name.as_str() {
                     "sphere" => ShapeKind::Sphere {
-                        center: values.vectors.pop_front().unwrap_or(Vector::new(0.0, 0.0, 0.0)),
-                        radius: values.numbers.pop_front().unwrap_or(1.0),
+                        center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                        radius: values.number("radius").unwrap_or(1.0),
                     },
                     "cube" => ShapeKind::Cube {
-                        center: values.vectors.pop_front().unwrap_or(Vector::new(0.0, 0.0, 0.0)),
-                        length: values.numbers.pop_front().unwrap_or(1.0),
+                        center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                        length: values.number("length").unwrap_or(1.0),
+                        uv_mapping: cube_mapping(values.keyword("uv_mapping")),
                     },
                     "plane" => ShapeKind::Plane {
-                        normal: values.vectors.pop_front().unwrap_or(Vector::new(0.0, 1.0, 0.0)),
-                        distance: values.numbers.pop_front().unwrap_or(1.0),
+                        normal: values.vector("normal").unwrap_or(Vector::new(0.0, 1.0, 0.0)),
+                        distance: values.number("distance").unwrap_or(1.0),
+                        tile_size: values.keyword("tile").map_or(50.0, |tile| tile.to_number()),
+                        tile_offset: match values.keyword("tile_offset") {
+                            Some(offset) => {
+                                let offset = offset.to_vector();
+                                UV { u: offset.x, v: offset.y }
+                            }
+                            None => UV { u: 0.0, v: 0.0 },
+                        },
                     },
-                    "csg" => {
-                        let operator = values.strings.pop_front();
-                        let operator = operator
-                            .as_ref()
-                            .map(|string| string.as_str())
-                            .unwrap_or("union");
-                        ShapeKind::CSG {
-                            operator: match operator {
-                                "union" => CSGOperator::Union,
-                                "intersection" => CSGOperator::Intersection,
-                                "difference" => CSGOperator::Difference,
+                    "cylinder" => ShapeKind::Cylinder {
+                        center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                        radius: values.number("radius").unwrap_or(1.0),
+                        height: values.number("height").unwrap_or(1.0),
+                    },
+                    "cone" => ShapeKind::Cone {
+                        center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                        radius: values.number("radius").unwrap_or(1.0),
+                        height: values.number("height").unwrap_or(1.0),
+                        top_radius: values.number("top_radius").unwrap_or(0.0),
+                    },
+                    "torus" => ShapeKind::Torus {
+                        center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                        radius: values.number("radius").unwrap_or(1.0),
+                        tube_radius: values.number("tube_radius").unwrap_or(0.25),
+                    },
+                    "disc" => ShapeKind::Disc {
+                        center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                        normal: values.vector("normal").unwrap_or(Vector::new(0.0, 1.0, 0.0)),
+                        radius: values.number("radius").unwrap_or(1.0),
+                    },
+                    "box" => ShapeKind::Box {
+                        center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                        size: values.vector("size").unwrap_or(Vector::new(1.0, 1.0, 1.0)),
+                        uv_mapping: cube_mapping(values.keyword("uv_mapping")),
+                        rounding: values.keyword("rounding").map_or(0.0, |rounding| rounding.to_number()),
+                    },
+                    "quadric" => {
+                        let mut coefficients = [0.0; 10];
+                        for (coefficient, name) in coefficients.iter_mut().zip(object_parameters(name)) {
+                            *coefficient = values.number(name).unwrap_or(0.0);
+                        }
+
+                        ShapeKind::Quadric {
+                            center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                            coefficients,
+                        }
+                    },
+                    "ellipsoid" | "paraboloid" | "hyperboloid" => {
+                        let center = values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0));
+                        let mut radii = values.vector("radii").unwrap_or(Vector::new(1.0, 1.0, 1.0));
+
+                        // A zero radius would divide by zero below
+                        let usable = |radius: f64| radius != 0.0 && radius.is_finite();
+                        if !(usable(radii.x) && usable(radii.y) && usable(radii.z)) {
+                            context.report_error(format!("{}() needs radii that are not zero", name));
+                            let fix = |radius: f64| if usable(radius) { radius } else { 1.0 };
+                            radii = Vector::new(fix(radii.x), fix(radii.y), fix(radii.z));
+                        }
+
+                        let (x, y, z) = (1.0 / (radii.x * radii.x), 1.0 / (radii.y * radii.y), 1.0 / (radii.z * radii.z));
+
+                        // Around the Y axis, with the inside where the sum is negative
+                        let coefficients = match name.as_str() {
+                            "ellipsoid" => [x, y, z, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+                            // Opening upwards from the center
+                            "paraboloid" => [x, 0.0, z, 0.0, 0.0, 0.0, 0.0, -1.0 / radii.y, 0.0, 0.0],
+                            _ => match values.number("sheets").unwrap_or(1.0) as i64 {
+                                1 => [x, -y, z, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+                                2 => [x, -y, z, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
                                 // FIXME: No panic
-                                operator => panic!("Unknown CSG operator: {}", operator),
+                                sheets => panic!("A hyperboloid has 1 or 2 sheets, not {}", sheets),
                             },
-                            // FIXME: No expect
-                            a: Box::new(values.objects.pop_front().expect("Expected object 1!")),
-                            b: Box::new(values.objects.pop_front().expect("Expected object 2!")),
+                        };
+
+                        ShapeKind::Quadric { center, coefficients }
+                    },
+                    "implicit" => {
+                        let formula = match values.string("formula") {
+                            Some(source) => Formula::compile(&source, context).unwrap_or_else(|err| {
+                                context.report_error(err);
+                                Formula::empty()
+                            }),
+                            None => {
+                                context.report_error("implicit() needs a formula".to_string());
+                                Formula::empty()
+                            }
+                        };
+
+                        ShapeKind::Implicit {
+                            center: values.vector("center").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                            radius: values.number("radius").unwrap_or(1.0),
+                            formula,
                         }
                     },
+                    "csg" => ShapeKind::CSG {
+                        operator: csg_operator(values.string("operator")),
+                        // FIXME: No expect
+                        shapes: vec![
+                            values.object("a").expect("Expected object 1!"),
+                            values.object("b").expect("Expected object 2!"),
+                        ],
+                        operand_materials: operand_materials(values.keyword("operand_materials"), false),
+                    },
+                    "csg_union" | "csg_intersection" | "csg_difference" | "csg_xor" => {
+                        // FIXME: No panic
+                        if values.objects.is_empty() {
+                            panic!("{}() needs at least one object", name);
+                        }
+
+                        ShapeKind::CSG {
+                            operator: csg_operator(Some(name["csg_".len()..].to_string())),
+                            shapes: values.objects.drain(..).collect(),
+                            operand_materials: operand_materials(values.keyword("operand_materials"), false),
+                        }
+                    },
+                    // Like a CSG, it has its own material unless told otherwise
+                    "group" => ShapeKind::Group {
+                        shapes: values.objects.drain(..).collect(),
+                        operand_materials: operand_materials(values.keyword("operand_materials"), false),
+                    },
+                    "capsule" => ShapeKind::Capsule {
+                        start: values.vector("start").unwrap_or(Vector::new(0.0, -0.5, 0.0)),
+                        end: values.vector("end").unwrap_or(Vector::new(0.0, 0.5, 0.0)),
+                        radius: values.number("radius").unwrap_or(0.5),
+                    },
+                    "smooth" => ShapeKind::Smooth {
+                        operator: csg_operator(values.string("operator")),
+                        // FIXME: No expect
+                        a: Box::new(values.object("a").expect("Expected object 1!")),
+                        b: Box::new(values.object("b").expect("Expected object 2!")),
+                        blend: values.number("blend").unwrap_or(1.0),
+                    },
+                    "twist" => ShapeKind::Twist {
+                        // FIXME: No expect
+                        shape: Box::new(values.object("shape").expect("Expected an object to twist!")),
+                        amount: values.number("amount").unwrap_or(0.0),
+                    },
+                    "repeat" => ShapeKind::Repeat {
+                        // FIXME: No expect
+                        shape: Box::new(values.object("shape").expect("Expected an object to repeat!")),
+                        spacing: values.vector("spacing").unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+                        count: values.vector("count").unwrap_or(Vector::new(1.0, 1.0, 1.0)),
+                    },
                     kind => panic!("Unknown shape type in grammar: {}", kind),
                 };
 
                 let transformation =
                     context.ray_tracer().get_current_transformation().clone();
 
-                let material = if let Some(texture) = values.textures.pop_front() {
+                let material = if let Some(texture) = values.texture("texture") {
                     Material::Texture(texture)
                 } else {
-                    Material::Color(values.colors.pop_front().unwrap_or(Color::BLACK))
+                    Material::Color(values.color("color").unwrap_or(Color::BLACK))
+                };
+
+                let texture_size = values.keyword("texture_size").map_or(1.0, |size| size.to_number());
+                let texture_space = match values.keyword("texture_space") {
+                    Some(value) => match value.to_string().as_str() {
+                        "uv" => TextureSpace::UV,
+                        "object" => TextureSpace::Object { size: texture_size },
+                        // FIXME: No panic
+                        space => panic!("Unknown texture space: {}", space),
+                    },
+                    None => TextureSpace::UV,
+                };
+
+                let maps = TextureMaps {
+                    reflectivity: values.keyword("reflectivity_map").map(|map| map.to_texture()),
+                    transparency: values.keyword("transparency_map").map(|map| map.to_texture()),
+                    bump: values.keyword("bump_map").map(|map| map.to_texture()),
+                    bump_strength: values.keyword("bump_strength").map_or(1.0, |strength| strength.to_number()),
+                    normal: values.keyword("normal_map").map(|map| map.to_texture()),
                 };
 
                 let object = Shape {
                     material,
-                    reflectivity: values.numbers.pop_front().unwrap_or(0.0),
-                    transparency: values.numbers.pop_front().unwrap_or(0.0),
+                    reflectivity: values.number("reflectivity").unwrap_or(0.0),
+                    transparency: values.number("transparency").unwrap_or(0.0),
                     kind: shape_kind,
                     transformation,
+                    texture_space,
+                    maps,
                 };
 
-                // FIXME: No assert
-                values.assert_empty();
+                values.report_unused(name, context);
 
                 Value::Object(object)
             }
-            AstExpression::Texture { texture_file } => {
+            AstExpression::Texture { texture_file, keyword_params, .. } => {
                 let texture_file = texture_file.evaluate(context).to_string();
-                Value::Texture(Texture::from_file(&texture_file))
+                let mut pixmap_texture = Texture::load_pixmap(&texture_file)
+                    .unwrap_or_else(|err| {
+                        context.report_error(err.to_string());
+                        Texture::missing()
+                    });
+
+                for (id, param) in keyword_params {
+                    let option = param.evaluate(context).to_string();
+
+                    // FIXME: No panic
+                    match (id.as_str(), option.as_str()) {
+                        ("filter", "nearest") => pixmap_texture.set_filter(Filter::Nearest),
+                        ("filter", "bilinear") => pixmap_texture.set_filter(Filter::Bilinear),
+                        ("filter", "bicubic") => pixmap_texture.set_filter(Filter::Bicubic),
+                        ("wrap", "repeat") => pixmap_texture.set_wrap(Wrap::Repeat),
+                        ("wrap", "clamp") => pixmap_texture.set_wrap(Wrap::Clamp),
+                        ("wrap", "mirror") => pixmap_texture.set_wrap(Wrap::Mirror),
+                        ("mipmaps", "on") => pixmap_texture.set_mipmaps(true),
+                        ("mipmaps", "off") => pixmap_texture.set_mipmaps(false),
+                        (id, option) => panic!("Unknown texture option {}: '{}'", id, option),
+                    }
+                }
+
+                Value::Texture(Texture::new(Box::new(pixmap_texture), &texture_file))
+            }
+            AstExpression::FunctionCall { id, param_list, .. } => {
+                let value_list: Vec<_> = param_list
+                    .iter()
+                    .map(|param| param.evaluate(context))
+                    .collect();
+
+                match find_builtin(id) {
+                    Some(builtin) => builtin.call(&value_list),
+                    // FIXME: No panic
+                    None => panic!("Unknown function {}()", id),
+                }
+            }
+            AstExpression::List(param_list) => {
+                let value_list = param_list
+                    .iter()
+                    .map(|param| param.evaluate(context))
+                    .collect();
+
+                Value::List(value_list)
+            }
+            AstExpression::Keyframes(keyframes) => {
+                let value_list = keyframes
+                    .iter()
+                    .map(|(time, value)| Value::List(vec![time.evaluate(context), value.evaluate(context)]))
+                    .collect();
+
+                Value::List(value_list)
+            }
+            AstExpression::Range { start, end } => {
+                let start = start.evaluate(context).to_number();
+                let end = end.evaluate(context).to_number();
+
+                let mut value_list = Vec::new();
+                let mut number = start;
+                while number < end {
+                    value_list.push(Value::Number(number));
+                    number += 1.0;
+                }
+
+                Value::List(value_list)
+            }
+            AstExpression::Index { list, index } => {
+                let list = list.evaluate(context);
+                let index = index.evaluate(context).to_number();
+                let list = list.to_list();
+
+                if index < 0.0 || index.fract() != 0.0 || index as usize >= list.len() {
+                    // FIXME: No panic
+                    panic!("Index {} out of bounds for list of length {}", index, list.len());
+                }
+
+                list[index as usize].clone()
             }
             AstExpression::Minus(expression) => {
                 match expression.evaluate(context) {
@@ -545,7 +1344,15 @@ impl AstExpression {
                 let b = b.evaluate(context);
 
                 match operator {
-                    BinaryOperator::Add => Value::Number(a.to_number() + b.to_number()),
+                    BinaryOperator::Add => {
+                        match (a, b) {
+                            (Value::List(mut a), Value::List(b)) => {
+                                a.extend(b);
+                                Value::List(a)
+                            }
+                            (a, b) => Value::Number(a.to_number() + b.to_number()),
+                        }
+                    }
                     BinaryOperator::Subtract => Value::Number(a.to_number() - b.to_number()),
                     BinaryOperator::Multiply => {
                         match (a, b) {
@@ -577,6 +1384,7 @@ impl AstExpression {
                             (x, y) => panic!("Cannot divide {:?} and {:?}", x, y),
                         }
                     }
+                    BinaryOperator::Modulo => Value::Number(a.to_number() % b.to_number()),
                     BinaryOperator::GreaterThan => {
                         match (a, b) {
                             (Value::Number(a), Value::Number(b)) => Value::Boolean(a > b),
@@ -589,7 +1397,6 @@ impl AstExpression {
                             (x, y) => panic!("Cannot compare {:?} and {:?}", x, y),
                         }
                     }
-                    operator => unimplemented!("Operator {:?} not yet implemented", operator),
                 }
             }
         }
@@ -600,10 +1407,10 @@ impl AstExpression {
             Rule::expression | Rule::mult_expression | Rule::bool_expression => {
                 let mut inner = pair.into_inner();
 
-                let expr_left = inner.next().unwrap();
-                let operator = inner.next();
+                // <operand> (<operator> <operand>)*, grouped from the left
+                let mut expression = AstExpression::from_pest(inner.next().unwrap());
 
-                if let Some(operator) = operator {
+                while let Some(operator) = inner.next() {
                     let expr_right = inner.next().unwrap();
 
                     let operator = match operator.as_str() {
@@ -617,15 +1424,14 @@ impl AstExpression {
                         operator => panic!("Unknown operator '{}' in the grammar", operator),
                     };
 
-                    AstExpression::BinaryOperation {
-                        a: Box::new(AstExpression::from_pest(expr_left)),
+                    expression = AstExpression::BinaryOperation {
+                        a: Box::new(expression),
                         operator,
                         b: Box::new(AstExpression::from_pest(expr_right)),
-                    }
-                } else {
-                    assert_eq!(inner.next(), None);
-                    AstExpression::from_pest(expr_left)
+                    };
                 }
+
+                expression
             }
             Rule::neg_expression => {
                 let mut inner = pair.into_inner();
@@ -638,13 +1444,24 @@ impl AstExpression {
                 }
 
                 let value = inner.next().unwrap();
-                assert_eq!(inner.next(), None);
                 assert_eq!(value.as_rule(), Rule::value);
 
+                // <value> [ <expression> ] [ <expression> ] ...
+                let mut expression = AstExpression::from_pest(value);
+                for index in inner {
+                    assert_eq!(index.as_rule(), Rule::index);
+                    let index = expect_expression(index.into_inner().next().unwrap());
+
+                    expression = AstExpression::Index {
+                        list: Box::new(expression),
+                        index: Box::new(index),
+                    };
+                }
+
                 if minus {
-                    AstExpression::Minus(Box::new(AstExpression::from_pest(value)))
+                    AstExpression::Minus(Box::new(expression))
                 } else {
-                    AstExpression::from_pest(value)
+                    expression
                 }
             }
             Rule::value => {
@@ -674,9 +1491,13 @@ impl AstExpression {
                 AstExpression::Value(Value::Color { r, g, b, a: 1.0 })
             }
             Rule::id_reference => {
-                AstExpression::Reference(pair.as_str().to_string())
+                AstExpression::Reference {
+                    id: pair.as_str().to_string(),
+                    position: Position::from_span(&pair.as_span()),
+                }
             }
             Rule::object => {
+                let position = Position::from_span(&pair.as_span());
                 let mut inner = pair.into_inner();
 
                 // obj_name ( <param_list> )
@@ -684,10 +1505,16 @@ impl AstExpression {
                 let obj_name = inner.next().unwrap();
                 assert_eq!(obj_name.as_rule(), Rule::obj_name);
 
-                let param_list = expect_param_list(inner.next().unwrap());
+                let (param_list, keyword_params) =
+                    expect_object_param_list(inner.next().unwrap());
                 assert_eq!(inner.next(), None);
 
-                AstExpression::Object { name: obj_name.as_str().to_string(), param_list }
+                AstExpression::Object {
+                    name: obj_name.as_str().to_string(),
+                    param_list,
+                    keyword_params,
+                    position,
+                }
             }
             Rule::vector => {
                 let mut inner = pair.into_inner();
@@ -724,12 +1551,69 @@ impl AstExpression {
                 AstExpression::Value(Value::String(string.to_string()))
             }
             Rule::texture => {
+                let position = Position::from_span(&pair.as_span());
                 let mut inner = pair.into_inner();
 
-                // texture ( <expression> )
-                let texture_file = expect_expression(inner.next().unwrap());
+                // texture ( <expression>, <keyword_params> )
+                let (mut param_list, keyword_params) =
+                    expect_object_param_list(inner.next().unwrap());
+
+                // FIXME: No panic
+                if param_list.len() != 1 {
+                    panic!("texture() takes the file name and keyword arguments only");
+                }
+                let texture_file = param_list.remove(0);
+
+                AstExpression::Texture {
+                    texture_file: Box::new(texture_file),
+                    keyword_params,
+                    position,
+                }
+            }
+            Rule::function_call => {
+                let position = Position::from_span(&pair.as_span());
+                let mut inner = pair.into_inner();
+
+                // <id> ( <param_list> )
+                let id = expect_id(inner.next().unwrap());
+                let param_list = expect_param_list(inner.next().unwrap());
+                assert_eq!(inner.next(), None);
+
+                AstExpression::FunctionCall { id, param_list, position }
+            }
+            Rule::list => {
+                let mut inner = pair.into_inner();
+
+                // [ <param_list> ]
+                let param_list = expect_param_list(inner.next().unwrap());
+                assert_eq!(inner.next(), None);
+
+                AstExpression::List(param_list)
+            }
+            Rule::keyframe_list => {
+                // [ <expression> : <expression> , ... ]
+                let keyframes = pair
+                    .into_inner()
+                    .map(|keyframe| {
+                        assert_eq!(keyframe.as_rule(), Rule::keyframe);
+                        let mut inner = keyframe.into_inner();
+                        let time = expect_expression(inner.next().unwrap());
+                        let value = expect_expression(inner.next().unwrap());
+                        (time, value)
+                    })
+                    .collect();
+
+                AstExpression::Keyframes(keyframes)
+            }
+            Rule::range => {
+                let mut inner = pair.into_inner();
+
+                // <expression> .. <expression>
+                let start = expect_expression(inner.next().unwrap());
+                let end = expect_expression(inner.next().unwrap());
+                assert_eq!(inner.next(), None);
 
-                AstExpression::Texture { texture_file: Box::new(texture_file) }
+                AstExpression::Range { start: Box::new(start), end: Box::new(end) }
             }
             _ => unimplemented!("Unimplemented rule: {}", pair)
         }
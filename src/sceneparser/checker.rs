@@ -0,0 +1,715 @@
+use super::ast_node::{
+    AstStatement, AstExpression, BinaryOperator, Function, Position,
+    object_parameters, MATERIAL_PARAMETERS, TEXTURE_PARAMETERS, BACKGROUND_MAPPINGS,
+    BACKGROUND_PARAMETERS, ANIMATION_PARAMETERS,
+};
+use super::animation::ANIMATION_CURVES;
+use super::context::Identifier;
+use super::formula::check_formula;
+use super::function::{find_builtin, CONSTANTS};
+use super::scene_loader::parse_scene;
+use super::texture::Texture;
+use super::value::Value;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter, Error};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Finds mistakes in a scene without executing it: undefined or unused names,
+// wrong argument counts, bad CSG operators or animation curves, formulas or
+// object and texture options, unreachable code and missing texture files.
+
+const CSG_OPERATORS: &[&str] = &["union", "intersection", "difference", "xor"];
+// Keyword arguments of objects that only take one of a few strings
+const OBJECT_OPTIONS: &[(&str, &str, &[&str])] = &[
+    ("uv_mapping", "cube UV mapping", &["faces", "cross"]),
+    ("texture_space", "texture space", &["uv", "object"]),
+    ("operand_materials", "operand_materials setting", &["on", "off"]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub position: Position,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}:{}: {}: {}", self.position.line, self.position.column, severity, self.message)
+    }
+}
+
+/// Checks each file and prints its diagnostics. Returns false if any file
+/// could not be parsed or has errors; warnings alone are fine.
+pub fn check_files(paths: &[String]) -> bool {
+    let mut success = true;
+
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                success = false;
+                continue;
+            }
+        };
+
+        let ast = match parse_scene(&source) {
+            Ok(ast) => ast,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                success = false;
+                continue;
+            }
+        };
+
+        for diagnostic in check_scene(&ast) {
+            println!("{}:{}", path, diagnostic);
+            if diagnostic.severity == Severity::Error {
+                success = false;
+            }
+        }
+    }
+
+    success
+}
+
+pub fn check_scene(ast: &AstStatement) -> Vec<Diagnostic> {
+    check_scene_in(ast, Path::new(""))
+}
+
+/// Like `check_scene`, but looks for texture files relative to `directory`
+/// instead of the working directory.
+pub fn check_scene_in(ast: &AstStatement, directory: &Path) -> Vec<Diagnostic> {
+    let mut checker = Checker { directory: directory.to_path_buf(), ..Checker::default() };
+    checker.collect(ast, false);
+
+    let mut scope: HashSet<Identifier> = CONSTANTS
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    scope.insert("time".to_string());
+
+    // Only the top level runs in order; functions can be called at any point
+    // after being defined, so their bodies are checked against every global.
+    checker.check_statement(ast, &mut scope, None);
+    checker.check_unused();
+
+    let mut diagnostics = checker.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.position.line, diagnostic.position.column));
+    diagnostics
+}
+
+#[derive(Default)]
+struct Checker<'a> {
+    diagnostics: Vec<Diagnostic>,
+    functions: HashMap<Identifier, &'a Function>,
+    // Functions whose definition has already been executed at the top level
+    defined_functions: HashSet<Identifier>,
+    called_functions: HashSet<Identifier>,
+    // Names assigned as globals anywhere, with their first assignment
+    globals: HashMap<Identifier, Position>,
+    // Locals assigned inside functions, with their first assignment
+    locals: Vec<(Identifier, Position)>,
+    references: HashSet<Identifier>,
+    // Where texture files are looked up
+    directory: PathBuf,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, position: Position, message: String) {
+        self.diagnostics.push(Diagnostic { position, severity: Severity::Error, message });
+    }
+
+    fn warning(&mut self, position: Position, message: String) {
+        self.diagnostics.push(Diagnostic { position, severity: Severity::Warning, message });
+    }
+
+    /// Records every function and assigned name, so that forward references
+    /// from function bodies can be resolved.
+    fn collect(&mut self, statement: &'a AstStatement, in_function: bool) {
+        match statement {
+            AstStatement::StatementList(statement_list) => {
+                for statement in statement_list {
+                    self.collect(statement, in_function);
+                }
+            }
+            AstStatement::Assignment { local, id, position, .. } => {
+                // At the top level, local variables are globals too.
+                if *local && in_function {
+                    self.locals.push((id.clone(), *position));
+                } else {
+                    self.globals.entry(id.clone()).or_insert(*position);
+                }
+            }
+            AstStatement::Function(function) => {
+                self.functions.insert(function.id().to_string(), function);
+                self.collect(function.body(), true);
+            }
+            AstStatement::Transformation { statement: body, .. }
+            | AstStatement::If { body, .. }
+            | AstStatement::While { body, .. }
+            | AstStatement::For { body, .. } => self.collect(body, in_function),
+            _ => (),
+        }
+    }
+
+    /// `function` is the one whose body is being checked, if any.
+    fn check_statement(
+        &mut self, statement: &'a AstStatement, scope: &mut HashSet<Identifier>,
+        function: Option<&'a Function>,
+    ) {
+        match statement {
+            AstStatement::StatementList(statement_list) => {
+                for statement in statement_list {
+                    self.check_statement(statement, scope, function);
+                }
+            }
+            AstStatement::Assignment { local, id, expression, .. } => {
+                self.check_expression(expression, scope);
+                if *local || function.is_none() {
+                    scope.insert(id.clone());
+                }
+            }
+            AstStatement::Function(definition) => {
+                self.defined_functions.insert(definition.id().to_string());
+
+                let mut function_scope = scope.clone();
+                function_scope.extend(self.globals.keys().cloned());
+                function_scope.extend(definition.param_list().iter().cloned());
+                self.check_statement(definition.body(), &mut function_scope, Some(definition));
+            }
+            AstStatement::CallFunction { id, param_list, position } => {
+                for param in param_list {
+                    self.check_expression(param, scope);
+                }
+                self.called_functions.insert(id.clone());
+
+                let called = match self.functions.get(id) {
+                    Some(called) => *called,
+                    None => {
+                        self.error(*position, format!("undefined function '{}'", id));
+                        return;
+                    }
+                };
+
+                if function.is_none() && !self.defined_functions.contains(id) {
+                    self.error(*position, format!("function '{}' is called before it is defined", id));
+                }
+
+                if called.param_list().len() != param_list.len() {
+                    self.error(*position, format!(
+                        "function '{}' takes {} arguments, got {}",
+                        id, called.param_list().len(), param_list.len()
+                    ));
+                }
+
+                // Whatever globals the function assigns are available after the call.
+                if function.is_none() {
+                    scope.extend(self.globals_assigned_by(called));
+                }
+            }
+            AstStatement::Draw { param_list } | AstStatement::AppendLight { param_list } => {
+                for param in param_list {
+                    self.check_expression(param, scope);
+                }
+            }
+            AstStatement::Transformation { arguments, transformation, statement, position } => {
+                if arguments.len() != transformation.argument_count() {
+                    self.error(*position, format!(
+                        "{}() takes {} arguments, got {}",
+                        transformation.name(), transformation.argument_count(), arguments.len()
+                    ));
+                }
+                for argument in arguments {
+                    self.check_expression(argument, scope);
+                }
+                self.check_statement(statement, scope, function);
+            }
+            AstStatement::If { condition, body, position } => {
+                self.check_expression(condition, scope);
+                if let Some(false) = constant_condition(condition) {
+                    self.warning(*position, "unreachable code: condition is always false".to_string());
+                }
+                self.check_statement(body, scope, function);
+            }
+            AstStatement::While { condition, body, position } => {
+                self.check_expression(condition, scope);
+                match constant_condition(condition) {
+                    Some(false) => {
+                        self.warning(*position, "unreachable code: condition is always false".to_string());
+                    }
+                    Some(true) => {
+                        self.error(*position, "loop never ends: condition is always true".to_string());
+                    }
+                    None => (),
+                }
+                self.check_statement(body, scope, function);
+            }
+            AstStatement::For { id, iterable, body, position } => {
+                self.check_expression(iterable, scope);
+                if is_constant_empty(iterable) {
+                    self.warning(*position, "unreachable code: loop over an empty range".to_string());
+                }
+                scope.insert(id.clone());
+                self.check_statement(body, scope, function);
+            }
+            AstStatement::SetCamera { position, look_at } => {
+                self.check_expression(position, scope);
+                if let Some(look_at) = look_at {
+                    self.check_expression(look_at, scope);
+                }
+            }
+            AstStatement::SetBackground { param_list, keyword_params, position } => {
+                for param in param_list {
+                    self.check_expression(param, scope);
+                    if let AstExpression::Value(Value::String(mapping)) = param {
+                        if !BACKGROUND_MAPPINGS.contains(&mapping.as_str()) {
+                            self.error(*position, format!(
+                                "unknown background mapping '{}', expected one of: {}",
+                                mapping, BACKGROUND_MAPPINGS.join(", ")
+                            ));
+                        }
+                    }
+                }
+
+                let mut seen = HashSet::new();
+
+                for (id, param) in keyword_params {
+                    self.check_expression(param, scope);
+
+                    if !seen.insert(id) {
+                        self.error(*position, format!("argument '{}' given more than once to set background()", id));
+                    }
+
+                    let options = BACKGROUND_PARAMETERS
+                        .iter()
+                        .find(|(name, _)| name == id)
+                        .map(|(_, options)| options);
+
+                    match (options, param) {
+                        (None, _) => {
+                            self.error(*position, format!("unknown argument '{}' for set background()", id));
+                        }
+                        (Some([]), AstExpression::Value(Value::String(_))) => {
+                            self.error(*position, format!("expected a number for '{}'", id));
+                        }
+                        (Some(options), AstExpression::Value(Value::String(option)))
+                            if !options.contains(&option.as_str()) =>
+                        {
+                            self.error(*position, format!(
+                                "unknown background {} '{}', expected one of: {}",
+                                id, option, options.join(", ")
+                            ));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            AstStatement::SetAnimation { param_list, keyword_params, position } => {
+                for param in param_list {
+                    self.check_expression(param, scope);
+                }
+                if !param_list.is_empty() {
+                    self.error(*position, "set animation() only takes keyword arguments".to_string());
+                }
+
+                let mut seen = HashSet::new();
+
+                for (id, param) in keyword_params {
+                    self.check_expression(param, scope);
+
+                    if !seen.insert(id.as_str()) {
+                        self.error(*position, format!("argument '{}' given more than once to set animation()", id));
+                    }
+
+                    if !ANIMATION_PARAMETERS.contains(&id.as_str()) {
+                        self.error(*position, format!("unknown argument '{}' for set animation()", id));
+                    } else if let AstExpression::Value(Value::String(_)) = param {
+                        self.error(*position, format!("expected a number for '{}'", id));
+                    }
+                }
+
+                if seen.contains("frames") && seen.contains("duration") {
+                    self.warning(*position, "set animation() ignores the duration when frames are given".to_string());
+                }
+            }
+            AstStatement::Comment { .. } | AstStatement::BlankLine => (),
+        }
+    }
+
+    fn check_expression(&mut self, expression: &AstExpression, scope: &HashSet<Identifier>) {
+        match expression {
+            AstExpression::Value(_) => (),
+            AstExpression::Reference { id, position } => {
+                self.references.insert(id.clone());
+                if !scope.contains(id) {
+                    if self.globals.contains_key(id) {
+                        self.error(*position, format!("variable '{}' is used before it is assigned", id));
+                    } else {
+                        self.error(*position, format!("undefined variable '{}'", id));
+                    }
+                }
+            }
+            AstExpression::Vector { x, y, z } => {
+                self.check_expression(x, scope);
+                self.check_expression(y, scope);
+                self.check_expression(z, scope);
+            }
+            AstExpression::Rgb { r, g, b } => {
+                self.check_expression(r, scope);
+                self.check_expression(g, scope);
+                self.check_expression(b, scope);
+            }
+            AstExpression::Object { name, param_list, keyword_params, position } => {
+                for param in param_list {
+                    self.check_expression(param, scope);
+                }
+
+                let parameters = object_parameters(name);
+                let mut seen = HashSet::new();
+
+                for (id, param) in keyword_params {
+                    self.check_expression(param, scope);
+
+                    if !parameters.contains(&id.as_str()) && !MATERIAL_PARAMETERS.contains(&id.as_str()) {
+                        self.error(*position, format!("unknown argument '{}' for {}()", id, name));
+                    }
+                    if !seen.insert(id) {
+                        self.error(*position, format!("argument '{}' given more than once to {}()", id, name));
+                    }
+                }
+
+                if name == "csg" || name == "smooth" {
+                    let operators = param_list
+                        .iter()
+                        .chain(keyword_params.iter().filter(|(id, _)| id == "operator").map(|(_, param)| param));
+
+                    for operator in operators {
+                        if let AstExpression::Value(Value::String(operator)) = operator {
+                            if !CSG_OPERATORS.contains(&operator.as_str()) {
+                                self.error(*position, format!(
+                                    "unknown CSG operator '{}', expected one of: {}",
+                                    operator, CSG_OPERATORS.join(", ")
+                                ));
+                            }
+                        }
+                    }
+                }
+
+                if name == "implicit" {
+                    let formulas = param_list
+                        .iter()
+                        .chain(keyword_params.iter().filter(|(id, _)| id == "formula").map(|(_, param)| param));
+
+                    for formula in formulas {
+                        if let AstExpression::Value(Value::String(formula)) = formula {
+                            match check_formula(formula) {
+                                Ok(variables) => {
+                                    for id in variables {
+                                        if !scope.contains(&id) {
+                                            self.error(*position, format!("undefined variable '{}' in formula", id));
+                                        }
+                                        self.references.insert(id);
+                                    }
+                                }
+                                Err(err) => self.error(*position, err),
+                            }
+                        }
+                    }
+                }
+
+                for (id, param) in keyword_params {
+                    let option = OBJECT_OPTIONS.iter().find(|(name, _, _)| name == id);
+
+                    if let (Some((_, description, options)), AstExpression::Value(Value::String(value))) = (option, param) {
+                        if !options.contains(&value.as_str()) {
+                            self.error(*position, format!(
+                                "unknown {} '{}', expected one of: {}",
+                                description, value, options.join(", ")
+                            ));
+                        }
+                    }
+                }
+            }
+            AstExpression::Texture { texture_file, keyword_params, position } => {
+                self.check_expression(texture_file, scope);
+                if let AstExpression::Value(Value::String(file)) = &**texture_file {
+                    let path = self.directory.join(file);
+                    if !path.exists() {
+                        self.error(*position, format!("texture file '{}' not found", file));
+                    } else if let Err(err) = Texture::load_pixmap(&path.to_string_lossy()) {
+                        self.error(*position, err.to_string());
+                    }
+                }
+
+                let mut seen = HashSet::new();
+
+                for (id, param) in keyword_params {
+                    self.check_expression(param, scope);
+
+                    if !seen.insert(id) {
+                        self.error(*position, format!("argument '{}' given more than once to texture()", id));
+                    }
+
+                    let options = TEXTURE_PARAMETERS
+                        .iter()
+                        .find(|(name, _)| name == id)
+                        .map(|(_, options)| options);
+
+                    match (options, param) {
+                        (None, _) => {
+                            self.error(*position, format!("unknown argument '{}' for texture()", id));
+                        }
+                        (Some(options), AstExpression::Value(Value::String(option)))
+                            if !options.contains(&option.as_str()) =>
+                        {
+                            self.error(*position, format!(
+                                "unknown texture {} '{}', expected one of: {}",
+                                id, option, options.join(", ")
+                            ));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            AstExpression::FunctionCall { id, param_list, position } => {
+                for param in param_list {
+                    self.check_expression(param, scope);
+                }
+
+                match find_builtin(id) {
+                    Some(builtin) => {
+                        if param_list.len() < builtin.min_args || param_list.len() > builtin.max_args {
+                            self.error(*position, format!(
+                                "wrong number of arguments for {}(): got {}", id, param_list.len()
+                            ));
+                        }
+
+                        if let (
+                            "animate", Some(AstExpression::Value(Value::String(curve)))
+                        ) = (id.as_str(), param_list.get(2)) {
+                            if !ANIMATION_CURVES.contains(&curve.as_str()) {
+                                self.error(*position, format!(
+                                    "unknown animation curve '{}', expected one of: {}",
+                                    curve, ANIMATION_CURVES.join(", ")
+                                ));
+                            }
+                        }
+                    }
+                    None if self.functions.contains_key(id) => {
+                        self.error(*position, format!(
+                            "function '{}' does not return a value; use 'call {}(...)'", id, id
+                        ));
+                    }
+                    None => self.error(*position, format!("undefined function '{}'", id)),
+                }
+            }
+            AstExpression::List(param_list) => {
+                for param in param_list {
+                    self.check_expression(param, scope);
+                }
+            }
+            AstExpression::Keyframes(keyframes) => {
+                for (time, value) in keyframes {
+                    self.check_expression(time, scope);
+                    self.check_expression(value, scope);
+                }
+            }
+            AstExpression::Range { start: a, end: b }
+            | AstExpression::Index { list: a, index: b }
+            | AstExpression::BinaryOperation { a, b, .. } => {
+                self.check_expression(a, scope);
+                self.check_expression(b, scope);
+            }
+            AstExpression::Minus(expression) => self.check_expression(expression, scope),
+        }
+    }
+
+    fn check_unused(&mut self) {
+        let mut unused: Vec<_> = self.globals
+            .iter()
+            .map(|(id, position)| (id.clone(), *position))
+            .chain(self.locals.iter().cloned())
+            .filter(|(id, _)| !id.starts_with('_') && !self.references.contains(id))
+            .collect();
+        unused.sort_by(|a, b| a.0.cmp(&b.0));
+        unused.dedup_by(|a, b| a.0 == b.0);
+
+        for (id, position) in unused {
+            self.warning(position, format!("variable '{}' is assigned but never used", id));
+        }
+
+        let uncalled: Vec<_> = self.functions
+            .values()
+            .filter(|function| !self.called_functions.contains(function.id()))
+            .map(|function| (function.id().to_string(), function.position()))
+            .collect();
+
+        for (id, position) in uncalled {
+            self.warning(position, format!("function '{}' is never called", id));
+        }
+    }
+
+    fn globals_assigned_by(&self, function: &Function) -> HashSet<Identifier> {
+        fn collect<'f>(
+            checker: &Checker<'f>, statement: &AstStatement,
+            visited: &mut HashSet<Identifier>, names: &mut HashSet<Identifier>,
+        ) {
+            match statement {
+                AstStatement::StatementList(statement_list) => {
+                    for statement in statement_list {
+                        collect(checker, statement, visited, names);
+                    }
+                }
+                AstStatement::Assignment { local: false, id, .. } => {
+                    names.insert(id.clone());
+                }
+                AstStatement::CallFunction { id, .. } => {
+                    if let Some(function) = checker.functions.get(id) {
+                        if visited.insert(id.clone()) {
+                            collect(checker, function.body(), visited, names);
+                        }
+                    }
+                }
+                AstStatement::Transformation { statement: body, .. }
+                | AstStatement::If { body, .. }
+                | AstStatement::While { body, .. }
+                | AstStatement::For { body, .. } => collect(checker, body, visited, names),
+                _ => (),
+            }
+        }
+
+        let mut visited = HashSet::new();
+        let mut names = HashSet::new();
+        visited.insert(function.id().to_string());
+        collect(self, function.body(), &mut visited, &mut names);
+        names
+    }
+}
+
+fn constant_number(expression: &AstExpression) -> Option<f64> {
+    match expression {
+        AstExpression::Value(Value::Number(number)) => Some(*number),
+        AstExpression::Minus(expression) => constant_number(expression).map(|number| -number),
+        AstExpression::BinaryOperation { a, operator, b } => {
+            let a = constant_number(a)?;
+            let b = constant_number(b)?;
+
+            match operator {
+                BinaryOperator::Add => Some(a + b),
+                BinaryOperator::Subtract => Some(a - b),
+                BinaryOperator::Multiply => Some(a * b),
+                BinaryOperator::Divide => Some(a / b),
+                BinaryOperator::Modulo => Some(a % b),
+                BinaryOperator::LessThan | BinaryOperator::GreaterThan => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn constant_condition(condition: &AstExpression) -> Option<bool> {
+    match condition {
+        AstExpression::BinaryOperation { a, operator: BinaryOperator::LessThan, b } => {
+            Some(constant_number(a)? < constant_number(b)?)
+        }
+        AstExpression::BinaryOperation { a, operator: BinaryOperator::GreaterThan, b } => {
+            Some(constant_number(a)? > constant_number(b)?)
+        }
+        _ => None,
+    }
+}
+
+fn is_constant_empty(iterable: &AstExpression) -> bool {
+    match iterable {
+        AstExpression::List(param_list) => param_list.is_empty(),
+        AstExpression::Range { start, end } => {
+            match (constant_number(start), constant_number(end)) {
+                (Some(start), Some(end)) => start >= end,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostics(source: &str) -> Vec<String> {
+        check_scene(&parse_scene(source).unwrap())
+            .iter()
+            .map(Diagnostic::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn undefined_and_unused_names() {
+        assert_eq!(diagnostics("x = y + 1\ny = 2\nz = x + w\n"), [
+            "1:5: error: variable 'y' is used before it is assigned",
+            "3:1: warning: variable 'z' is assigned but never used",
+            "3:9: error: undefined variable 'w'",
+        ]);
+        assert_eq!(diagnostics("a = 1\nfunction f(p)\n  local q = p\nend\n_b = 2\n"), [
+            "1:1: warning: variable 'a' is assigned but never used",
+            "2:1: warning: function 'f' is never called",
+            "3:3: warning: variable 'q' is assigned but never used",
+        ]);
+    }
+
+    #[test]
+    fn constant_conditions() {
+        let source = "if 1 > 2 then\n  draw(sphere(1))\nend\nwhile 1 < 2 do\nend\nfor i in 3..1 do\nend\n";
+
+        assert_eq!(diagnostics(source), [
+            "1:1: warning: unreachable code: condition is always false",
+            "4:1: error: loop never ends: condition is always true",
+            "6:1: warning: unreachable code: loop over an empty range",
+        ]);
+    }
+
+    #[test]
+    fn argument_counts() {
+        let source = "function f(a, b)\nend\ncall f(1)\nx = sin(1, 2)\ny = f(1, 2)\ncall g()\nrotate(1, 2) do\nend\ndraw(sphere(x + y))\n";
+
+        assert_eq!(diagnostics(source), [
+            "3:1: error: function 'f' takes 2 arguments, got 1",
+            "4:5: error: wrong number of arguments for sin(): got 2",
+            "5:5: error: function 'f' does not return a value; use 'call f(...)'",
+            "6:1: error: undefined function 'g'",
+            "7:1: error: rotate() takes 3 arguments, got 2",
+        ]);
+    }
+
+    /// The example scenes are all clean
+    #[test]
+    fn example_scenes() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+        for entry in fs::read_dir(root.join("src/scenes")).unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+            let errors: Vec<_> = check_scene_in(&parse_scene(&source).unwrap(), root)
+                .into_iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .map(|diagnostic| diagnostic.to_string())
+                .collect();
+
+            assert!(errors.is_empty(), "{:?}: {:?}", path, errors);
+        }
+    }
+}
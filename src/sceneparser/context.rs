@@ -1,6 +1,7 @@
 use crate::raytracer::raytracer::RayTracer;
 use super::value::Value;
 use super::ast_node::Function;
+use super::function::CONSTANTS;
 
 use std::collections::HashMap;
 
@@ -11,15 +12,23 @@ pub struct SceneContext<'a> {
     globals: HashMap<Identifier, Value>,
     functions: HashMap<Identifier, Function>,
     ray_tracer: &'a mut RayTracer,
+    // Problems that didn't stop the scene from running, like missing textures
+    errors: Vec<String>,
 }
 
 impl<'r> SceneContext<'r> {
     pub fn new(ray_tracer: &'r mut RayTracer) -> SceneContext<'r> {
+        let globals = CONSTANTS
+            .iter()
+            .map(|(name, value)| (name.to_string(), Value::Number(*value)))
+            .collect();
+
         Self {
             stack: Default::default(),
-            globals: Default::default(),
+            globals,
             functions: Default::default(),
             ray_tracer,
+            errors: vec![],
         }
     }
 
@@ -39,6 +48,14 @@ impl<'r> SceneContext<'r> {
         &mut self.ray_tracer
     }
 
+    pub fn report_error(&mut self, error: String) {
+        self.errors.push(error);
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
     pub fn add_function(&mut self, id: Identifier, function: Function) {
         self.functions.insert(id, function);
     }
@@ -0,0 +1,412 @@
+use super::ast_node::{AstStatement, AstExpression, BinaryOperator};
+use super::scene_loader::parse_scene;
+use super::value::Value;
+
+use std::fmt::{Display, Formatter, Error};
+use std::fs;
+
+// Re-emits scene source from the AST, with one statement per line, blocks
+// indented by four spaces, and normalized spacing inside expressions.
+
+const INDENT: &str = "    ";
+
+const COLOR_NAMES: &[(&str, (f64, f64, f64))] = &[
+    ("red", (1.0, 0.0, 0.0)),
+    ("orange", (1.0, 0.5, 0.0)),
+    ("yellow", (1.0, 1.0, 0.0)),
+    ("green", (0.0, 1.0, 0.0)),
+    ("blue", (0.0, 0.0, 1.0)),
+    ("purple", (1.0, 0.0, 1.0)),
+    ("black", (0.0, 0.0, 0.0)),
+    ("white", (1.0, 1.0, 1.0)),
+];
+
+pub fn format_scene(ast: &AstStatement) -> String {
+    let mut output = String::new();
+
+    match ast {
+        AstStatement::StatementList(statement_list) => {
+            format_block(statement_list, 0, &mut output)
+        }
+        statement => format_statement(statement, 0, &mut output),
+    }
+
+    output
+}
+
+/// Formats each file in place, or with `check` only reports the ones that
+/// would change. Returns false if any file failed or needs formatting.
+pub fn format_files(paths: &[String], check: bool) -> bool {
+    let mut success = true;
+
+    for path in paths {
+        match format_file(path) {
+            Ok(formatted) => {
+                let source = fs::read_to_string(path).unwrap_or_default();
+
+                if source == formatted {
+                    continue;
+                }
+
+                if check {
+                    println!("{} needs formatting", path);
+                    success = false;
+                } else if let Err(err) = fs::write(path, formatted) {
+                    eprintln!("{}: {}", path, err);
+                    success = false;
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                success = false;
+            }
+        }
+    }
+
+    success
+}
+
+fn format_file(path: &str) -> Result<String, String> {
+    let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let ast = parse_scene(&source).map_err(|err| err.to_string())?;
+
+    // Comments can only be kept between statements; refuse to drop the ones
+    // written in the middle of an expression.
+    if count_comments(&source) != count_comment_statements(&ast) {
+        return Err("comments inside statements cannot be formatted".to_string());
+    }
+
+    Ok(format_scene(&ast))
+}
+
+fn count_comments(source: &str) -> usize {
+    let mut count = 0;
+    let mut quote = None;
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '/') if chars.peek() == Some(&'/') => {
+                count += 1;
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    count
+}
+
+fn count_comment_statements(statement: &AstStatement) -> usize {
+    match statement {
+        AstStatement::StatementList(statement_list) => {
+            statement_list.iter().map(count_comment_statements).sum()
+        }
+        AstStatement::Comment { .. } => 1,
+        AstStatement::Function(function) => count_comment_statements(function.body()),
+        AstStatement::Transformation { statement: body, .. }
+        | AstStatement::If { body, .. }
+        | AstStatement::While { body, .. }
+        | AstStatement::For { body, .. } => count_comment_statements(body),
+        _ => 0,
+    }
+}
+
+fn format_block(statement_list: &[AstStatement], indent: usize, output: &mut String) {
+    for statement in statement_list {
+        if let AstStatement::Comment { text, trailing: true } = statement {
+            // Put it back on the previous statement's line
+            output.pop();
+            output.push(' ');
+            output.push_str(text);
+            output.push('\n');
+        } else {
+            format_statement(statement, indent, output);
+        }
+    }
+}
+
+/// Writes `<header>`, the indented body, and the closing `end`.
+fn format_body(header: String, body: &AstStatement, indent: usize, output: &mut String) {
+    output.push_str(&header);
+    output.push('\n');
+
+    match body {
+        AstStatement::StatementList(statement_list) => {
+            format_block(statement_list, indent + 1, output)
+        }
+        statement => format_statement(statement, indent + 1, output),
+    }
+
+    output.push_str(&INDENT.repeat(indent));
+    output.push_str("end\n");
+}
+
+fn format_statement(statement: &AstStatement, indent: usize, output: &mut String) {
+    if let AstStatement::BlankLine = statement {
+        output.push('\n');
+        return;
+    }
+
+    output.push_str(&INDENT.repeat(indent));
+
+    match statement {
+        AstStatement::StatementList(_) => {
+            format_body("do".to_string(), statement, indent, output);
+            return;
+        }
+        AstStatement::Assignment { local, id, expression, .. } => {
+            if *local {
+                output.push_str("local ");
+            }
+            output.push_str(&format!("{} = {}", id, expression));
+        }
+        AstStatement::Function(function) => {
+            let header = format!(
+                "function {}({})", function.id(), function.param_list().join(", ")
+            );
+            format_body(header, function.body(), indent, output);
+            return;
+        }
+        AstStatement::CallFunction { id, param_list, .. } => {
+            output.push_str(&format!("call {}({})", id, ParamList(param_list)));
+        }
+        AstStatement::Draw { param_list } => {
+            output.push_str(&format!("draw({})", ParamList(param_list)));
+        }
+        AstStatement::Transformation { arguments, transformation, statement, .. } => {
+            let header = format!("{}({})", transformation.name(), ParamList(arguments));
+
+            match **statement {
+                AstStatement::StatementList(_) => {
+                    format_body(format!("{} do", header), statement, indent, output)
+                }
+                // Chained transformations stay on the same level
+                AstStatement::Transformation { .. } => {
+                    output.push_str(&header);
+                    output.push('\n');
+                    format_statement(statement, indent, output);
+                }
+                _ => {
+                    output.push_str(&header);
+                    output.push('\n');
+                    format_statement(statement, indent + 1, output);
+                }
+            }
+            return;
+        }
+        AstStatement::If { condition, body, .. } => {
+            format_body(format!("if {} then", condition), body, indent, output);
+            return;
+        }
+        AstStatement::While { condition, body, .. } => {
+            format_body(format!("while {} do", condition), body, indent, output);
+            return;
+        }
+        AstStatement::For { id, iterable, body, .. } => {
+            format_body(format!("for {} in {} do", id, iterable), body, indent, output);
+            return;
+        }
+        AstStatement::AppendLight { param_list } => {
+            output.push_str(&format!("append light({})", ParamList(param_list)));
+        }
+        AstStatement::SetCamera { position, look_at } => {
+            match look_at {
+                Some(look_at) => output.push_str(&format!("set camera({}, look_at: {})", position, look_at)),
+                None => output.push_str(&format!("set camera({})", position)),
+            }
+        }
+        AstStatement::SetBackground { param_list, keyword_params, .. }
+        | AstStatement::SetAnimation { param_list, keyword_params, .. } => {
+            let name = match statement {
+                AstStatement::SetBackground { .. } => "background",
+                _ => "animation",
+            };
+            output.push_str(&format!("set {}({}", name, ParamList(param_list)));
+            for (index, (id, param)) in keyword_params.iter().enumerate() {
+                if index != 0 || !param_list.is_empty() {
+                    output.push_str(", ");
+                }
+                output.push_str(&format!("{}: {}", id, param));
+            }
+            output.push(')');
+        }
+        AstStatement::Comment { text, .. } => {
+            output.push_str(text);
+        }
+        AstStatement::BlankLine => unreachable!(),
+    }
+
+    output.push('\n');
+}
+
+struct ParamList<'a>(&'a [AstExpression]);
+
+impl Display for ParamList<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        for (index, param) in self.0.iter().enumerate() {
+            if index != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        Ok(())
+    }
+}
+
+impl AstExpression {
+    /// How tightly the expression binds, so that parentheses are only added
+    /// where the grammar needs them.
+    fn precedence(&self) -> u8 {
+        match self {
+            AstExpression::BinaryOperation { operator, .. } => match operator {
+                BinaryOperator::LessThan | BinaryOperator::GreaterThan => 1,
+                BinaryOperator::Add | BinaryOperator::Subtract => 2,
+                BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 3,
+            },
+            AstExpression::Range { .. } => 0,
+            AstExpression::Minus(_) => 4,
+            _ => 5,
+        }
+    }
+}
+
+struct Operand<'a>(&'a AstExpression, u8);
+
+impl Display for Operand<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let Operand(expression, precedence) = self;
+
+        if expression.precedence() < *precedence {
+            write!(f, "({})", expression)
+        } else {
+            write!(f, "{}", expression)
+        }
+    }
+}
+
+impl Display for AstExpression {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            AstExpression::Value(value) => match value {
+                Value::Number(number) => write!(f, "{}", number),
+                Value::String(string) if string.contains('\'') => write!(f, "\"{}\"", string),
+                Value::String(string) => write!(f, "'{}'", string),
+                Value::Color { r, g, b, .. } => {
+                    let name = COLOR_NAMES
+                        .iter()
+                        .find(|(_, color)| *color == (*r, *g, *b));
+
+                    match name {
+                        Some((name, _)) => write!(f, "{}", name),
+                        None => write!(f, "rgb({}, {}, {})", r, g, b),
+                    }
+                }
+                value => panic!("Value {:?} cannot be written in a scene", value),
+            },
+            AstExpression::Reference { id, .. } => write!(f, "{}", id),
+            AstExpression::Vector { x, y, z } => write!(f, "<{}, {}, {}>", x, y, z),
+            AstExpression::Rgb { r, g, b } => write!(f, "rgb({}, {}, {})", r, g, b),
+            AstExpression::Object { name, param_list, keyword_params, .. } => {
+                write!(f, "{}({}", name, ParamList(param_list))?;
+                for (index, (id, param)) in keyword_params.iter().enumerate() {
+                    if index != 0 || !param_list.is_empty() {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", id, param)?;
+                }
+                write!(f, ")")
+            }
+            AstExpression::Texture { texture_file, keyword_params, .. } => {
+                write!(f, "texture({}", texture_file)?;
+                for (id, param) in keyword_params {
+                    write!(f, ", {}: {}", id, param)?;
+                }
+                write!(f, ")")
+            }
+            AstExpression::FunctionCall { id, param_list, .. } => {
+                write!(f, "{}({})", id, ParamList(param_list))
+            }
+            AstExpression::List(param_list) => write!(f, "[{}]", ParamList(param_list)),
+            AstExpression::Keyframes(keyframes) => {
+                write!(f, "[")?;
+                for (index, (time, value)) in keyframes.iter().enumerate() {
+                    if index != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", time, value)?;
+                }
+                write!(f, "]")
+            }
+            AstExpression::Range { start, end } => write!(f, "{}..{}", start, end),
+            AstExpression::Index { list, index } => {
+                write!(f, "{}[{}]", Operand(list, 5), index)
+            }
+            AstExpression::Minus(expression) => write!(f, "-{}", Operand(expression, 5)),
+            AstExpression::BinaryOperation { a, operator, b } => {
+                let operator_str = match operator {
+                    BinaryOperator::Add => "+",
+                    BinaryOperator::Subtract => "-",
+                    BinaryOperator::Multiply => "*",
+                    BinaryOperator::Divide => "/",
+                    BinaryOperator::Modulo => "%",
+                    BinaryOperator::LessThan => "<",
+                    BinaryOperator::GreaterThan => ">",
+                };
+                let precedence = self.precedence();
+
+                // Operators group from the left, so only the right side needs
+                // parentheses for equal precedence.
+                write!(
+                    f, "{} {} {}",
+                    Operand(a, precedence), operator_str, Operand(b, precedence + 1)
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene_files() -> Vec<std::path::PathBuf> {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut files: Vec<_> = fs::read_dir(root.join("src/scenes"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.push(root.join("globes.scene"));
+        files
+    }
+
+    /// Formatting the example scenes keeps their comments, and formatting
+    /// them again changes nothing.
+    #[test]
+    fn formats_scenes_idempotently() {
+        for file in scene_files() {
+            let source = fs::read_to_string(&file).unwrap();
+            let once = format_scene(&parse_scene(&source).unwrap());
+            let twice = format_scene(&parse_scene(&once).unwrap());
+
+            assert_eq!(once, twice, "{:?}", file);
+            assert_eq!(count_comments(&source), count_comments(&once), "{:?}", file);
+        }
+    }
+
+    #[test]
+    fn normalizes_spacing_and_parentheses() {
+        let source = "x = 1 - (2 - 3) + 4*(5+6) // trailing\n\n\n// own\ny = -(x + 1) - -[1, 2][0]\nif x < 2 then\nend";
+        let formatted = format_scene(&parse_scene(source).unwrap());
+
+        assert_eq!(
+            formatted,
+            "x = 1 - (2 - 3) + 4 * (5 + 6) // trailing\n\n// own\ny = -(x + 1) - -[1, 2][0]\nif x < 2 then\nend\n"
+        );
+        assert_eq!(formatted, format_scene(&parse_scene(&formatted).unwrap()));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::raytracer::math_shapes::ImplicitFunction;
+use crate::raytracer::vector::Vector;
+use super::ast_node::{AstExpression, BinaryOperator};
+use super::context::{SceneContext, Identifier};
+use super::function::find_builtin;
+use super::scene_loader::parse_formula;
+use super::value::Value;
+
+use std::fmt::{Debug, Formatter, Error};
+use std::sync::Arc;
+
+// Formulas of a point, written as strings in the scene language, like the
+// surface in implicit('x*x + y*y + z*z - 1'). They are turned into closures
+// when the object is made, so that the ray tracer can evaluate them on any
+// thread without the scene. x, y and z are the point; other names are read
+// from the scene right away.
+
+// The builtins that take and give numbers only
+const FORMULA_FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "asin", "acos", "atan", "atan2", "sqrt", "pow", "abs", "floor",
+    "deg2rad", "min", "max", "clamp", "lerp",
+];
+
+#[derive(Clone)]
+pub struct Formula {
+    source: String,
+    function: ImplicitFunction,
+}
+
+impl Formula {
+    pub fn compile(source: &str, context: &mut SceneContext) -> Result<Formula, String> {
+        let expression = parse_formula(source)
+            .map_err(|err| format!("Invalid formula '{}':\n{}", source, err))?;
+
+        Ok(Formula {
+            source: source.to_string(),
+            function: compile(&expression, context)
+                .map_err(|err| format!("Invalid formula '{}': {}", source, err))?,
+        })
+    }
+
+    /// Outside everywhere, for formulas that didn't compile
+    pub fn empty() -> Formula {
+        Formula {
+            source: "1".to_string(),
+            function: Arc::new(|_| 1.0),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn function(&self) -> &ImplicitFunction {
+        &self.function
+    }
+}
+
+impl Debug for Formula {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "Formula({:?})", self.source)
+    }
+}
+
+/// Checks only the syntax and the names of the functions called, and gives
+/// the names of the scene variables used.
+pub fn check_formula(source: &str) -> Result<Vec<Identifier>, String> {
+    let expression = parse_formula(source)
+        .map_err(|err| format!("Invalid formula '{}':\n{}", source, err))?;
+
+    let mut variables = vec![];
+    check_expression(&expression, &mut variables)
+        .map_err(|err| format!("Invalid formula '{}': {}", source, err))?;
+
+    Ok(variables)
+}
+
+fn check_expression(expression: &AstExpression, variables: &mut Vec<Identifier>) -> Result<(), String> {
+    match expression {
+        AstExpression::Reference { id, .. } => {
+            if !["x", "y", "z"].contains(&id.as_str()) && !variables.contains(id) {
+                variables.push(id.clone());
+            }
+            Ok(())
+        }
+        AstExpression::FunctionCall { id, param_list, .. } => {
+            if !FORMULA_FUNCTIONS.contains(&id.as_str()) {
+                return Err(format!("{}() can't be used in formulas", id));
+            }
+            param_list.iter().try_for_each(|param| check_expression(param, variables))
+        }
+        AstExpression::Minus(expression) => check_expression(expression, variables),
+        AstExpression::BinaryOperation { a, b, .. } => {
+            check_expression(a, variables)?;
+            check_expression(b, variables)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn compile(expression: &AstExpression, context: &mut SceneContext) -> Result<ImplicitFunction, String> {
+    Ok(match expression {
+        AstExpression::Value(Value::Number(number)) => {
+            let number = *number;
+            Arc::new(move |_| number)
+        }
+        AstExpression::Reference { id, .. } => match id.as_str() {
+            "x" => Arc::new(|p: Vector| p.x),
+            "y" => Arc::new(|p: Vector| p.y),
+            "z" => Arc::new(|p: Vector| p.z),
+            id => {
+                let value = match context.locals().get(id) {
+                    Some(value) => Some(value.clone()),
+                    None => context.globals().get(id).cloned(),
+                };
+                match value {
+                    Some(Value::Number(number)) => Arc::new(move |_| number),
+                    Some(value) => return Err(format!("'{}' is not a number: {:?}", id, value)),
+                    None => return Err(format!("Unknown variable '{}'", id)),
+                }
+            }
+        },
+        AstExpression::Minus(expression) => {
+            let expression = compile(expression, context)?;
+            Arc::new(move |p| -expression(p))
+        }
+        AstExpression::BinaryOperation { a, operator, b } => {
+            let a = compile(a, context)?;
+            let b = compile(b, context)?;
+
+            match operator {
+                BinaryOperator::Add => Arc::new(move |p| a(p) + b(p)),
+                BinaryOperator::Subtract => Arc::new(move |p| a(p) - b(p)),
+                BinaryOperator::Multiply => Arc::new(move |p| a(p) * b(p)),
+                BinaryOperator::Divide => Arc::new(move |p| a(p) / b(p)),
+                BinaryOperator::Modulo => Arc::new(move |p| a(p) % b(p)),
+                BinaryOperator::LessThan | BinaryOperator::GreaterThan => {
+                    return Err("comparisons can't be used in formulas".to_string())
+                }
+            }
+        }
+        AstExpression::FunctionCall { id, param_list, .. } => {
+            let builtin = match find_builtin(id) {
+                Some(builtin) if FORMULA_FUNCTIONS.contains(&id.as_str()) => builtin,
+                _ => return Err(format!("{}() can't be used in formulas", id)),
+            };
+            if param_list.len() < builtin.min_args || param_list.len() > builtin.max_args {
+                return Err(format!("Wrong number of arguments for {}(): got {}", id, param_list.len()));
+            }
+
+            let args = param_list
+                .iter()
+                .map(|param| compile(param, context))
+                .collect::<Result<Vec<_>, _>>()?;
+            let function = builtin.function;
+
+            Arc::new(move |p| {
+                let values: Vec<_> = args.iter().map(|arg| Value::Number(arg(p))).collect();
+                function(&values).to_number()
+            })
+        }
+        expression => return Err(format!("only numbers can be used in formulas, got {:?}", expression)),
+    })
+}
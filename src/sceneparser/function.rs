@@ -0,0 +1,275 @@
+use crate::raytracer::math::PI;
+use crate::raytracer::noise::Perlin;
+use crate::raytracer::procedural_texture::{ProceduralTexture, Pattern};
+use super::animation::animate;
+use super::texture::Texture;
+use super::value::Value;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+// Functions and constants available to every scene without being defined.
+
+pub type BuiltinFunction = fn(&[Value]) -> Value;
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    pub function: BuiltinFunction,
+}
+
+pub const CONSTANTS: &[(&str, f64)] = &[
+    ("pi", PI),
+    ("tau", 2.0 * PI),
+];
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin { name: "sin", min_args: 1, max_args: 1, function: |args| number(args, f64::sin) },
+    Builtin { name: "cos", min_args: 1, max_args: 1, function: |args| number(args, f64::cos) },
+    Builtin { name: "tan", min_args: 1, max_args: 1, function: |args| number(args, f64::tan) },
+    Builtin { name: "asin", min_args: 1, max_args: 1, function: |args| number(args, f64::asin) },
+    Builtin { name: "acos", min_args: 1, max_args: 1, function: |args| number(args, f64::acos) },
+    Builtin { name: "atan", min_args: 1, max_args: 1, function: |args| number(args, f64::atan) },
+    Builtin { name: "atan2", min_args: 2, max_args: 2, function: atan2 },
+    Builtin { name: "sqrt", min_args: 1, max_args: 1, function: |args| number(args, f64::sqrt) },
+    Builtin { name: "pow", min_args: 2, max_args: 2, function: pow },
+    Builtin { name: "abs", min_args: 1, max_args: 1, function: |args| number(args, f64::abs) },
+    Builtin { name: "floor", min_args: 1, max_args: 1, function: |args| number(args, f64::floor) },
+    Builtin { name: "deg2rad", min_args: 1, max_args: 1, function: |args| number(args, f64::to_radians) },
+    Builtin { name: "min", min_args: 1, max_args: usize::MAX, function: min },
+    Builtin { name: "max", min_args: 1, max_args: usize::MAX, function: max },
+    Builtin { name: "clamp", min_args: 3, max_args: 3, function: clamp },
+    Builtin { name: "lerp", min_args: 3, max_args: 3, function: lerp },
+    Builtin { name: "animate", min_args: 2, max_args: 3, function: animate },
+    Builtin { name: "normalize", min_args: 1, max_args: 1, function: normalize },
+    Builtin { name: "length", min_args: 1, max_args: 1, function: length },
+    Builtin { name: "len", min_args: 1, max_args: 1, function: len },
+    Builtin { name: "random", min_args: 1, max_args: 1, function: random },
+    Builtin { name: "hsv", min_args: 3, max_args: 3, function: hsv },
+    Builtin { name: "checker", min_args: 2, max_args: 3, function: checker },
+    Builtin { name: "stripes", min_args: 2, max_args: 3, function: stripes },
+    Builtin { name: "gradient", min_args: 2, max_args: 2, function: gradient },
+    Builtin { name: "radial_gradient", min_args: 2, max_args: 2, function: radial_gradient },
+    Builtin { name: "noise", min_args: 2, max_args: 4, function: noise },
+    Builtin { name: "marble", min_args: 2, max_args: 4, function: marble },
+    Builtin { name: "wood", min_args: 2, max_args: 4, function: wood },
+];
+
+pub fn find_builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+impl Builtin {
+    pub fn call(&self, args: &[Value]) -> Value {
+        // FIXME: No panic
+        if args.len() < self.min_args || args.len() > self.max_args {
+            panic!(
+                "Wrong number of arguments for {}(): got {}", self.name, args.len()
+            );
+        }
+
+        (self.function)(args)
+    }
+}
+
+fn number(args: &[Value], function: fn(f64) -> f64) -> Value {
+    Value::Number(function(args[0].to_number()))
+}
+
+fn atan2(args: &[Value]) -> Value {
+    Value::Number(args[0].to_number().atan2(args[1].to_number()))
+}
+
+fn pow(args: &[Value]) -> Value {
+    Value::Number(args[0].to_number().powf(args[1].to_number()))
+}
+
+fn min(args: &[Value]) -> Value {
+    let numbers = args.iter().map(Value::to_number);
+    Value::Number(numbers.fold(f64::INFINITY, f64::min))
+}
+
+fn max(args: &[Value]) -> Value {
+    let numbers = args.iter().map(Value::to_number);
+    Value::Number(numbers.fold(f64::NEG_INFINITY, f64::max))
+}
+
+fn clamp(args: &[Value]) -> Value {
+    let x = args[0].to_number();
+    let min = args[1].to_number();
+    let max = args[2].to_number();
+
+    Value::Number(x.max(min).min(max))
+}
+
+fn lerp(args: &[Value]) -> Value {
+    let t = args[2].to_number();
+    let mix = |a: f64, b: f64| a + (b - a) * t;
+
+    match (&args[0], &args[1]) {
+        (Value::Number(a), Value::Number(b)) => Value::Number(mix(*a, *b)),
+        (Value::Vector { x: x1, y: y1, z: z1 }, Value::Vector { x: x2, y: y2, z: z2 }) => {
+            Value::Vector { x: mix(*x1, *x2), y: mix(*y1, *y2), z: mix(*z1, *z2) }
+        }
+        (Value::Color { r: r1, g: g1, b: b1, a: a1 }, Value::Color { r: r2, g: g2, b: b2, a: a2 }) => {
+            Value::Color { r: mix(*r1, *r2), g: mix(*g1, *g2), b: mix(*b1, *b2), a: mix(*a1, *a2) }
+        }
+        // FIXME: No panic
+        (a, b) => panic!("Cannot lerp between {:?} and {:?}", a, b),
+    }
+}
+
+fn normalize(args: &[Value]) -> Value {
+    let vector = args[0].to_vector().normalized();
+    Value::Vector { x: vector.x, y: vector.y, z: vector.z }
+}
+
+fn length(args: &[Value]) -> Value {
+    Value::Number(args[0].to_vector().length())
+}
+
+fn len(args: &[Value]) -> Value {
+    match &args[0] {
+        Value::List(list) => Value::Number(list.len() as f64),
+        Value::String(string) => Value::Number(string.chars().count() as f64),
+        // FIXME: No panic
+        value => panic!("Cannot get the length of {:?}", value),
+    }
+}
+
+/// Returns a number in 0..1 that is always the same for the same seed, so that
+/// every frame of an animation sees the same "random" scene.
+fn random(args: &[Value]) -> Value {
+    let seed = args[0].to_number().to_bits();
+    Value::Number(StdRng::seed_from_u64(seed).gen())
+}
+
+/// Hue, saturation and value, all in 0..1; the hue wraps around.
+fn hsv(args: &[Value]) -> Value {
+    let h = args[0].to_number().rem_euclid(1.0) * 6.0;
+    let s = args[1].to_number();
+    let v = args[2].to_number();
+
+    // A tiny negative hue wraps around to exactly 6, which is sector 5
+    let sector = h.floor().min(5.0);
+    let f = h - sector;
+    let p = v * (1.0 - s);
+    let q = v * (1.0 - s * f);
+    let t = v * (1.0 - s * (1.0 - f));
+
+    let (r, g, b) = match sector as u8 {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    };
+
+    Value::Color { r, g, b, a: 1.0 }
+}
+
+// Procedural textures all take two colors, then an optional size of the
+// pattern, then for the noisy ones an optional seed.
+
+fn procedural(name: &str, args: &[Value], pattern: Pattern) -> Value {
+    let texture = ProceduralTexture::new(pattern, args[0].to_color(), args[1].to_color());
+    Value::Texture(Texture::new(Box::new(texture), name))
+}
+
+fn optional_number(args: &[Value], index: usize, default: f64) -> f64 {
+    args.get(index).map_or(default, Value::to_number)
+}
+
+fn perlin(args: &[Value]) -> Perlin {
+    Perlin::new(optional_number(args, 3, 0.0).to_bits())
+}
+
+fn checker(args: &[Value]) -> Value {
+    let count = optional_number(args, 2, 8.0);
+    procedural("checker", args, Pattern::Checker { count })
+}
+
+fn stripes(args: &[Value]) -> Value {
+    let count = optional_number(args, 2, 8.0);
+    procedural("stripes", args, Pattern::Stripes { count })
+}
+
+fn gradient(args: &[Value]) -> Value {
+    procedural("gradient", args, Pattern::LinearGradient)
+}
+
+fn radial_gradient(args: &[Value]) -> Value {
+    procedural("radial_gradient", args, Pattern::RadialGradient)
+}
+
+fn noise(args: &[Value]) -> Value {
+    let scale = optional_number(args, 2, 8.0);
+    procedural("noise", args, Pattern::Noise { scale, perlin: perlin(args) })
+}
+
+fn marble(args: &[Value]) -> Value {
+    let scale = optional_number(args, 2, 4.0);
+    procedural("marble", args, Pattern::Marble { scale, perlin: perlin(args) })
+}
+
+fn wood(args: &[Value]) -> Value {
+    let rings = optional_number(args, 2, 8.0);
+    procedural("wood", args, Pattern::Wood { rings, perlin: perlin(args) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(value: Value) -> (f64, f64, f64) {
+        match value {
+            Value::Color { r, g, b, .. } => (r, g, b),
+            value => panic!("Expected a color, got {:?}", value),
+        }
+    }
+
+    #[test]
+    fn builtin_table() {
+        for (i, builtin) in BUILTINS.iter().enumerate() {
+            assert!(builtin.min_args <= builtin.max_args, "{}", builtin.name);
+            assert!(BUILTINS[..i].iter().all(|other| other.name != builtin.name), "{} twice", builtin.name);
+            assert_eq!(find_builtin(builtin.name).map(|found| found.max_args), Some(builtin.max_args));
+        }
+
+        let counts = |name: &str| find_builtin(name).map(|builtin| (builtin.min_args, builtin.max_args));
+        assert_eq!(counts("atan2"), Some((2, 2)));
+        assert_eq!(counts("min"), Some((1, usize::MAX)));
+        assert_eq!(counts("animate"), Some((2, 3)));
+        assert_eq!(counts("noise"), Some((2, 4)));
+        assert_eq!(counts("pi"), None);
+
+        let constant = |name: &str| CONSTANTS.iter().find(|(constant, _)| *constant == name).map(|(_, value)| *value);
+        assert_eq!(constant("pi"), Some(PI));
+        assert_eq!(constant("tau"), Some(2.0 * PI));
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong number of arguments for clamp(): got 2")]
+    fn too_few_arguments() {
+        find_builtin("clamp").unwrap().call(&[Value::Number(1.0), Value::Number(2.0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong number of arguments for sqrt(): got 2")]
+    fn too_many_arguments() {
+        find_builtin("sqrt").unwrap().call(&[Value::Number(1.0), Value::Number(2.0)]);
+    }
+
+    #[test]
+    fn hsv_hues_wrap_around() {
+        let hsv = |h: f64| color(hsv(&[Value::Number(h), Value::Number(1.0), Value::Number(1.0)]));
+
+        assert_eq!(hsv(0.0), (1.0, 0.0, 0.0));
+        assert_eq!(hsv(1.0 / 3.0), (0.0, 1.0, 0.0));
+        assert_eq!(hsv(-1.0 / 3.0), (0.0, 0.0, 1.0));
+        // Wraps around to 6 / 6
+        assert_eq!(hsv(-1e-20), (1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,1390 @@
+use crate::raytracer::color::{RaytracerPixmap, ColorPixmap, Color};
+
+use std::path::Path;
+
+// Image files that can be used as textures. Each format is recognized by the
+// first bytes of the file, or by its extension for the ones without a
+// signature; adding a format only needs a new entry in IMAGE_FORMATS.
+
+pub type DecodeFunction = fn(&[u8]) -> Result<RaytracerPixmap, String>;
+
+pub struct ImageFormat {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub matches: fn(&[u8]) -> bool,
+    pub decode: DecodeFunction,
+    // Whether its colors can be brighter than white
+    pub high_dynamic_range: bool,
+}
+
+pub const IMAGE_FORMATS: &[ImageFormat] = &[
+    ImageFormat {
+        name: "PNG",
+        extensions: &["png"],
+        matches: |bytes| bytes.starts_with(b"\x89PNG"),
+        decode: decode_png,
+        high_dynamic_range: false,
+    },
+    ImageFormat {
+        name: "JPEG",
+        extensions: &["jpg", "jpeg"],
+        matches: |bytes| bytes.starts_with(&[0xff, 0xd8]),
+        decode: jpeg::decode,
+        high_dynamic_range: false,
+    },
+    ImageFormat {
+        name: "BMP",
+        extensions: &["bmp"],
+        matches: |bytes| bytes.starts_with(b"BM"),
+        decode: decode_bmp,
+        high_dynamic_range: false,
+    },
+    ImageFormat {
+        name: "Netpbm",
+        extensions: &["pbm", "pgm", "ppm", "pnm"],
+        matches: |bytes| {
+            bytes.len() > 2 && bytes[0] == b'P' && (b'1'..=b'6').contains(&bytes[1])
+                && bytes[2].is_ascii_whitespace()
+        },
+        decode: decode_netpbm,
+        high_dynamic_range: false,
+    },
+    ImageFormat {
+        name: "PFM",
+        extensions: &["pfm"],
+        matches: |bytes| bytes.starts_with(b"PF") || bytes.starts_with(b"Pf"),
+        decode: decode_pfm,
+        high_dynamic_range: true,
+    },
+    ImageFormat {
+        name: "Radiance HDR",
+        extensions: &["hdr", "pic"],
+        matches: |bytes| bytes.starts_with(b"#?RADIANCE") || bytes.starts_with(b"#?RGBE"),
+        decode: decode_hdr,
+        high_dynamic_range: true,
+    },
+    // Last, because it has no signature at the start
+    ImageFormat {
+        name: "TGA",
+        extensions: &["tga"],
+        matches: |bytes| bytes.ends_with(b"TRUEVISION-XFILE.\0"),
+        decode: decode_tga,
+        high_dynamic_range: false,
+    },
+];
+
+pub fn find_image_format(path: &Path, bytes: &[u8]) -> Option<&'static ImageFormat> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    IMAGE_FORMATS
+        .iter()
+        .find(|format| (format.matches)(bytes))
+        .or_else(|| {
+            let extension = extension?;
+            IMAGE_FORMATS
+                .iter()
+                .find(|format| format.extensions.contains(&extension.as_str()))
+        })
+}
+
+/// Reads numbers from a byte slice, failing instead of panicking at the end.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        Reader { bytes, position }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position + count;
+        let bytes = self.bytes.get(self.position..end).ok_or("unexpected end of file")?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16_le(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u16_be(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32_le(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32_le(&mut self) -> Result<i32, String> {
+        Ok(self.u32_le()? as i32)
+    }
+
+    /// A whitespace separated word of a text header, skipping `#` comments
+    fn word(&mut self) -> Result<&'a str, String> {
+        loop {
+            match self.bytes.get(self.position) {
+                Some(b'#') => {
+                    while self.bytes.get(self.position).is_some_and(|&c| c != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => self.position += 1,
+                Some(_) => break,
+                None => return Err("unexpected end of file".to_string()),
+            }
+        }
+
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|c| !c.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .map_err(|_| "invalid header".to_string())
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, String> {
+        let word = self.word()?;
+        word.parse().map_err(|_| format!("invalid number '{}' in header", word))
+    }
+
+    fn line(&mut self) -> Result<&'a str, String> {
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|&c| c != b'\n') {
+            self.position += 1;
+        }
+        if self.position >= self.bytes.len() {
+            return Err("unexpected end of file".to_string());
+        }
+        self.position += 1;
+
+        std::str::from_utf8(&self.bytes[start..self.position - 1])
+            .map_err(|_| "invalid header".to_string())
+    }
+}
+
+/// Checks the size from the header before the pixmap is allocated, along with
+/// the least pixel data that size needs, so that a few bytes claiming to be a
+/// huge image are rejected without trying to allocate it.
+fn check_size(width: usize, height: usize, data_size: usize, available: usize) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("empty image".to_string());
+    }
+    if width.checked_mul(height).is_none_or(|size| size > 1 << 28) {
+        return Err(format!("image too large: {}x{}", width, height));
+    }
+    if data_size > available {
+        return Err(format!("unexpected end of file: {}x{} image", width, height));
+    }
+    Ok(())
+}
+
+fn color_from_u8(r: u8, g: u8, b: u8, a: u8) -> Color {
+    Color::from_u8(r, g, b, Some(a))
+}
+
+fn decode_png(bytes: &[u8]) -> Result<RaytracerPixmap, String> {
+    let png = lodepng::decode32(bytes).map_err(|err| err.to_string())?;
+    let mut pixmap = RaytracerPixmap::new(png.width, png.height);
+
+    for y in 0..png.height {
+        for x in 0..png.width {
+            let pixel = png.buffer[y * png.width + x];
+            pixmap.set_pixel_color(x, y, color_from_u8(pixel.r, pixel.g, pixel.b, pixel.a));
+        }
+    }
+
+    Ok(pixmap)
+}
+
+fn decode_bmp(bytes: &[u8]) -> Result<RaytracerPixmap, String> {
+    let mut reader = Reader::new(bytes, 10);
+    let data_offset = reader.u32_le()? as usize;
+    let header_size = reader.u32_le()? as usize;
+
+    if header_size < 40 {
+        return Err("old OS/2 bitmaps are not supported".to_string());
+    }
+
+    let width = reader.i32_le()?;
+    let height = reader.i32_le()?;
+    let _planes = reader.u16_le()?;
+    let bits = reader.u16_le()? as usize;
+    let compression = reader.u32_le()?;
+    let _image_size = reader.u32_le()?;
+    let _resolution = reader.take(8)?;
+    let colors_used = reader.u32_le()? as usize;
+    let _colors_important = reader.u32_le()?;
+
+    // Rows are stored from the bottom, unless the height is negative
+    let top_down = height < 0;
+    let (width, height) = (width.unsigned_abs() as usize, height.unsigned_abs() as usize);
+    if !matches!(bits, 1 | 2 | 4 | 8 | 16 | 24 | 32) {
+        return Err(format!("unsupported bit depth {}", bits));
+    }
+    let row_size = (width * bits).div_ceil(32) * 4;
+    check_size(width, height, row_size.saturating_mul(height), bytes.len().saturating_sub(data_offset))?;
+
+    // Bit masks for each channel; the default ones are for BI_RGB
+    let masks = match (compression, bits) {
+        (0, 16) => [0x7c00, 0x03e0, 0x001f, 0],
+        (0, 32) | (0, 24) => [0xff_0000, 0xff00, 0xff, 0],
+        (0, _) => [0, 0, 0, 0],
+        (3, 16) | (3, 32) => {
+            // Right after the header, unless they are part of it
+            let mut reader = Reader::new(bytes, 14 + 40);
+            let r = reader.u32_le()?;
+            let g = reader.u32_le()?;
+            let b = reader.u32_le()?;
+            let a = if header_size >= 56 { reader.u32_le()? } else { 0 };
+            [r, g, b, a]
+        }
+        (1, _) | (2, _) => return Err("RLE compressed bitmaps are not supported".to_string()),
+        (compression, bits) => {
+            return Err(format!("unsupported compression {} for {} bit bitmaps", compression, bits))
+        }
+    };
+
+    let palette = if bits <= 8 {
+        if colors_used > 1 << bits {
+            return Err(format!("{} palette colors for a {} bit bitmap", colors_used, bits));
+        }
+        let count = if colors_used == 0 { 1 << bits } else { colors_used };
+        let mut reader = Reader::new(bytes, 14 + header_size);
+        let mut palette = Vec::with_capacity(count);
+        for _ in 0..count {
+            let bgra = reader.take(4)?;
+            palette.push(color_from_u8(bgra[2], bgra[1], bgra[0], 255));
+        }
+        palette
+    } else {
+        vec![]
+    };
+
+    // Scales a masked channel to 0..255
+    let channel = |value: u32, mask: u32| -> u8 {
+        if mask == 0 {
+            return 255;
+        }
+        let shifted = (value & mask) >> mask.trailing_zeros();
+        let max = mask >> mask.trailing_zeros();
+        (shifted * 255 / max) as u8
+    };
+
+    let mut pixmap = RaytracerPixmap::new(width, height);
+
+    for row in 0..height {
+        let start = data_offset + row * row_size;
+        let data = bytes.get(start..start + row_size).ok_or("unexpected end of file")?;
+        let y = if top_down { row } else { height - 1 - row };
+
+        for x in 0..width {
+            let color = match bits {
+                1 | 2 | 4 | 8 => {
+                    let bit = x * bits;
+                    let index = (data[bit / 8] >> (8 - bits - bit % 8)) as usize & ((1 << bits) - 1);
+                    *palette.get(index).ok_or("palette index out of range")?
+                }
+                16 => {
+                    let value = u16::from_le_bytes([data[x * 2], data[x * 2 + 1]]) as u32;
+                    color_from_u8(
+                        channel(value, masks[0]), channel(value, masks[1]),
+                        channel(value, masks[2]), channel(value, masks[3]),
+                    )
+                }
+                24 => color_from_u8(data[x * 3 + 2], data[x * 3 + 1], data[x * 3], 255),
+                _ => {
+                    let value = u32::from_le_bytes([
+                        data[x * 4], data[x * 4 + 1], data[x * 4 + 2], data[x * 4 + 3]
+                    ]);
+                    color_from_u8(
+                        channel(value, masks[0]), channel(value, masks[1]),
+                        channel(value, masks[2]), channel(value, masks[3]),
+                    )
+                }
+            };
+            pixmap.set_pixel_color(x, y, color);
+        }
+    }
+
+    Ok(pixmap)
+}
+
+fn decode_tga(bytes: &[u8]) -> Result<RaytracerPixmap, String> {
+    let mut reader = Reader::new(bytes, 0);
+    let id_length = reader.u8()? as usize;
+    let color_map_type = reader.u8()?;
+    let image_type = reader.u8()?;
+    let color_map_first = reader.u16_le()? as usize;
+    let color_map_length = reader.u16_le()? as usize;
+    let color_map_bits = reader.u8()? as usize;
+    let _origin = reader.take(4)?;
+    let width = reader.u16_le()? as usize;
+    let height = reader.u16_le()? as usize;
+    let bits = reader.u8()? as usize;
+    let descriptor = reader.u8()?;
+    reader.take(id_length)?;
+
+    let (rle, kind) = match image_type {
+        1..=3 => (false, image_type),
+        9..=11 => (true, image_type - 8),
+        image_type => return Err(format!("unsupported TGA image type {}", image_type)),
+    };
+
+    // Checked before the size, since the size depends on them
+    let supported_depth = match kind {
+        1 => color_map_type == 1 && matches!(bits, 8 | 16) && matches!(color_map_bits, 8 | 15 | 16 | 24 | 32),
+        2 => matches!(bits, 15 | 16 | 24 | 32),
+        _ => matches!(bits, 8 | 16),
+    };
+    if !supported_depth {
+        return Err(format!("unsupported TGA bit depth {} for image type {}", bits, image_type));
+    }
+
+    // Run length packets hold up to 128 pixels, after a byte saying how many
+    let pixel_size = bits.div_ceil(8);
+    let data_size = if rle {
+        (width * height).div_ceil(128) * (1 + pixel_size)
+    } else {
+        width * height * pixel_size
+    };
+    check_size(width, height, data_size, bytes.len() - reader.position)?;
+
+    let read_color = |reader: &mut Reader, bits: usize| -> Result<Color, String> {
+        let bytes = reader.take(bits.div_ceil(8))?;
+        Ok(match bits {
+            8 => color_from_u8(bytes[0], bytes[0], bytes[0], 255),
+            15 | 16 => {
+                let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+                let scale = |v: u16| ((v & 0x1f) * 255 / 31) as u8;
+                color_from_u8(scale(value >> 10), scale(value >> 5), scale(value), 255)
+            }
+            24 => color_from_u8(bytes[2], bytes[1], bytes[0], 255),
+            32 => color_from_u8(bytes[2], bytes[1], bytes[0], bytes[3]),
+            bits => return Err(format!("unsupported TGA bit depth {}", bits)),
+        })
+    };
+
+    let mut color_map = vec![];
+    if color_map_type == 1 {
+        for _ in 0..color_map_length {
+            color_map.push(read_color(&mut reader, color_map_bits)?);
+        }
+    }
+
+    let read_pixel = |reader: &mut Reader| -> Result<Color, String> {
+        match kind {
+            1 => {
+                let index = match bits {
+                    8 => reader.u8()? as usize,
+                    16 => reader.u16_le()? as usize,
+                    bits => return Err(format!("unsupported TGA index size {}", bits)),
+                };
+                index
+                    .checked_sub(color_map_first)
+                    .and_then(|index| color_map.get(index))
+                    .copied()
+                    .ok_or_else(|| "color map index out of range".to_string())
+            }
+            3 if bits == 16 => {
+                // Gray and alpha
+                let bytes = reader.take(2)?;
+                Ok(color_from_u8(bytes[0], bytes[0], bytes[0], bytes[1]))
+            }
+            _ => read_color(reader, bits),
+        }
+    };
+
+    // Grown as pixels are read, so a lying header can't allocate much
+    let mut pixels = vec![];
+    while pixels.len() < width * height {
+        if rle {
+            let header = reader.u8()?;
+            let count = (header & 0x7f) as usize + 1;
+
+            if header & 0x80 != 0 {
+                let color = read_pixel(&mut reader)?;
+                pixels.extend(std::iter::repeat_n(color, count));
+            } else {
+                for _ in 0..count {
+                    pixels.push(read_pixel(&mut reader)?);
+                }
+            }
+        } else {
+            pixels.push(read_pixel(&mut reader)?);
+        }
+    }
+
+    // Rows go up from the bottom, unless bit 5 says otherwise
+    let top_down = descriptor & 0x20 != 0;
+    let right_to_left = descriptor & 0x10 != 0;
+    let mut pixmap = RaytracerPixmap::new(width, height);
+
+    for (index, color) in pixels.into_iter().take(width * height).enumerate() {
+        let (x, row) = (index % width, index / width);
+        let x = if right_to_left { width - 1 - x } else { x };
+        let y = if top_down { row } else { height - 1 - row };
+        pixmap.set_pixel_color(x, y, color);
+    }
+
+    Ok(pixmap)
+}
+
+fn decode_netpbm(bytes: &[u8]) -> Result<RaytracerPixmap, String> {
+    let mut reader = Reader::new(bytes, 0);
+    // Picked by the extension, so the magic number may be anything
+    let kind = match reader.word()?.as_bytes() {
+        [b'P', kind @ b'1'..=b'6'] => *kind,
+        _ => return Err("not a Netpbm file".to_string()),
+    };
+    let width: usize = reader.number()?;
+    let height: usize = reader.number()?;
+
+    let max_value: u32 = if kind == b'1' || kind == b'4' { 1 } else { reader.number()? };
+    if max_value == 0 || max_value > 65535 {
+        return Err(format!("invalid maximum value {}", max_value));
+    }
+
+    let channels = match kind {
+        b'3' | b'6' => 3,
+        _ => 1,
+    };
+    let sample_count = width.saturating_mul(height).saturating_mul(channels);
+    // At least a digit for each sample in the plain formats
+    let data_size = match kind {
+        b'4' => width.div_ceil(8).saturating_mul(height),
+        b'5' | b'6' if max_value > 255 => sample_count.saturating_mul(2),
+        _ => sample_count,
+    };
+    check_size(width, height, data_size, bytes.len() - reader.position)?;
+
+    // A single whitespace character separates the header from binary data
+    if kind >= b'4' {
+        reader.position += 1;
+    }
+
+    let mut samples = Vec::with_capacity(sample_count);
+
+    match kind {
+        b'1' => {
+            // Bits may be written without spaces in between
+            for _ in 0..sample_count {
+                let bit = loop {
+                    match reader.u8()? {
+                        b'#' => {
+                            while reader.bytes.get(reader.position).is_some_and(|&c| c != b'\n') {
+                                reader.position += 1;
+                            }
+                        }
+                        c if c.is_ascii_whitespace() => (),
+                        c => break c,
+                    }
+                };
+                match bit {
+                    b'0' | b'1' => samples.push((bit - b'0') as u32),
+                    _ => return Err(format!("invalid bit '{}' in bitmap", bit as char)),
+                }
+            }
+        }
+        b'2' | b'3' => {
+            for _ in 0..sample_count {
+                samples.push(reader.number()?);
+            }
+        }
+        b'4' => {
+            let row_size = width.div_ceil(8);
+            for _ in 0..height {
+                let row = reader.take(row_size)?;
+                for x in 0..width {
+                    samples.push(((row[x / 8] >> (7 - x % 8)) & 1) as u32);
+                }
+            }
+        }
+        _ => {
+            for _ in 0..sample_count {
+                let sample = if max_value < 256 {
+                    reader.u8()? as u32
+                } else {
+                    reader.u16_be()? as u32
+                };
+                samples.push(sample);
+            }
+        }
+    }
+
+    let mut pixmap = RaytracerPixmap::new(width, height);
+    let scale = |sample: u32| sample.min(max_value) as f64 / max_value as f64;
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) * channels;
+            let color = match kind {
+                // In bitmaps 1 is black
+                b'1' | b'4' => {
+                    let value = 1.0 - scale(samples[index]);
+                    Color::new(value, value, value, 1.0)
+                }
+                b'3' | b'6' => Color::new(
+                    scale(samples[index]), scale(samples[index + 1]), scale(samples[index + 2]), 1.0
+                ),
+                _ => {
+                    let value = scale(samples[index]);
+                    Color::new(value, value, value, 1.0)
+                }
+            };
+            pixmap.set_pixel_color(x, y, color);
+        }
+    }
+
+    Ok(pixmap)
+}
+
+fn decode_pfm(bytes: &[u8]) -> Result<RaytracerPixmap, String> {
+    let mut reader = Reader::new(bytes, 0);
+    let channels = match reader.word()? {
+        "PF" => 3,
+        "Pf" => 1,
+        kind => return Err(format!("unknown PFM type '{}'", kind)),
+    };
+    let width: usize = reader.number()?;
+    let height: usize = reader.number()?;
+    let scale: f64 = reader.number()?;
+    let data_size = width.saturating_mul(height).saturating_mul(channels * 4);
+    check_size(width, height, data_size, bytes.len() - reader.position)?;
+    reader.position += 1;
+
+    // The sign of the scale gives the byte order
+    let little_endian = scale < 0.0;
+    let mut pixmap = RaytracerPixmap::new(width, height);
+
+    for row in 0..height {
+        for x in 0..width {
+            let mut values = [0.0; 3];
+            for value in values.iter_mut().take(channels) {
+                let bytes = reader.take(4)?;
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                *value = if little_endian {
+                    f32::from_le_bytes(bytes)
+                } else {
+                    f32::from_be_bytes(bytes)
+                } as f64;
+            }
+            if channels == 1 {
+                values = [values[0]; 3];
+            }
+
+            // Rows go up from the bottom
+            let color = Color::new(values[0], values[1], values[2], 1.0);
+            pixmap.set_pixel_color(x, height - 1 - row, color);
+        }
+    }
+
+    Ok(pixmap)
+}
+
+fn decode_hdr(bytes: &[u8]) -> Result<RaytracerPixmap, String> {
+    let mut reader = Reader::new(bytes, 0);
+
+    // Header lines end with an empty one
+    loop {
+        let line = reader.line()?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported HDR format '{}'", format));
+            }
+        }
+    }
+
+    let resolution = reader.line()?;
+    let words: Vec<&str> = resolution.split_whitespace().collect();
+    let (width, height, bottom_up) = match words.as_slice() {
+        ["-Y", height, "+X", width] => (width, height, false),
+        ["+Y", height, "+X", width] => (width, height, true),
+        _ => return Err(format!("unsupported HDR orientation '{}'", resolution)),
+    };
+    let width: usize = width.parse().map_err(|_| "invalid HDR width".to_string())?;
+    let height: usize = height.parse().map_err(|_| "invalid HDR height".to_string())?;
+    // Each scanline starts with a pixel or a run length header
+    check_size(width, height, height.saturating_mul(4), bytes.len() - reader.position)?;
+
+    let mut pixmap = RaytracerPixmap::new(width, height);
+    let mut scanline = vec![[0u8; 4]; width];
+
+    for row in 0..height {
+        read_hdr_scanline(&mut reader, &mut scanline)?;
+
+        let y = if bottom_up { height - 1 - row } else { row };
+        for (x, rgbe) in scanline.iter().enumerate() {
+            // A shared exponent for the three channels
+            let color = if rgbe[3] == 0 {
+                Color::new(0.0, 0.0, 0.0, 1.0)
+            } else {
+                let scale = 2f64.powi(rgbe[3] as i32 - 136);
+                Color::new(
+                    rgbe[0] as f64 * scale, rgbe[1] as f64 * scale, rgbe[2] as f64 * scale, 1.0
+                )
+            };
+            pixmap.set_pixel_color(x, y, color);
+        }
+    }
+
+    Ok(pixmap)
+}
+
+fn read_hdr_scanline(reader: &mut Reader, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+    let width = scanline.len();
+    let start = reader.position;
+    let header = reader.take(4)?;
+
+    // Newer run length encoding, with each channel stored separately
+    if (8..0x8000).contains(&width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0 {
+        if ((header[2] as usize) << 8 | header[3] as usize) != width {
+            return Err("HDR scanline width mismatch".to_string());
+        }
+
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = reader.u8()? as usize;
+                let (count, run) = if count > 128 { (count - 128, true) } else { (count, false) };
+
+                if count == 0 || x + count > width {
+                    return Err("bad HDR scanline data".to_string());
+                }
+
+                if run {
+                    let value = reader.u8()?;
+                    for pixel in &mut scanline[x..x + count] {
+                        pixel[channel] = value;
+                    }
+                } else {
+                    for pixel in &mut scanline[x..x + count] {
+                        pixel[channel] = reader.u8()?;
+                    }
+                }
+                x += count;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Flat pixels, where (1, 1, 1, n) repeats the previous one
+    reader.position = start;
+    let mut x = 0;
+    let mut shift = 0;
+    while x < width {
+        let rgbe = reader.take(4)?;
+        if rgbe[0] == 1 && rgbe[1] == 1 && rgbe[2] == 1 {
+            let previous = *scanline.get(x.wrapping_sub(1)).ok_or("bad HDR scanline data")?;
+            // Each marker in a row counts 256 times more than the one before
+            if rgbe[3] == 0 || shift > 24 {
+                return Err("bad HDR scanline data".to_string());
+            }
+            let count = (rgbe[3] as usize) << shift;
+            for pixel in scanline.iter_mut().skip(x).take(count) {
+                *pixel = previous;
+            }
+            x += count;
+            shift += 8;
+        } else {
+            scanline[x] = [rgbe[0], rgbe[1], rgbe[2], rgbe[3]];
+            x += 1;
+            shift = 0;
+        }
+    }
+
+    Ok(())
+}
+
+/// Baseline (sequential, Huffman coded) JPEG, as written by most cameras
+/// and image editors; progressive and arithmetic coded files are rejected.
+mod jpeg {
+    use super::{Reader, check_size};
+    use crate::raytracer::color::{RaytracerPixmap, ColorPixmap, Color};
+
+    const ZIGZAG: [usize; 64] = [
+        0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+        12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+        35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+        58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+    ];
+
+    #[derive(Clone, Default)]
+    struct HuffmanTable {
+        // For each code length, the first code, and where its values start
+        first_code: [i32; 17],
+        max_code: [i32; 17],
+        first_value: [usize; 17],
+        values: Vec<u8>,
+    }
+
+    impl HuffmanTable {
+        fn new(counts: &[u8], values: &[u8]) -> Self {
+            let mut table = HuffmanTable { values: values.to_vec(), ..Default::default() };
+            let mut code = 0;
+            let mut index = 0;
+
+            for length in 1..=16 {
+                let count = counts[length - 1] as usize;
+                table.first_code[length] = code;
+                table.first_value[length] = index;
+                table.max_code[length] = if count == 0 { -1 } else { code + count as i32 - 1 };
+                code = (code + count as i32) << 1;
+                index += count;
+            }
+
+            table
+        }
+    }
+
+    struct Component {
+        id: u8,
+        h: usize,
+        v: usize,
+        quantization: usize,
+        dc_table: usize,
+        ac_table: usize,
+        dc_prediction: i32,
+        // Blocks covering whole MCUs, so may be larger than the image
+        width: usize,
+        height: usize,
+        samples: Vec<u8>,
+    }
+
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+        buffer: u32,
+        bits: u32,
+    }
+
+    impl<'a> BitReader<'a> {
+        fn bit(&mut self) -> Result<u32, String> {
+            if self.bits == 0 {
+                let byte = *self.bytes.get(self.position).ok_or("unexpected end of JPEG data")?;
+                self.position += 1;
+
+                if byte == 0xff {
+                    match self.bytes.get(self.position) {
+                        // A stuffed zero after a real 0xff byte
+                        Some(0) => self.position += 1,
+                        // Past the data; keep feeding ones like libjpeg does
+                        _ => self.position -= 1,
+                    }
+                }
+                self.buffer = byte as u32;
+                self.bits = 8;
+            }
+
+            self.bits -= 1;
+            Ok((self.buffer >> self.bits) & 1)
+        }
+
+        fn bits(&mut self, count: u32) -> Result<i32, String> {
+            let mut value = 0;
+            for _ in 0..count {
+                value = (value << 1) | self.bit()? as i32;
+            }
+            Ok(value)
+        }
+
+        fn decode(&mut self, table: &HuffmanTable) -> Result<u8, String> {
+            let mut code = 0;
+            for length in 1..=16 {
+                code = (code << 1) | self.bit()? as i32;
+                if code <= table.max_code[length] {
+                    let index = table.first_value[length] + (code - table.first_code[length]) as usize;
+                    return table.values.get(index).copied().ok_or_else(|| "bad Huffman code".to_string());
+                }
+            }
+            Err("bad Huffman code".to_string())
+        }
+
+        /// Reads a value of `size` bits, where the low half is negative
+        fn receive_extend(&mut self, size: u8) -> Result<i32, String> {
+            if size == 0 {
+                return Ok(0);
+            }
+            let value = self.bits(size as u32)?;
+            Ok(if value < 1 << (size - 1) { value - (1 << size) + 1 } else { value })
+        }
+
+        /// Skips to the RSTn marker that follows a restart interval
+        fn restart(&mut self) -> Result<(), String> {
+            self.bits = 0;
+            while self.position + 1 < self.bytes.len() {
+                if self.bytes[self.position] == 0xff && (0xd0..=0xd7).contains(&self.bytes[self.position + 1]) {
+                    self.position += 2;
+                    return Ok(());
+                }
+                self.position += 1;
+            }
+            Err("missing JPEG restart marker".to_string())
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<RaytracerPixmap, String> {
+        let mut reader = Reader::new(bytes, 2);
+        let mut quantization = [[0u16; 64]; 4];
+        let mut dc_tables = vec![HuffmanTable::default(); 4];
+        let mut ac_tables = vec![HuffmanTable::default(); 4];
+        let mut components: Vec<Component> = vec![];
+        let mut size = (0, 0);
+        let mut restart_interval = 0;
+
+        loop {
+            // Markers can be padded with any number of 0xff bytes
+            let mut marker = reader.u8()?;
+            if marker != 0xff {
+                return Err("invalid JPEG marker".to_string());
+            }
+            while marker == 0xff {
+                marker = reader.u8()?;
+            }
+
+            if marker == 0xd9 {
+                return Err("JPEG file has no image data".to_string());
+            }
+
+            let length = reader.u16_be()? as usize;
+            let mut segment = Reader::new(reader.take(length.saturating_sub(2))?, 0);
+
+            match marker {
+                // Quantization tables
+                0xdb => {
+                    while segment.position < segment.bytes.len() {
+                        let info = segment.u8()?;
+                        let table = quantization.get_mut((info & 3) as usize).ok_or("bad table")?;
+                        for &index in ZIGZAG.iter() {
+                            table[index] = if info >> 4 == 0 {
+                                segment.u8()? as u16
+                            } else {
+                                segment.u16_be()?
+                            };
+                        }
+                    }
+                }
+                // Huffman tables
+                0xc4 => {
+                    while segment.position < segment.bytes.len() {
+                        let info = segment.u8()?;
+                        let counts = segment.take(16)?;
+                        let total = counts.iter().map(|&count| count as usize).sum();
+                        let values = segment.take(total)?;
+                        let table = HuffmanTable::new(counts, values);
+
+                        if info >> 4 == 0 {
+                            dc_tables[(info & 3) as usize] = table;
+                        } else {
+                            ac_tables[(info & 3) as usize] = table;
+                        }
+                    }
+                }
+                // Baseline and extended sequential frames
+                0xc0 | 0xc1 => {
+                    let precision = segment.u8()?;
+                    if precision != 8 {
+                        return Err(format!("{} bit JPEG is not supported", precision));
+                    }
+                    let height = segment.u16_be()? as usize;
+                    let width = segment.u16_be()? as usize;
+                    // Every 8x8 block takes at least two bits, even when flat
+                    let blocks = width.div_ceil(8) * height.div_ceil(8);
+                    check_size(width, height, blocks.div_ceil(4), bytes.len() - reader.position)?;
+                    size = (width, height);
+
+                    for _ in 0..segment.u8()? {
+                        let id = segment.u8()?;
+                        let sampling = segment.u8()?;
+                        let (h, v) = ((sampling >> 4) as usize, (sampling & 15) as usize);
+                        if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                            return Err("invalid JPEG sampling factors".to_string());
+                        }
+                        components.push(Component {
+                            id,
+                            h,
+                            v,
+                            quantization: (segment.u8()? & 3) as usize,
+                            dc_table: 0,
+                            ac_table: 0,
+                            dc_prediction: 0,
+                            width: 0,
+                            height: 0,
+                            samples: vec![],
+                        });
+                    }
+                }
+                0xc2 | 0xc6 | 0xca | 0xce => {
+                    return Err("progressive JPEG is not supported".to_string());
+                }
+                0xc3 | 0xc5 | 0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                    return Err("lossless, hierarchical and arithmetic coded JPEG are not supported".to_string());
+                }
+                0xdd => restart_interval = segment.u16_be()? as usize,
+                // Start of scan
+                0xda => {
+                    if components.is_empty() {
+                        return Err("JPEG scan before frame header".to_string());
+                    }
+                    for _ in 0..segment.u8()? {
+                        let id = segment.u8()?;
+                        let tables = segment.u8()?;
+                        let component = components
+                            .iter_mut()
+                            .find(|component| component.id == id)
+                            .ok_or("JPEG scan uses an unknown component")?;
+                        component.dc_table = (tables >> 4 & 3) as usize;
+                        component.ac_table = (tables & 3) as usize;
+                    }
+
+                    let mut bit_reader = BitReader {
+                        bytes: &bytes[reader.position..],
+                        position: 0,
+                        buffer: 0,
+                        bits: 0,
+                    };
+                    decode_scan(
+                        &mut bit_reader, &mut components, size, restart_interval,
+                        &quantization, &dc_tables, &ac_tables,
+                    )?;
+
+                    return to_pixmap(&components, size);
+                }
+                // Application data, comments and the like
+                _ => (),
+            }
+        }
+    }
+
+    fn decode_scan(
+        reader: &mut BitReader, components: &mut [Component], (width, height): (usize, usize),
+        restart_interval: usize, quantization: &[[u16; 64]; 4],
+        dc_tables: &[HuffmanTable], ac_tables: &[HuffmanTable],
+    ) -> Result<(), String> {
+        let h_max = components.iter().map(|component| component.h).max().unwrap();
+        let v_max = components.iter().map(|component| component.v).max().unwrap();
+        let single = components.len() == 1;
+
+        // With one component there are no MCUs, just blocks covering the image
+        let (mcu_width, mcu_height) = if single { (8, 8) } else { (8 * h_max, 8 * v_max) };
+        let mcus_x = width.div_ceil(mcu_width);
+        let mcus_y = height.div_ceil(mcu_height);
+
+        for component in components.iter_mut() {
+            let (h, v) = if single { (1, 1) } else { (component.h, component.v) };
+            component.width = mcus_x * h * 8;
+            component.height = mcus_y * v * 8;
+            component.samples = vec![0; component.width * component.height];
+        }
+
+        let mut block = [0i32; 64];
+
+        for mcu in 0..mcus_x * mcus_y {
+            if restart_interval != 0 && mcu != 0 && mcu % restart_interval == 0 {
+                reader.restart()?;
+                for component in components.iter_mut() {
+                    component.dc_prediction = 0;
+                }
+            }
+
+            let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+
+            for component in components.iter_mut() {
+                let (h, v) = if single { (1, 1) } else { (component.h, component.v) };
+
+                for block_y in 0..v {
+                    for block_x in 0..h {
+                        block.iter_mut().for_each(|value| *value = 0);
+
+                        // 8 bit samples need at most 11 bits for DC and 10 for AC values
+                        let size = reader.decode(&dc_tables[component.dc_table])?;
+                        if size > 11 {
+                            return Err("bad JPEG block".to_string());
+                        }
+                        component.dc_prediction += reader.receive_extend(size)?;
+                        if component.dc_prediction.abs() > 2047 {
+                            return Err("bad JPEG block".to_string());
+                        }
+                        block[0] = component.dc_prediction;
+
+                        let mut index = 1;
+                        while index < 64 {
+                            let symbol = reader.decode(&ac_tables[component.ac_table])?;
+                            let (run, size) = ((symbol >> 4) as usize, symbol & 15);
+
+                            if size == 0 {
+                                if run == 15 {
+                                    index += 16;
+                                    continue;
+                                }
+                                break;
+                            }
+
+                            index += run;
+                            if index >= 64 || size > 10 {
+                                return Err("bad JPEG block".to_string());
+                            }
+                            block[ZIGZAG[index]] = reader.receive_extend(size)?;
+                            index += 1;
+                        }
+
+                        let table = &quantization[component.quantization];
+                        for (value, &factor) in block.iter_mut().zip(table.iter()) {
+                            *value *= factor as i32;
+                        }
+
+                        let x = (mcu_x * h + block_x) * 8;
+                        let y = (mcu_y * v + block_y) * 8;
+                        idct(&block, component, x, y);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Inverse DCT of a block, written to the component at (x, y)
+    fn idct(block: &[i32; 64], component: &mut Component, x: usize, y: usize) {
+        use std::f64::consts::PI;
+
+        let mut cosines = [[0.0; 8]; 8];
+        for (i, row) in cosines.iter_mut().enumerate() {
+            for (u, cosine) in row.iter_mut().enumerate() {
+                let scale = if u == 0 { 1.0 / 2f64.sqrt() } else { 1.0 };
+                *cosine = scale * ((2 * i + 1) as f64 * u as f64 * PI / 16.0).cos();
+            }
+        }
+
+        // Rows, then columns
+        let mut rows = [0.0; 64];
+        for v in 0..8 {
+            for i in 0..8 {
+                rows[v * 8 + i] = (0..8)
+                    .map(|u| cosines[i][u] * block[v * 8 + u] as f64)
+                    .sum::<f64>() / 2.0;
+            }
+        }
+
+        for (j, row) in cosines.iter().enumerate() {
+            for i in 0..8 {
+                let value = (0..8)
+                    .map(|v| row[v] * rows[v * 8 + i])
+                    .sum::<f64>() / 2.0;
+                let sample = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+                component.samples[(y + j) * component.width + x + i] = sample;
+            }
+        }
+    }
+
+    fn to_pixmap(components: &[Component], (width, height): (usize, usize)) -> Result<RaytracerPixmap, String> {
+        let h_max = components.iter().map(|component| component.h).max().unwrap();
+        let v_max = components.iter().map(|component| component.v).max().unwrap();
+
+        // Subsampled components cover the same area with fewer samples
+        let sample = |component: &Component, x: usize, y: usize| -> f64 {
+            let x = x * component.h / h_max;
+            let y = y * component.v / v_max;
+            component.samples[y * component.width + x] as f64
+        };
+
+        let mut pixmap = RaytracerPixmap::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let color = match components {
+                    [gray] => {
+                        let value = sample(gray, x, y) / 255.0;
+                        Color::new(value, value, value, 1.0)
+                    }
+                    [y_component, cb, cr] => {
+                        let luma = sample(y_component, x, y);
+                        let cb = sample(cb, x, y) - 128.0;
+                        let cr = sample(cr, x, y) - 128.0;
+
+                        let channel = |value: f64| (value / 255.0).clamp(0.0, 1.0);
+                        Color::new(
+                            channel(luma + 1.402 * cr),
+                            channel(luma - 0.344136 * cb - 0.714136 * cr),
+                            channel(luma + 1.772 * cb),
+                            1.0,
+                        )
+                    }
+                    _ => return Err(format!("JPEG with {} components is not supported", components.len())),
+                };
+                pixmap.set_pixel_color(x, y, color);
+            }
+        }
+
+        Ok(pixmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color(pixmap: &RaytracerPixmap, x: usize, y: usize, (r, g, b, a): (u8, u8, u8, u8), tolerance: u8) {
+        let color = pixmap.get_pixel_color(x, y);
+        let expected = color_from_u8(r, g, b, a);
+        let tolerance = tolerance as f64 / 255.0 + 1e-9;
+
+        assert!(
+            (color.r - expected.r).abs() <= tolerance && (color.g - expected.g).abs() <= tolerance
+                && (color.b - expected.b).abs() <= tolerance && (color.a - expected.a).abs() <= tolerance,
+            "({}, {}) is {:?}, expected {:?}", x, y, color, expected
+        );
+    }
+
+    fn decode(name: &str, bytes: &[u8]) -> Result<RaytracerPixmap, String> {
+        let format = find_image_format(Path::new(name), bytes).ok_or("unknown format")?;
+        (format.decode)(bytes)
+    }
+
+    /// 16x16 with 4:2:0 chroma and a quantization table of ones: orange on
+    /// top, a gray ramp from black going right below. Each Huffman table
+    /// gives all of its values codes of the same length.
+    fn jpeg(dc_values: &[u8; 5], ac_values: &[u8; 10]) -> Vec<u8> {
+        let mut bytes = vec![0xff, 0xd8, 0xff, 0xdb, 0x00, 0x43, 0x00];
+        bytes.extend_from_slice(&[1; 64]);
+        bytes.extend_from_slice(&[
+            0xff, 0xc0, 0x00, 0x11, 0x08, 0x00, 0x10, 0x00, 0x10,
+            0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x00, 0x03, 0x11, 0x00,
+            0xff, 0xc4, 0x00, 0x18, 0x00, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+        bytes.extend_from_slice(dc_values);
+        bytes.extend_from_slice(&[0xff, 0xc4, 0x00, 0x1d, 0x10, 0, 0, 0, 0, 0, 0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(ac_values);
+        bytes.extend_from_slice(&[
+            0xff, 0xda, 0x00, 0x0c, 0x03, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x00, 0x3f, 0x00,
+            0x1b, 0xb0, 0x00, 0x00, 0x34, 0x10, 0x05, 0xb6, 0x06, 0x10, 0x56, 0x08, 0x40, 0x12, 0x00,
+            0x00, 0xb6, 0xc0, 0xc2, 0x0a, 0xc1, 0x08, 0x01, 0x2a, 0x40, 0x99, 0x20, 0x9b, 0x41, 0xdb, 0x04,
+            0xfc, 0x00, 0x52, 0x80, 0x28, 0x60, 0x22, 0x10, 0x6f, 0xc2, 0x4a, 0x00, 0xff, 0xd9,
+        ]);
+        bytes
+    }
+
+    const JPEG_DC_VALUES: [u8; 5] = [0x00, 0x08, 0x09, 0x0a, 0x0b];
+    const JPEG_AC_VALUES: [u8; 10] = [0x00, 0x09, 0x19, 0x45, 0x67, 0x84, 0xa6, 0xa7, 0xc2, 0xe6];
+
+    #[test]
+    fn jpeg_baseline() {
+        let pixmap = decode("image.jpg", &jpeg(&JPEG_DC_VALUES, &JPEG_AC_VALUES)).unwrap();
+
+        assert_eq!((pixmap.get_width(), pixmap.get_height()), (16, 16));
+        for y in 0..16 {
+            for x in 0..16 {
+                let expected = if y < 8 { (255, 128, 0, 255) } else { (x as u8 * 16, x as u8 * 16, x as u8 * 16, 255) };
+                assert_color(&pixmap, x, y, expected, 3);
+            }
+        }
+    }
+
+    #[test]
+    fn jpeg_sizes_out_of_range() {
+        // Every DC value 32 bits long, or AC values 11 bits long
+        let error = decode("image.jpg", &jpeg(&[0x20; 5], &JPEG_AC_VALUES)).err();
+        assert_eq!(error.as_deref(), Some("bad JPEG block"));
+
+        let error = decode("image.jpg", &jpeg(&JPEG_DC_VALUES, &[0x0b; 10])).err();
+        assert_eq!(error.as_deref(), Some("bad JPEG block"));
+
+        assert!(decode("image.jpg", &jpeg(&[0xff; 5], &JPEG_AC_VALUES)).is_err());
+    }
+
+    fn bmp(width: i32, height: i32, bits: u16, colors_used: u32, palette: &[[u8; 4]], data: &[u8]) -> Vec<u8> {
+        let data_offset = 14 + 40 + palette.len() as u32 * 4;
+        let mut bytes = b"BM".to_vec();
+        bytes.extend_from_slice(&(data_offset + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&data_offset.to_le_bytes());
+        bytes.extend_from_slice(&40u32.to_le_bytes());
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&bits.to_le_bytes());
+        // No compression, image size and resolution
+        bytes.extend_from_slice(&[0; 16]);
+        bytes.extend_from_slice(&colors_used.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        palette.iter().for_each(|color| bytes.extend_from_slice(color));
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn bmp_true_color_and_palette() {
+        // Bottom row first, each padded to four bytes
+        let data = [255, 0, 0, 0, 255, 0, 0, 0, 0, 0, 255, 255, 255, 255, 0, 0];
+        let pixmap = decode("image.bmp", &bmp(2, 2, 24, 0, &[], &data)).unwrap();
+        assert_color(&pixmap, 0, 0, (255, 0, 0, 255), 0);
+        assert_color(&pixmap, 1, 0, (255, 255, 255, 255), 0);
+        assert_color(&pixmap, 0, 1, (0, 0, 255, 255), 0);
+        assert_color(&pixmap, 1, 1, (0, 255, 0, 255), 0);
+
+        // Top down, with two colors
+        let palette = [[0, 0, 0, 0], [255, 255, 255, 0]];
+        let pixmap = decode("image.bmp", &bmp(3, -1, 1, 2, &palette, &[0b1010_0000, 0, 0, 0])).unwrap();
+        assert_color(&pixmap, 0, 0, (255, 255, 255, 255), 0);
+        assert_color(&pixmap, 1, 0, (0, 0, 0, 255), 0);
+        assert_color(&pixmap, 2, 0, (255, 255, 255, 255), 0);
+    }
+
+    #[test]
+    fn bmp_header_out_of_range() {
+        let error = decode("image.bmp", &bmp(1, 1, 8, 0xffff_ffff, &[], &[0; 4])).err();
+        assert_eq!(error.as_deref(), Some("4294967295 palette colors for a 8 bit bitmap"));
+
+        let error = decode("image.bmp", &bmp(16384, 16384, 24, 0, &[], &[0; 16])).err();
+        assert_eq!(error.as_deref(), Some("unexpected end of file: 16384x16384 image"));
+    }
+
+    #[test]
+    fn tga() {
+        let header = |image_type: u8, width: u8| {
+            vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0, width, 0, 1, 0, 24, 0x20]
+        };
+
+        let mut bytes = header(2, 2);
+        bytes.extend_from_slice(&[0, 0, 255, 0, 255, 0]);
+        let pixmap = decode("image.tga", &bytes).unwrap();
+        assert_color(&pixmap, 0, 0, (255, 0, 0, 255), 0);
+        assert_color(&pixmap, 1, 0, (0, 255, 0, 255), 0);
+
+        // A run of two blue pixels, then a single red one
+        let mut bytes = header(10, 3);
+        bytes.extend_from_slice(&[0x81, 255, 0, 0, 0x00, 0, 0, 255]);
+        let pixmap = decode("image.tga", &bytes).unwrap();
+        assert_color(&pixmap, 0, 0, (0, 0, 255, 255), 0);
+        assert_color(&pixmap, 1, 0, (0, 0, 255, 255), 0);
+        assert_color(&pixmap, 2, 0, (255, 0, 0, 255), 0);
+    }
+
+    #[test]
+    fn tga_malformed() {
+        // 6912x29952 with a pixel depth of 0, which would need no data
+        let bytes = [0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 27, 0, 117, 0, 0x20, 0, 0, 0, 0, 0, 0, 0];
+        let error = decode("image.tga", &bytes).err();
+        assert_eq!(error.as_deref(), Some("unsupported TGA bit depth 0 for image type 2"));
+
+        // Color mapped, but without a color map
+        let bytes = [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 8, 0x20, 0];
+        assert!(decode("image.tga", &bytes).is_err());
+
+        let bytes = [0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 64, 0, 64, 24, 0x20, 0];
+        let error = decode("image.tga", &bytes).err();
+        assert_eq!(error.as_deref(), Some("unsupported TGA bit depth 24 for image type 3"));
+    }
+
+    #[test]
+    fn netpbm() {
+        let pixmap = decode("image.ppm", b"P3\n# comment\n2 1\n255\n255 0 0  0 0 255\n").unwrap();
+        assert_color(&pixmap, 0, 0, (255, 0, 0, 255), 0);
+        assert_color(&pixmap, 1, 0, (0, 0, 255, 255), 0);
+
+        let pixmap = decode("image.ppm", b"P6 2 1 255\n\xff\x00\x00\x00\xff\x00").unwrap();
+        assert_color(&pixmap, 0, 0, (255, 0, 0, 255), 0);
+        assert_color(&pixmap, 1, 0, (0, 255, 0, 255), 0);
+
+        let pixmap = decode("image.pgm", b"P5\n1 1\n65535\n\x80\x00").unwrap();
+        assert_color(&pixmap, 0, 0, (128, 128, 128, 255), 1);
+
+        // In bitmaps 1 is black
+        let pixmap = decode("image.pbm", b"P4\n10 1\n\x80\x40").unwrap();
+        for x in 0..10 {
+            let value = if x == 0 || x == 9 { 0 } else { 255 };
+            assert_color(&pixmap, x, 0, (value, value, value, 255), 0);
+        }
+    }
+
+    #[test]
+    fn netpbm_plain_bitmap() {
+        // Bits may be packed or separated
+        let pixmap = decode("image.pbm", b"P1\n4 2\n0110\n1 0 # comment\n0 1\n").unwrap();
+        for (x, &bit) in [0, 1, 1, 0, 1, 0, 0, 1].iter().enumerate() {
+            let value = if bit == 1 { 0 } else { 255 };
+            assert_color(&pixmap, x % 4, x / 4, (value, value, value, 255), 0);
+        }
+
+        let error = decode("image.pbm", b"P1\n1 1\n+\n").err();
+        assert_eq!(error.as_deref(), Some("invalid bit '+' in bitmap"));
+    }
+
+    #[test]
+    fn netpbm_malformed() {
+        // Picked by the extension
+        assert_eq!(decode("image.ppm", b"X\n").err().as_deref(), Some("not a Netpbm file"));
+        assert_eq!(decode("image.ppm", b"P7\n1 1\n").err().as_deref(), Some("not a Netpbm file"));
+
+        let error = decode("image.ppm", b"P6\n16384 16384\n255\n\x00\x00\x00").err();
+        assert_eq!(error.as_deref(), Some("unexpected end of file: 16384x16384 image"));
+        assert!(decode("image.ppm", b"P3\n2 1\n255\n1 2 3 4 5\n").is_err());
+    }
+
+    #[test]
+    fn pfm() {
+        let mut bytes = b"PF\n1 1\n-1.0\n".to_vec();
+        for value in [0.5f32, 2.0, 0.25].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let color = decode("image.pfm", &bytes).unwrap().get_pixel_color(0, 0);
+        assert_eq!((color.r, color.g, color.b), (0.5, 2.0, 0.25));
+    }
+
+    fn hdr(width: usize, data: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X {}\n", width).into_bytes();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn hdr_flat_and_run_length() {
+        // (1, 0.5, 0.25) as 128, 64 and 32 times 2^(129 - 136)
+        let expected = |pixmap: &RaytracerPixmap, width: usize| {
+            for x in 0..width {
+                let color = pixmap.get_pixel_color(x, 0);
+                assert_eq!((color.r, color.g, color.b), (1.0, 0.5, 0.25), "at {}", x);
+            }
+        };
+
+        // A pixel, repeated twice
+        let pixmap = decode("image.hdr", &hdr(3, &[128, 64, 32, 129, 1, 1, 1, 2])).unwrap();
+        expected(&pixmap, 3);
+
+        // One run of eight for each channel
+        let data = [2, 2, 0, 8, 0x88, 128, 0x88, 64, 0x88, 32, 0x88, 129];
+        let pixmap = decode("image.hdr", &hdr(8, &data)).unwrap();
+        expected(&pixmap, 8);
+    }
+
+    #[test]
+    fn hdr_malformed() {
+        // Repeat markers with a count of 0
+        let mut data = vec![128, 64, 32, 129];
+        for _ in 0..9 {
+            data.extend_from_slice(&[1, 1, 1, 0]);
+        }
+        assert_eq!(decode("image.hdr", &hdr(16, &data)).err().as_deref(), Some("bad HDR scanline data"));
+
+        let bytes = b"#?RADIANCE\n\n-Y 16384 +X 16384\n\x80\x40\x20\x81";
+        let error = decode("image.hdr", bytes).err();
+        assert_eq!(error.as_deref(), Some("unexpected end of file: 16384x16384 image"));
+    }
+}
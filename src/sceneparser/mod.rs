@@ -3,5 +3,10 @@ pub mod ast_node;
 pub mod context;
 pub mod shape;
 pub mod texture;
+pub mod image_format;
 pub mod value;
-pub mod function;
\ No newline at end of file
+pub mod function;
+pub mod animation;
+pub mod formula;
+pub mod formatter;
+pub mod checker;
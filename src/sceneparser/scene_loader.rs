@@ -1,11 +1,13 @@
 use crate::raytracer::raytracer::RayTracer;
+use crate::raytracer::animation::Animation;
 
-use super::ast_node::AstStatement;
+use super::ast_node::{AstStatement, AstExpression};
 use super::context::SceneContext;
 
 use pest::Parser;
 use pest::iterators::Pairs;
 use pest_derive::Parser;
+use std::fmt::{Display, Formatter, Error};
 use std::fs::File;
 use std::io::Read;
 use crate::sceneparser::value::Value;
@@ -21,18 +23,71 @@ b = sphere(<-15, -5, -10>, 25)
 draw(csg(a, b, 'difference', rgb(0.0, 1.0, 1.0), 0.0, 0.8))
 ";
 
-pub fn load_scene(ray_tracer: &mut RayTracer, time: f64) -> Result<(), pest::error::Error<Rule>> {
-    let scene = File::open("globes.scene")
+#[derive(Debug)]
+pub enum SceneError {
+    Parse(pest::error::Error<Rule>),
+    /// The scene ran, but with the given problems
+    Execution(Vec<String>),
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            SceneError::Parse(err) => write!(f, "{}", err),
+            SceneError::Execution(errors) => write!(f, "{}", errors.join("\n")),
+        }
+    }
+}
+
+impl From<pest::error::Error<Rule>> for SceneError {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        SceneError::Parse(err)
+    }
+}
+
+/// The scene the debugger shows: globes.scene, or a small built-in one.
+pub fn read_scene() -> String {
+    File::open("globes.scene")
         .and_then(|mut file| {
             let mut scene = String::new();
             file.read_to_string(&mut scene)?;
             Ok(scene)
         })
-        .unwrap_or(SCENE.to_string());
+        .unwrap_or(SCENE.to_string())
+}
+
+pub fn load_scene(ray_tracer: &mut RayTracer, time: f64) -> Result<(), SceneError> {
+    load_scene_source(ray_tracer, &read_scene(), time)
+}
 
+pub fn load_scene_source(ray_tracer: &mut RayTracer, scene: &str, time: f64) -> Result<(), SceneError> {
     let mut context = SceneContext::new(ray_tracer);
     context.globals().insert("time".to_string(), Value::Number(time));
-    let mut pairs: Pairs<Rule> = SceneParser::parse(Rule::scene, &scene)?;
+
+    let ast = parse_scene(scene)?;
+    ast.execute(&mut context);
+
+    if context.errors().is_empty() {
+        Ok(())
+    } else {
+        Err(SceneError::Execution(context.errors().to_vec()))
+    }
+}
+
+/// The frames of the scene's animation, found by running it once at the
+/// start, without rendering anything.
+pub fn scene_animation(scene: &str) -> Result<Animation, SceneError> {
+    let mut ray_tracer = RayTracer::new_default(1, 1);
+    let animation = *ray_tracer.animation();
+
+    match load_scene_source(&mut ray_tracer, scene, animation.time(0)) {
+        Ok(()) | Err(SceneError::Execution(_)) => Ok(*ray_tracer.animation()),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn parse_scene(scene: &str) -> Result<AstStatement, pest::error::Error<Rule>> {
+    let mut pairs: Pairs<Rule> = SceneParser::parse(Rule::scene, scene)?;
 
     let statement_list = pairs.next().unwrap();
     assert_eq!(statement_list.as_rule(), Rule::statement_list);
@@ -40,8 +95,109 @@ pub fn load_scene(ray_tracer: &mut RayTracer, time: f64) -> Result<(), pest::err
     let eoi = pairs.next().unwrap();
     assert_eq!(eoi.as_rule(), Rule::EOI);
 
-    let ast = AstStatement::from_pest(statement_list);
-    ast.execute(&mut context);
+    // Give it the whole scene, so that it also keeps the comments around it.
+    Ok(AstStatement::from_statement_list(statement_list, scene, 0))
+}
+
+/// Parses a string holding just an expression.
+pub fn parse_formula(formula: &str) -> Result<AstExpression, pest::error::Error<Rule>> {
+    let mut pairs: Pairs<Rule> = SceneParser::parse(Rule::formula, formula)?;
+
+    Ok(AstExpression::from_pest(pairs.next().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Object and transformation names are only keywords when called
+    #[test]
+    fn object_and_transformation_names_as_variables() {
+        let scene = "box = cube(5)\nscale = 2\ngroup = [box]\nshear = scale * 2\nrepeat = shear\n\
+                     scale(scale, repeat, 1) draw(group[0])\ndraw(box (size: <1, 2, 3>))";
+        let mut ray_tracer = RayTracer::new_default(1, 1);
+
+        assert!(load_scene_source(&mut ray_tracer, scene, 0.0).is_ok());
+        assert!(parse_scene("box(1) = 2").is_err());
+        assert!(parse_scene("function twist(a)\nend").is_err());
+    }
+
+    #[test]
+    fn loop_keywords_are_reserved() {
+        assert!(parse_scene("for i in 1..3 do\n  inner = i\nend").is_ok());
+        assert!(parse_scene("for = 1").is_err());
+        assert!(parse_scene("in = 1").is_err());
+        assert!(parse_scene("function f(in)\nend").is_err());
+    }
+
+    /// Chains of the same precedence group from the left, so `10 - 4 - 3`
+    /// is `(10 - 4) - 3`; before the formatter's parser change, everything
+    /// after the second operand was dropped.
+    #[test]
+    fn operators_group_from_the_left() {
+        let mut ray_tracer = RayTracer::new_default(1, 1);
+        let mut context = SceneContext::new(&mut ray_tracer);
+
+        for &(formula, expected) in &[("10 - 4 - 3", 3.0), ("24 / 4 / 2", 3.0), ("2 - 3 + 4", 3.0), ("1 + 2 * 3 - 4", 3.0)] {
+            match parse_formula(formula).unwrap().evaluate(&mut context) {
+                Value::Number(number) => assert_eq!(number, expected, "{}", formula),
+                value => panic!("{} evaluated to {:?}", formula, value),
+            }
+        }
+    }
+
+    #[test]
+    fn zero_radii_are_reported() {
+        for scene in &["draw(ellipsoid(radii: <10, 0, 10>))", "draw(paraboloid(radii: <1, 0, 1>))"] {
+            let mut ray_tracer = RayTracer::new_default(1, 1);
+            assert!(load_scene_source(&mut ray_tracer, scene, 0.0).is_err(), "{}", scene);
+        }
+    }
+
+    fn evaluated(formula: &str) -> String {
+        let mut ray_tracer = RayTracer::new_default(1, 1);
+        let mut context = SceneContext::new(&mut ray_tracer);
+
+        format!("{:?}", parse_formula(formula).unwrap().evaluate(&mut context))
+    }
+
+    /// Keyword arguments are taken first, and positional ones fill in the
+    /// rest by type, in order
+    #[test]
+    fn keyword_and_positional_arguments() {
+        let keywords = evaluated("sphere(radius: 5, color: red, reflectivity: 0.3)");
+
+        assert_eq!(evaluated("sphere(5, red, 0.3)"), keywords);
+        assert_eq!(evaluated("sphere(0.3, red, radius: 5)"), keywords);
+        assert_ne!(evaluated("sphere(0.3, red, 5)"), keywords);
+    }
+
+    #[test]
+    fn unused_arguments_are_reported() {
+        let errors = |scene: &str| {
+            let mut ray_tracer = RayTracer::new_default(1, 1);
+            match load_scene_source(&mut ray_tracer, scene, 0.0) {
+                Err(SceneError::Execution(errors)) => errors,
+                _ => panic!("{} ran without errors", scene),
+            }
+        };
+
+        assert_eq!(
+            errors("draw(sphere(1, texture: checker(white, black, 2), color: red))"),
+            ["Argument 'color' for sphere() conflicts with another one and is unused"]
+        );
+        assert_eq!(errors("draw(sphere(1, 'big'))"), ["sphere() got 1 unused positional arguments"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Argument 'radius' given more than once to sphere()")]
+    fn duplicate_arguments() {
+        evaluated("sphere(radius: 1, radius: 2)");
+    }
 
-    Ok(())
+    #[test]
+    #[should_panic(expected = "Unknown argument 'size' for sphere(), expected one of: center, radius")]
+    fn unknown_arguments() {
+        evaluated("sphere(size: 1)");
+    }
 }
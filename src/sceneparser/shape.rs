@@ -1,12 +1,16 @@
 use crate::raytracer::rt_object::RTObject;
-use crate::raytracer::material::{SolidColorMaterial, TexturedMaterial};
+use crate::raytracer::material::{SolidColorMaterial, TexturedMaterial, MappedMaterial, MaterialMaps};
 use crate::raytracer::color::Color;
-use crate::raytracer::vector::Vector;
-use crate::raytracer::math_shapes::{MathSphere, MathCube, MathPlane};
+use crate::raytracer::vector::{Vector, UV};
+use crate::raytracer::math_shapes::{
+    MathSphere, MathCube, MathPlane, MathCone, MathTorus, MathDisc, MathBox, MathQuadric, MathImplicit,
+    CubeMapping,
+};
 use crate::raytracer::transformation::MatrixTransformation;
 use crate::raytracer::csg::{CSG, Operator};
+use crate::raytracer::sdf::{Sdf, MathSdf};
 use super::texture::Texture;
-use crate::raytracer::texture::PixmapTexture;
+use super::formula::Formula;
 
 #[derive(Debug, Clone)]
 pub struct Shape {
@@ -15,14 +19,52 @@ pub struct Shape {
     pub transparency: f64,
     pub kind: ShapeKind,
     pub transformation: MatrixTransformation,
+    pub texture_space: TextureSpace,
+    pub maps: TextureMaps,
+}
+
+/// Optional textures for the other properties of the material
+#[derive(Debug, Clone)]
+pub struct TextureMaps {
+    pub reflectivity: Option<Texture>,
+    pub transparency: Option<Texture>,
+    pub bump: Option<Texture>,
+    pub bump_strength: f64,
+    pub normal: Option<Texture>,
+}
+
+/// Where the texture of a shape is looked up
+#[derive(Debug, Clone, Copy)]
+pub enum TextureSpace {
+    UV,
+    /// At the point in the shape's own space, repeating every `size` units
+    Object { size: f64 },
 }
 
 #[derive(Debug, Clone)]
 pub enum ShapeKind {
     Sphere { center: Vector, radius: f64 },
-    Cube { center: Vector, length: f64 },
-    Plane { normal: Vector, distance: f64 },
-    CSG { operator: CSGOperator, a: Box<Shape>, b: Box<Shape> },
+    Cube { center: Vector, length: f64, uv_mapping: CubeMapping },
+    Plane { normal: Vector, distance: f64, tile_size: f64, tile_offset: UV },
+    Cylinder { center: Vector, radius: f64, height: f64 },
+    Cone { center: Vector, radius: f64, height: f64, top_radius: f64 },
+    Torus { center: Vector, radius: f64, tube_radius: f64 },
+    Disc { center: Vector, normal: Vector, radius: f64 },
+    /// Drawn as a signed distance field when `rounding` isn't 0
+    Box { center: Vector, size: Vector, uv_mapping: CubeMapping, rounding: f64 },
+    /// See MathQuadric for the order of the coefficients
+    Quadric { center: Vector, coefficients: [f64; 10] },
+    Implicit { center: Vector, radius: f64, formula: Formula },
+    /// With `operand_materials`, each surface keeps the material of the shape
+    /// it comes from
+    CSG { operator: CSGOperator, shapes: Vec<Shape>, operand_materials: bool },
+    /// A union placed by its own transformation, on top of its shapes' own
+    Group { shapes: Vec<Shape>, operand_materials: bool },
+    // Signed distance fields; see to_sdf() for the shapes they can take
+    Capsule { start: Vector, end: Vector, radius: f64 },
+    Smooth { operator: CSGOperator, a: Box<Shape>, b: Box<Shape>, blend: f64 },
+    Twist { shape: Box<Shape>, amount: f64 },
+    Repeat { shape: Box<Shape>, spacing: Vector, count: Vector },
 }
 
 #[derive(Debug, Clone)]
@@ -30,6 +72,7 @@ pub enum CSGOperator {
     Intersection,
     Union,
     Difference,
+    Xor,
 }
 
 #[derive(Debug, Clone)]
@@ -47,15 +90,36 @@ impl Shape {
                 ))
             }
             Material::Texture(texture) => {
-                let texture = PixmapTexture::from_pixmap(
-                    texture.pixmap().clone()
+                let mut material = TexturedMaterial::new(
+                    texture.texture().clone_box(), self.reflectivity, self.transparency
                 );
-                Box::new(TexturedMaterial::new(
-                    Box::new(texture), self.reflectivity, self.transparency
-                ))
+                if let TextureSpace::Object { size } = self.texture_space {
+                    material.set_solid(size);
+                }
+                Box::new(material)
             }
         };
 
+        let maps = &self.maps;
+        let material: Box<dyn crate::raytracer::material::Material> =
+            if maps.reflectivity.is_some() || maps.transparency.is_some()
+                || maps.bump.is_some() || maps.normal.is_some()
+            {
+                let to_texture = |map: &Option<Texture>| {
+                    map.as_ref().map(|texture| texture.texture().clone_box())
+                };
+
+                Box::new(MappedMaterial::new(material, MaterialMaps {
+                    reflectivity: to_texture(&maps.reflectivity),
+                    transparency: to_texture(&maps.transparency),
+                    bump: to_texture(&maps.bump),
+                    bump_strength: maps.bump_strength,
+                    normal: to_texture(&maps.normal),
+                }))
+            } else {
+                material
+            };
+
         RTObject::new(
             match self.kind {
                 ShapeKind::Sphere { center, radius } => {
@@ -63,32 +127,166 @@ impl Shape {
                         self.transformation.clone(), center, radius
                     ))
                 }
-                ShapeKind::Cube { center, length } => {
-                    Box::new(MathCube::new(
+                ShapeKind::Cube { center, length, uv_mapping } => {
+                    let mut cube = MathCube::new(
                         self.transformation.clone(), center, length
-                    ))
+                    );
+                    cube.set_uv_mapping(uv_mapping);
+                    Box::new(cube)
                 },
-                ShapeKind::Plane { normal, distance } => {
-                    Box::new(MathPlane::from_normal(
+                ShapeKind::Plane { normal, distance, tile_size, tile_offset } => {
+                    let mut plane = MathPlane::from_normal(
                         self.transformation.clone(), normal, distance
+                    );
+                    plane.set_tiling(tile_size, tile_offset);
+                    Box::new(plane)
+                },
+                ShapeKind::Cylinder { center, radius, height } => {
+                    Box::new(MathCone::new_cylinder(
+                        self.transformation.clone(), center, radius, height
                     ))
                 },
-                ShapeKind::CSG { ref operator, ref a, ref b } => {
-                    let a = a.to_rt_object();
-                    let b = b.to_rt_object();
-
-                    let operator = match operator {
-                        CSGOperator::Intersection => Operator::Intersection,
-                        CSGOperator::Union => Operator::Union,
-                        CSGOperator::Difference => Operator::Difference,
-                    };
-
-                    Box::new(CSG::new(
-                        self.transformation.clone(), a, b, operator
+                ShapeKind::Cone { center, radius, height, top_radius } => {
+                    Box::new(MathCone::new(
+                        self.transformation.clone(), center, radius, top_radius, height
                     ))
+                },
+                ShapeKind::Torus { center, radius, tube_radius } => {
+                    Box::new(MathTorus::new(
+                        self.transformation.clone(), center, radius, tube_radius
+                    ))
+                },
+                ShapeKind::Disc { center, normal, radius } => {
+                    Box::new(MathDisc::new(
+                        self.transformation.clone(), center, normal, radius
+                    ))
+                },
+                ShapeKind::Box { rounding, .. } if rounding != 0.0 => {
+                    Box::new(MathSdf::new(MatrixTransformation::create_identity_matrix(), self.to_sdf()))
+                },
+                ShapeKind::Box { center, size, uv_mapping, .. } => {
+                    let mut shape = MathBox::new(
+                        self.transformation.clone(), center, size
+                    );
+                    shape.set_uv_mapping(uv_mapping);
+                    Box::new(shape)
+                },
+                ShapeKind::Quadric { center, coefficients } => {
+                    Box::new(MathQuadric::new(
+                        self.transformation.clone(), center, coefficients
+                    ))
+                },
+                ShapeKind::Implicit { center, radius, ref formula } => {
+                    Box::new(MathImplicit::new(
+                        self.transformation.clone(), center, radius, formula.function().clone()
+                    ))
+                },
+                ShapeKind::CSG { ref operator, ref shapes, operand_materials } => {
+                    let objects = shapes.iter().map(|shape| shape.to_rt_object()).collect();
+
+                    let mut csg = CSG::new(
+                        self.transformation.clone(), objects, operator.operator()
+                    );
+                    csg.set_operand_materials(operand_materials);
+                    Box::new(csg)
+                }
+                ShapeKind::Group { ref shapes, operand_materials } => {
+                    let objects = shapes
+                        .iter()
+                        .map(|shape| shape.transformed(&self.transformation).to_rt_object())
+                        .collect();
+
+                    let mut csg = CSG::new(
+                        MatrixTransformation::create_identity_matrix(), objects, Operator::Union
+                    );
+                    csg.set_operand_materials(operand_materials);
+                    Box::new(csg)
+                }
+                ShapeKind::Capsule { .. } | ShapeKind::Smooth { .. } |
+                ShapeKind::Twist { .. } | ShapeKind::Repeat { .. } => {
+                    // The parts carry their own transformations, like in a CSG
+                    Box::new(MathSdf::new(MatrixTransformation::create_identity_matrix(), self.to_sdf()))
                 }
             },
             Some(material),
         )
     }
+
+    /// The shape moved by `transformation`, after its own transformation.
+    pub fn transformed(&self, transformation: &MatrixTransformation) -> Shape {
+        let transform = |shape: &Shape| Box::new(shape.transformed(transformation));
+        let kind = match self.kind {
+            ShapeKind::CSG { ref operator, ref shapes, operand_materials } => ShapeKind::CSG {
+                operator: operator.clone(),
+                shapes: shapes.iter().map(|shape| shape.transformed(transformation)).collect(),
+                operand_materials,
+            },
+            // Its shapes follow its own transformation
+            ShapeKind::Smooth { ref operator, ref a, ref b, blend } => ShapeKind::Smooth {
+                operator: operator.clone(),
+                a: transform(a),
+                b: transform(b),
+                blend,
+            },
+            ShapeKind::Twist { ref shape, amount } => ShapeKind::Twist { shape: transform(shape), amount },
+            ShapeKind::Repeat { ref shape, spacing, count } => {
+                ShapeKind::Repeat { shape: transform(shape), spacing, count }
+            }
+            ref kind => kind.clone(),
+        };
+
+        Shape {
+            kind,
+            transformation: self.transformation.compose_with(transformation),
+            ..self.clone()
+        }
+    }
+
+    /// The shape as a signed distance field, for blending and bending.
+    pub fn to_sdf(&self) -> Sdf {
+        let sdf = match self.kind {
+            ShapeKind::Sphere { center, radius } => Sdf::Sphere { center, radius },
+            ShapeKind::Cube { center, length, .. } => {
+                Sdf::Box { center, size: Vector::new(length, length, length), rounding: 0.0 }
+            }
+            ShapeKind::Box { center, size, rounding, .. } => Sdf::Box { center, size, rounding },
+            ShapeKind::Torus { center, radius, tube_radius } => Sdf::Torus { center, radius, tube_radius },
+            ShapeKind::Cylinder { center, radius, height } => Sdf::Cylinder { center, radius, height },
+            ShapeKind::Capsule { start, end, radius } => Sdf::Capsule { start, end, radius },
+            ShapeKind::Smooth { ref operator, ref a, ref b, blend } => {
+                return Sdf::Smooth {
+                    operator: operator.operator(),
+                    a: Box::new(a.to_sdf()),
+                    b: Box::new(b.to_sdf()),
+                    blend,
+                };
+            }
+            ShapeKind::Twist { ref shape, amount } => {
+                return Sdf::twist(self.transformation.clone(), amount, shape.to_sdf());
+            }
+            ShapeKind::Repeat { ref shape, spacing, count } => {
+                return Sdf::Repeat {
+                    frame: self.transformation.clone(),
+                    spacing,
+                    count,
+                    sdf: Box::new(shape.to_sdf()),
+                };
+            }
+            // FIXME: No panic
+            ref kind => panic!("Can't blend or bend this shape: {:?}", kind),
+        };
+
+        Sdf::transformed(self.transformation.clone(), sdf)
+    }
+}
+
+impl CSGOperator {
+    pub fn operator(&self) -> Operator {
+        match self {
+            CSGOperator::Intersection => Operator::Intersection,
+            CSGOperator::Union => Operator::Union,
+            CSGOperator::Difference => Operator::Difference,
+            CSGOperator::Xor => Operator::Xor,
+        }
+    }
 }
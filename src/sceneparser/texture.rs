@@ -1,45 +1,139 @@
-use crate::raytracer::color::{RaytracerPixmap, ColorPixmap, Color};
+use crate::raytracer::color::{Color, ColorPixmap, RaytracerPixmap};
+use crate::raytracer::procedural_texture::{ProceduralTexture, Pattern};
+use crate::raytracer::texture::{PixmapTexture, Texture as RaytracerTexture};
+use crate::raytracer::vector::UV;
+use super::image_format::find_image_format;
 
-use lodepng;
-use std::fmt::{Debug, Formatter, Error};
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter, Error};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+// Decoded textures, shared by every frame and thread, so that each file is
+// only decoded again when it changes on disk.
+static TEXTURE_CACHE: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, PixmapTexture)>>> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io { file: String, error: io::Error },
+    UnknownFormat { file: String },
+    Decode { file: String, format: &'static str, message: String },
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            TextureError::Io { file, error } => {
+                write!(f, "can't read texture '{}': {}", file, error)
+            }
+            TextureError::UnknownFormat { file } => {
+                write!(f, "unknown image format for texture '{}'", file)
+            }
+            TextureError::Decode { file, format, message } => {
+                write!(f, "can't decode {} texture '{}': {}", format, file, message)
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Texture {
-    pixmap: Rc<RaytracerPixmap>,
-    filename: String,
+    texture: Box<dyn RaytracerTexture>,
+    // The file name, or the function that made the texture
+    name: String,
 }
 
 impl Debug for Texture {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "Texture {{ filename: {:?} }}", self.filename)
+        write!(f, "Texture {{ name: {:?} }}", self.name)
     }
 }
 
 impl Texture {
-    pub fn from_file(filename: &str) -> Self {
-        // FIXME: No unwrap
-        let png = lodepng::decode32_file(filename).unwrap();
-
-        let mut pixmap = RaytracerPixmap::new(png.width, png.height);
-
-        for x in 0..png.width {
-            for y in 0..png.height {
-                let pixel = png.buffer[y * png.width + x];
-                let color = Color::new(
-                    pixel.r as f64 / 255.0,
-                    pixel.g as f64 / 255.0,
-                    pixel.b as f64 / 255.0,
-                    pixel.a as f64 / 255.0,
-                );
-                pixmap.set_pixel_color(x, y, color);
+    pub fn new(texture: Box<dyn RaytracerTexture>, name: &str) -> Self {
+        Texture { texture, name: name.to_owned() }
+    }
+
+    pub fn from_file(filename: &str) -> Result<Self, TextureError> {
+        let texture = Texture::load_pixmap(filename)?;
+        Ok(Texture::new(Box::new(texture), filename))
+    }
+
+    /// A magenta checker that stands in for a texture that failed to load, so
+    /// that the rest of the scene still renders
+    pub fn missing() -> PixmapTexture {
+        let checker = ProceduralTexture::new(
+            Pattern::Checker { count: 8.0 },
+            Color::new(1.0, 0.0, 1.0, 1.0),
+            Color::new(0.0, 0.0, 0.0, 1.0),
+        );
+
+        let size = 64;
+        let mut pixmap = RaytracerPixmap::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                let uv = UV {
+                    u: (x as f64 + 0.5) / size as f64,
+                    v: (y as f64 + 0.5) / size as f64,
+                };
+                pixmap.set_pixel_color(x, y, checker.get_color_at(uv));
             }
         }
 
-        Texture { pixmap: Rc::new(pixmap), filename: filename.to_owned() }
+        PixmapTexture::from_pixmap(pixmap)
+    }
+
+    /// Loads the file through the texture cache
+    pub fn load_pixmap(filename: &str) -> Result<PixmapTexture, TextureError> {
+        let key = fs::canonicalize(filename).and_then(|path| {
+            let modified = fs::metadata(&path)?.modified()?;
+            Ok((path, modified))
+        });
+
+        let (path, modified) = match key {
+            Ok(key) => key,
+            // Let decoding report the problem
+            Err(_) => return Texture::decode_file(filename),
+        };
+
+        let cache = TEXTURE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+
+        if let Some((cached_modified, texture)) = cache.lock().unwrap().get(&path) {
+            if *cached_modified == modified {
+                return Ok(texture.clone());
+            }
+        }
+
+        // Decoded without holding the lock, so other files can load meanwhile
+        let texture = Texture::decode_file(filename)?;
+        cache.lock().unwrap().insert(path, (modified, texture.clone()));
+
+        Ok(texture)
+    }
+
+    fn decode_file(filename: &str) -> Result<PixmapTexture, TextureError> {
+        let bytes = fs::read(filename).map_err(|error| {
+            TextureError::Io { file: filename.to_owned(), error }
+        })?;
+
+        let format = find_image_format(Path::new(filename), &bytes).ok_or_else(|| {
+            TextureError::UnknownFormat { file: filename.to_owned() }
+        })?;
+
+        let pixmap = (format.decode)(&bytes).map_err(|message| {
+            TextureError::Decode { file: filename.to_owned(), format: format.name, message }
+        })?;
+
+        let mut texture = PixmapTexture::from_pixmap(pixmap);
+        texture.set_high_dynamic_range(format.high_dynamic_range);
+
+        Ok(texture)
     }
 
-    pub fn pixmap(&self) -> &RaytracerPixmap {
-        &*self.pixmap
+    pub fn texture(&self) -> &dyn RaytracerTexture {
+        &*self.texture
     }
 }
\ No newline at end of file
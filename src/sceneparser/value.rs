@@ -1,6 +1,7 @@
 use super::shape::Shape;
 use super::texture::Texture;
 use crate::raytracer::vector::Vector;
+use crate::raytracer::color::Color;
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -11,6 +12,7 @@ pub enum Value {
     Vector { x: f64, y: f64, z: f64 },
     Object(Shape),
     Texture(Texture),
+    List(Vec<Value>),
 }
 
 impl Value {
@@ -30,6 +32,22 @@ impl Value {
         }
     }
 
+    pub fn to_color(&self) -> Color {
+        match self {
+            Value::Color { r, g, b, a } => Color::new(*r, *g, *b, *a),
+            // FIXME: no panic
+            value => panic!("Cannot convert value to color: {:?}", value),
+        }
+    }
+
+    pub fn to_texture(&self) -> Texture {
+        match self {
+            Value::Texture(texture) => texture.clone(),
+            // FIXME: no panic
+            value => panic!("Cannot convert value to texture: {:?}", value),
+        }
+    }
+
     pub fn to_vector(&self) -> Vector {
         match self {
             Value::Vector { x, y, z } => Vector::new(*x, *y, *z),
@@ -38,6 +56,14 @@ impl Value {
         }
     }
 
+    pub fn to_list(&self) -> &[Value] {
+        match self {
+            Value::List(list) => list,
+            // FIXME: no panic
+            value => panic!("Cannot convert value to list: {:?}", value),
+        }
+    }
+
     pub fn to_string(&self) -> String {
         match self {
             Value::String(string) => string.to_owned(),